@@ -0,0 +1,95 @@
+//! Answers ICMP echo requests in place, the way an XDP program would before
+//! returning `XDP_TX`: reflect the frame, turn the request into a reply and
+//! fix the checksums incrementally.
+
+use netp::checksum;
+use netp::link::{EtherType, Ethernet};
+use netp::network::{IPv4, InetProtocol};
+use netp::reflect::reflect;
+use netp::transport::icmp::{Icmp, IcmpType};
+
+#[derive(Debug, PartialEq, Eq)]
+enum Action {
+    Pass,
+    Tx,
+}
+
+const TTL: u8 = 64;
+
+fn respond(frame: &mut [u8]) -> Action {
+    let is_echo_request = || -> Option<bool> {
+        let (eth, rem) = Ethernet::new(frame).ok()?;
+        let (ip, rem) = IPv4::new(rem).ok()?;
+        let (icmp, _) = Icmp::new(rem).ok()?;
+        Some(
+            eth.ethertype() == EtherType::IPv4
+                && ip.protocol() == InetProtocol::ICMP
+                && ip.fragment_offset() == [0, 0]
+                && !ip.more_fragments()
+                && icmp.icmp_type() == IcmpType::EchoRequest,
+        )
+    };
+    if is_echo_request() != Some(true) {
+        return Action::Pass;
+    }
+
+    let Ok(reflected) = reflect(frame) else {
+        return Action::Pass;
+    };
+    let (l3, l4) = frame.split_at_mut(reflected.l4_offset);
+
+    let Ok((mut ip, _)) = IPv4::new_mut(&mut l3[reflected.l3_offset..]) else {
+        return Action::Pass;
+    };
+    let old = u16::from_be_bytes([ip.ttl(), ip.protocol_u8()]);
+    ip.set_ttl(TTL);
+    let new = u16::from_be_bytes([TTL, ip.protocol_u8()]);
+    ip.set_csum(checksum::update_u16(ip.csum(), old, new));
+
+    let Ok((mut icmp, _)) = Icmp::new_mut(l4) else {
+        return Action::Pass;
+    };
+    let old = u16::from_be_bytes([icmp.icmp_type_u8(), icmp.code()]);
+    icmp.set_icmp_type(IcmpType::EchoReply);
+    let new = u16::from_be_bytes([icmp.icmp_type_u8(), icmp.code()]);
+    icmp.set_csum(checksum::update_u16(icmp.csum(), old, new));
+
+    Action::Tx
+}
+
+fn main() {
+    let mut frame = [
+        0x02, 0x00, 0x00, 0x00, 0x00, 0x02, 0x02, 0x00, 0x00, 0x00, 0x00, 0x01, 0x08, 0x00, 0x45,
+        0x00, 0x00, 0x20, 0x12, 0x34, 0x40, 0x00, 0x3f, 0x01, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x01,
+        0x0a, 0x00, 0x00, 0x02, 0x08, 0x00, 0x00, 0x00, 0x00, 0x2a, 0x00, 0x01, 0x70, 0x69, 0x6e,
+        0x67,
+    ];
+    {
+        let (mut ip, rem) = IPv4::new_mut(&mut frame[14..]).unwrap();
+        ip.update_csum();
+        let (mut icmp, payload) = Icmp::new_mut(rem).unwrap();
+        icmp.update_csum(payload);
+    }
+
+    assert_eq!(respond(&mut frame), Action::Tx);
+
+    let (eth, rem) = Ethernet::new(&frame).unwrap();
+    let (ip, rem) = IPv4::new(rem).unwrap();
+    let (icmp, payload) = Icmp::new(rem).unwrap();
+    println!(
+        "{:02x?} -> {:02x?}, {:?} -> {:?} ttl {}, {:?} id {} seq {}",
+        eth.source(),
+        eth.destination(),
+        ip.source(),
+        ip.destination(),
+        ip.ttl(),
+        icmp.icmp_type(),
+        icmp.identifier(),
+        icmp.sequence_num(),
+    );
+    assert_eq!(ip.csum(), ip.calc_csum());
+    assert_eq!(icmp.csum(), icmp.calc_csum(payload));
+
+    // Replies are left alone.
+    assert_eq!(respond(&mut frame), Action::Pass);
+}
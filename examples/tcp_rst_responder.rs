@@ -0,0 +1,126 @@
+//! Resets every TCP connection attempt the way a closed port does
+//! \[[RFC793](https://datatracker.ietf.org/doc/html/rfc793#section-3.4)\]: the
+//! frame is reflected, options and payload are trimmed off the tail as
+//! `bpf_xdp_adjust_tail` would and the segment becomes a bare RST.
+
+use netp::buf::PacketBuf;
+use netp::checksum;
+use netp::link::{EtherType, Ethernet};
+use netp::network::{IPv4, InetProtocol};
+use netp::reflect::reflect;
+use netp::transport::tcp::Tcp;
+
+#[derive(Debug, PartialEq, Eq)]
+enum Action {
+    Pass,
+    Tx,
+}
+
+const RST: u8 = 0x04;
+const ACK: u8 = 0x10;
+
+/// Sequence and acknowledgment numbers plus flags of the reset answering a
+/// segment, `None` for resets which must never be answered.
+fn reset_for(tcp: &Tcp<&[u8]>, payload_len: usize) -> Option<(u32, u32, u8)> {
+    if tcp.rst() {
+        return None;
+    }
+    if tcp.ack() {
+        return Some((tcp.ack_num(), 0, RST));
+    }
+    let len = payload_len as u32 + tcp.syn() as u32 + tcp.fin() as u32;
+    Some((0, tcp.sequence_num().wrapping_add(len), RST | ACK))
+}
+
+fn respond(frame: &mut [u8], len: usize) -> Action {
+    let answer = || {
+        let (eth, rem) = Ethernet::new(&frame[..len]).ok()?;
+        let (ip, rem) = IPv4::new(rem).ok()?;
+        if eth.ethertype() != EtherType::IPv4 || ip.protocol() != InetProtocol::TCP {
+            return None;
+        }
+        let payload_len = (ip.total_length() as usize).checked_sub(ip.size() as usize)?;
+        let (tcp, _) = Tcp::new(rem).ok()?;
+        let payload_len = payload_len.checked_sub(tcp.size() as usize)?;
+        reset_for(&tcp, payload_len)
+    };
+    let Some((seq, ack, flags)) = answer() else {
+        return Action::Pass;
+    };
+
+    let Ok(reflected) = reflect(&mut frame[..len]) else {
+        return Action::Pass;
+    };
+    let Ok(mut buf) = PacketBuf::new(frame, 0, len) else {
+        return Action::Pass;
+    };
+    let new_len = reflected.l4_offset + Tcp::MIN_LEN;
+    if buf.adjust_tail(new_len as isize - len as isize).is_err() {
+        return Action::Pass;
+    }
+
+    let Ok((mut ip, l4)) = IPv4::from_buf(&mut buf, reflected.l3_offset) else {
+        return Action::Pass;
+    };
+    ip.set_total_length_u16((l4 - reflected.l3_offset + Tcp::MIN_LEN) as u16);
+    ip.update_csum();
+    let (source, destination) = (*ip.source(), *ip.destination());
+
+    let data = buf.data_mut();
+    let Some(segment) = data.get_mut(reflected.l4_offset..) else {
+        return Action::Pass;
+    };
+    // Data offset of 5 words, options are gone.
+    segment[12] = 5 << 4;
+    let Ok((mut tcp, _)) = Tcp::new_mut(segment) else {
+        return Action::Pass;
+    };
+    tcp.set_sequence_num(seq);
+    tcp.set_ack_num(ack);
+    tcp.set_flags(flags);
+    tcp.set_window_size(0);
+    tcp.set_csum(0);
+    tcp.slice_mut()[18..20].fill(0);
+    let sum = checksum::pseudo_ipv4(&source, &destination, 6, Tcp::MIN_LEN as u16);
+    let csum = checksum::finish(checksum::sum(sum, tcp.slice()));
+    tcp.set_csum(csum);
+
+    Action::Tx
+}
+
+fn main() {
+    // SYN with an MSS option to 10.0.0.2:22.
+    let mut frame = [
+        0x02, 0x00, 0x00, 0x00, 0x00, 0x02, 0x02, 0x00, 0x00, 0x00, 0x00, 0x01, 0x08, 0x00, 0x45,
+        0x00, 0x00, 0x2c, 0x00, 0x01, 0x40, 0x00, 0x40, 0x06, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x01,
+        0x0a, 0x00, 0x00, 0x02, 0xc3, 0x50, 0x00, 0x16, 0x00, 0x00, 0x03, 0xe8, 0x00, 0x00, 0x00,
+        0x00, 0x60, 0x02, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00, 0x02, 0x04, 0x05, 0xb4,
+    ];
+    let len = frame.len();
+    IPv4::new_mut(&mut frame[14..]).unwrap().0.update_csum();
+
+    assert_eq!(respond(&mut frame, len), Action::Tx);
+
+    let len = 14 + 20 + Tcp::MIN_LEN;
+    let (_, rem) = Ethernet::new(&frame[..len]).unwrap();
+    let (ip, rem) = IPv4::new(rem).unwrap();
+    let (tcp, _) = Tcp::new(rem).unwrap();
+    println!(
+        "{:?}:{} -> {:?}:{} seq {} ack {} rst {} ack {}",
+        ip.source(),
+        tcp.source(),
+        ip.destination(),
+        tcp.destination(),
+        tcp.sequence_num(),
+        tcp.ack_num(),
+        tcp.rst(),
+        tcp.ack(),
+    );
+    assert_eq!(ip.csum(), ip.calc_csum());
+    let sum = checksum::pseudo_ipv4(ip.source(), ip.destination(), 6, 20);
+    assert_eq!(checksum::finish(checksum::sum(sum, tcp.slice())), 0);
+    assert_eq!(tcp.ack_num(), 1001);
+
+    // Resets are never answered.
+    assert_eq!(respond(&mut frame, len), Action::Pass);
+}
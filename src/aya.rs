@@ -1,7 +1,30 @@
-use aya_ebpf_bindings::bindings::xdp_action;
+use aya_ebpf::cty::c_long;
+use aya_ebpf::maps::Array;
+use aya_ebpf::programs::{TcContext, XdpContext};
+use aya_ebpf_bindings::bindings::{
+    xdp_action, BPF_F_MARK_MANGLED_0, BPF_F_PSEUDO_HDR, TC_ACT_OK, TC_ACT_REDIRECT, TC_ACT_SHOT,
+};
 
+use crate::link::{EtherType, Ethernet};
+use crate::network::{IPv4, IPv4Size};
+use crate::transport::tcp::{Tcp, TcpSize};
+use crate::transport::udp::Udp;
+
+#[derive(Debug)]
 pub struct BoundsError;
 
+#[derive(Debug)]
+pub enum Error {
+    OutOfBounds,
+    Malformed,
+}
+
+impl From<BoundsError> for Error {
+    fn from(_: BoundsError) -> Self {
+        Error::OutOfBounds
+    }
+}
+
 #[macro_export]
 macro_rules! bounds {
     ($ctx:expr, $size:expr) => {
@@ -70,3 +93,389 @@ impl<T, E> XdpErr<T> for Result<T, E> {
         self.map_err(|_| xdp_action::XDP_ABORTED)
     }
 }
+
+pub trait TcErr<T> {
+    fn or_ok(self) -> Result<T, i32>
+    where
+        T: core::marker::Sized;
+    fn or_shot(self) -> Result<T, i32>
+    where
+        T: core::marker::Sized;
+    fn or_redirect(self) -> Result<T, i32>
+    where
+        T: core::marker::Sized;
+}
+
+// The `TC_ACT_*` bindings are `u32` or `i32` depending on the target.
+#[allow(clippy::unnecessary_cast)]
+impl<T, E> TcErr<T> for Result<T, E> {
+    fn or_ok(self) -> Result<T, i32>
+    where
+        T: core::marker::Sized,
+    {
+        self.map_err(|_| TC_ACT_OK as i32)
+    }
+
+    fn or_shot(self) -> Result<T, i32>
+    where
+        T: core::marker::Sized,
+    {
+        self.map_err(|_| TC_ACT_SHOT as i32)
+    }
+
+    fn or_redirect(self) -> Result<T, i32>
+    where
+        T: core::marker::Sized,
+    {
+        self.map_err(|_| TC_ACT_REDIRECT as i32)
+    }
+}
+
+pub const IPV4_CSUM_OFFSET: usize = 10;
+pub const TCP_CSUM_OFFSET: usize = 16;
+pub const UDP_CSUM_OFFSET: usize = 6;
+
+/// Fixes the IPv4 header checksum at packet offset `csum_offset` after an
+/// address changed from `old` to `new`.
+#[inline(always)]
+pub fn l3_csum_replace_addr(
+    ctx: &TcContext,
+    csum_offset: usize,
+    old: &[u8; 4],
+    new: &[u8; 4],
+) -> Result<(), c_long> {
+    ctx.l3_csum_replace(
+        csum_offset,
+        u32::from_ne_bytes(*old) as u64,
+        u32::from_ne_bytes(*new) as u64,
+        4,
+    )
+}
+
+/// Fixes the IPv4 header checksum at packet offset `csum_offset` after a
+/// 16-bit field changed from `old` to `new`, both in host order.
+#[inline(always)]
+pub fn l3_csum_replace_u16(
+    ctx: &TcContext,
+    csum_offset: usize,
+    old: u16,
+    new: u16,
+) -> Result<(), c_long> {
+    ctx.l3_csum_replace(csum_offset, old.to_be() as u64, new.to_be() as u64, 2)
+}
+
+/// Fixes the TCP or UDP checksum at packet offset `csum_offset` after an
+/// IPv4 address of the pseudo header changed from `old` to `new`.
+///
+/// With `udp` set a zero checksum is left alone and a computed zero is
+/// written as `0xffff`.
+#[inline(always)]
+pub fn l4_csum_replace_addr(
+    ctx: &TcContext,
+    csum_offset: usize,
+    old: &[u8; 4],
+    new: &[u8; 4],
+    udp: bool,
+) -> Result<(), c_long> {
+    let mangled = if udp { BPF_F_MARK_MANGLED_0 } else { 0 };
+    ctx.l4_csum_replace(
+        csum_offset,
+        u32::from_ne_bytes(*old) as u64,
+        u32::from_ne_bytes(*new) as u64,
+        (BPF_F_PSEUDO_HDR | mangled | 4) as u64,
+    )
+}
+
+/// Fixes the TCP or UDP checksum at packet offset `csum_offset` after a port
+/// changed from `old` to `new`, both in host order. See `l4_csum_replace_addr`.
+#[inline(always)]
+pub fn l4_csum_replace_port(
+    ctx: &TcContext,
+    csum_offset: usize,
+    old: u16,
+    new: u16,
+    udp: bool,
+) -> Result<(), c_long> {
+    let mangled = if udp { BPF_F_MARK_MANGLED_0 } else { 0 };
+    ctx.l4_csum_replace(
+        csum_offset,
+        old.to_be() as u64,
+        new.to_be() as u64,
+        (mangled | 2) as u64,
+    )
+}
+
+/// Program context giving access to the linear packet data, `data()..data_end()`.
+///
+/// # Safety
+///
+/// `data()..data_end()` must be a valid, readable and writable range of
+/// memory for as long as the context is borrowed, since `slice_at` and the
+/// `from_ctx` constructors turn it into slices.
+pub unsafe trait PacketContext {
+    fn data(&self) -> usize;
+    fn data_end(&self) -> usize;
+}
+
+unsafe impl PacketContext for XdpContext {
+    #[inline(always)]
+    fn data(&self) -> usize {
+        XdpContext::data(self)
+    }
+
+    #[inline(always)]
+    fn data_end(&self) -> usize {
+        XdpContext::data_end(self)
+    }
+}
+
+/// Only the linear part of the skb is visible, headers past it need a
+/// `TcContext::pull_data` first.
+unsafe impl PacketContext for TcContext {
+    #[inline(always)]
+    fn data(&self) -> usize {
+        TcContext::data(self)
+    }
+
+    #[inline(always)]
+    fn data_end(&self) -> usize {
+        TcContext::data_end(self)
+    }
+}
+
+/// Backend index for `hash` from a Maglev table of `size` entries loaded
+/// into an `Array` map, see `crate::maglev`.
+#[inline(always)]
+pub fn maglev_lookup(table: &Array<u32>, size: u32, hash: u32) -> Option<u32> {
+    table.get(crate::maglev::slot(hash, size)).copied()
+}
+
+/// Checks that `len` bytes starting at `offset` are inside the packet, in a
+/// way the verifier can follow, and returns a pointer to them.
+#[inline(always)]
+pub fn ptr_at<C: PacketContext>(
+    ctx: &C,
+    offset: usize,
+    len: usize,
+) -> Result<*mut u8, BoundsError> {
+    let start = ctx.data() + offset;
+    if start + len > ctx.data_end() {
+        return Err(BoundsError);
+    }
+    Ok(start as *mut u8)
+}
+
+#[inline(always)]
+pub fn slice_at<C: PacketContext>(
+    ctx: &C,
+    offset: usize,
+    len: usize,
+) -> Result<&[u8], BoundsError> {
+    let ptr = ptr_at(ctx, offset, len)?;
+    Ok(unsafe { core::slice::from_raw_parts(ptr, len) })
+}
+
+/// # Safety
+///
+/// The returned slice aliases the packet, the caller must not hold any other
+/// view overlapping the same bytes while it is alive.
+#[inline(always)]
+#[allow(clippy::mut_from_ref)]
+pub unsafe fn slice_at_mut<C: PacketContext>(
+    ctx: &C,
+    offset: usize,
+    len: usize,
+) -> Result<&mut [u8], BoundsError> {
+    let ptr = ptr_at(ctx, offset, len)?;
+    Ok(core::slice::from_raw_parts_mut(ptr, len))
+}
+
+#[inline(always)]
+fn ethernet_len<C: PacketContext>(ctx: &C, offset: usize) -> Result<usize, Error> {
+    let head = slice_at(ctx, offset, Ethernet::MIN_LEN)?;
+    Ok(match EtherType::from([head[12], head[13]]) {
+        EtherType::VlanDoubleTaggedFrame => Ethernet::MAX_LEN,
+        EtherType::VlanTaggedFrame => Ethernet::MIN_LEN + 2,
+        _ => Ethernet::MIN_LEN,
+    })
+}
+
+#[inline(always)]
+fn ipv4_len<C: PacketContext>(ctx: &C, offset: usize) -> Result<usize, Error> {
+    let head = slice_at(ctx, offset, IPv4::MIN_LEN)?;
+    let size = IPv4Size::try_from_ihl_u8(head[0] & 0xF).map_err(|_| Error::Malformed)?;
+    Ok(size as usize)
+}
+
+#[inline(always)]
+fn tcp_len<C: PacketContext>(ctx: &C, offset: usize) -> Result<usize, Error> {
+    let head = slice_at(ctx, offset, Tcp::MIN_LEN)?;
+    let size = TcpSize::try_from_data_offset_u8(head[12] >> 4).map_err(|_| Error::Malformed)?;
+    Ok(size as usize)
+}
+
+impl<'pkt> Ethernet<&'pkt [u8]> {
+    /// Parses the header at `offset` straight from the packet, returning it
+    /// along with the offset of the next header.
+    #[inline(always)]
+    pub fn from_ctx<C: PacketContext>(ctx: &'pkt C, offset: usize) -> Result<(Self, usize), Error> {
+        let len = ethernet_len(ctx, offset)?;
+        let (eth, _) = Ethernet::new(slice_at(ctx, offset, len)?).map_err(|_| Error::Malformed)?;
+        Ok((eth, offset + len))
+    }
+}
+
+impl<'pkt> Ethernet<&'pkt mut [u8]> {
+    /// Mutable counterpart of `Ethernet::from_ctx`.
+    ///
+    /// # Safety
+    ///
+    /// See `slice_at_mut`.
+    #[inline(always)]
+    pub unsafe fn from_ctx_mut<C: PacketContext>(
+        ctx: &'pkt C,
+        offset: usize,
+    ) -> Result<(Self, usize), Error> {
+        let len = ethernet_len(ctx, offset)?;
+        let (eth, _) =
+            Ethernet::new_mut(slice_at_mut(ctx, offset, len)?).map_err(|_| Error::Malformed)?;
+        Ok((eth, offset + len))
+    }
+}
+
+impl<'pkt> IPv4<&'pkt [u8]> {
+    /// Parses the header at `offset` straight from the packet, returning it
+    /// along with the offset of the next header.
+    #[inline(always)]
+    pub fn from_ctx<C: PacketContext>(ctx: &'pkt C, offset: usize) -> Result<(Self, usize), Error> {
+        let len = ipv4_len(ctx, offset)?;
+        let (ip, _) = IPv4::new(slice_at(ctx, offset, len)?).map_err(|_| Error::Malformed)?;
+        Ok((ip, offset + len))
+    }
+}
+
+impl<'pkt> IPv4<&'pkt mut [u8]> {
+    /// Mutable counterpart of `IPv4::from_ctx`.
+    ///
+    /// # Safety
+    ///
+    /// See `slice_at_mut`.
+    #[inline(always)]
+    pub unsafe fn from_ctx_mut<C: PacketContext>(
+        ctx: &'pkt C,
+        offset: usize,
+    ) -> Result<(Self, usize), Error> {
+        let len = ipv4_len(ctx, offset)?;
+        let (ip, _) =
+            IPv4::new_mut(slice_at_mut(ctx, offset, len)?).map_err(|_| Error::Malformed)?;
+        Ok((ip, offset + len))
+    }
+}
+
+impl<'pkt> Tcp<&'pkt [u8]> {
+    /// Parses the header at `offset` straight from the packet, returning it
+    /// along with the offset of the payload.
+    #[inline(always)]
+    pub fn from_ctx<C: PacketContext>(ctx: &'pkt C, offset: usize) -> Result<(Self, usize), Error> {
+        let len = tcp_len(ctx, offset)?;
+        let (tcp, _) = Tcp::new(slice_at(ctx, offset, len)?).map_err(|_| Error::Malformed)?;
+        Ok((tcp, offset + len))
+    }
+}
+
+impl<'pkt> Tcp<&'pkt mut [u8]> {
+    /// Mutable counterpart of `Tcp::from_ctx`.
+    ///
+    /// # Safety
+    ///
+    /// See `slice_at_mut`.
+    #[inline(always)]
+    pub unsafe fn from_ctx_mut<C: PacketContext>(
+        ctx: &'pkt C,
+        offset: usize,
+    ) -> Result<(Self, usize), Error> {
+        let len = tcp_len(ctx, offset)?;
+        let (tcp, _) =
+            Tcp::new_mut(slice_at_mut(ctx, offset, len)?).map_err(|_| Error::Malformed)?;
+        Ok((tcp, offset + len))
+    }
+}
+
+impl<'pkt> Udp<&'pkt [u8]> {
+    /// Parses the header at `offset` straight from the packet, returning it
+    /// along with the offset of the payload.
+    #[inline(always)]
+    pub fn from_ctx<C: PacketContext>(ctx: &'pkt C, offset: usize) -> Result<(Self, usize), Error> {
+        let (udp, _) = Udp::new(slice_at(ctx, offset, Udp::SIZE)?).map_err(|_| Error::Malformed)?;
+        Ok((udp, offset + Udp::SIZE))
+    }
+}
+
+impl<'pkt> Udp<&'pkt mut [u8]> {
+    /// Mutable counterpart of `Udp::from_ctx`.
+    ///
+    /// # Safety
+    ///
+    /// See `slice_at_mut`.
+    #[inline(always)]
+    pub unsafe fn from_ctx_mut<C: PacketContext>(
+        ctx: &'pkt C,
+        offset: usize,
+    ) -> Result<(Self, usize), Error> {
+        let (udp, _) =
+            Udp::new_mut(slice_at_mut(ctx, offset, Udp::SIZE)?).map_err(|_| Error::Malformed)?;
+        Ok((udp, offset + Udp::SIZE))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::InetProtocol;
+
+    struct Buf<'a>(&'a [u8]);
+
+    unsafe impl PacketContext for Buf<'_> {
+        fn data(&self) -> usize {
+            self.0.as_ptr() as usize
+        }
+
+        fn data_end(&self) -> usize {
+            self.0.as_ptr() as usize + self.0.len()
+        }
+    }
+
+    const PACKET: [u8; 42] = [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x08, 0x00, 0x45,
+        0x00, 0x00, 0x1c, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x01,
+        0x0a, 0x00, 0x00, 0x02, 0x13, 0x88, 0x00, 0x35, 0x00, 0x08, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn parse_from_ctx() {
+        let ctx = Buf(&PACKET);
+
+        let (eth, offset) = Ethernet::from_ctx(&ctx, 0).unwrap();
+        assert_eq!(eth.ethertype(), EtherType::IPv4);
+        let (ip, offset) = IPv4::from_ctx(&ctx, offset).unwrap();
+        assert_eq!(ip.protocol(), InetProtocol::UDP);
+        let (udp, offset) = Udp::from_ctx(&ctx, offset).unwrap();
+        assert_eq!(udp.destination(), 53);
+        assert_eq!(offset, PACKET.len());
+
+        assert!(matches!(
+            Udp::from_ctx(&Buf(&PACKET[..40]), 34),
+            Err(Error::OutOfBounds)
+        ));
+        assert!(matches!(Tcp::from_ctx(&ctx, 34), Err(Error::OutOfBounds)));
+    }
+
+    #[test]
+    #[allow(clippy::unnecessary_cast)]
+    fn action_codes() {
+        let err: Result<(), ()> = Err(());
+        assert_eq!(err.or_shot(), Err(TC_ACT_SHOT as i32));
+        assert_eq!(err.or_ok(), Err(TC_ACT_OK as i32));
+        assert_eq!(err.or_drop(), Err(xdp_action::XDP_DROP));
+    }
+}
@@ -0,0 +1,316 @@
+//! Packet buffer with explicit headroom and tailroom.
+//!
+//! `PacketBuf` tracks the packet inside a larger buffer the same way an XDP
+//! frame does: `head` is the start of the buffer, the packet lives in
+//! `data..tail` and everything after `tail` up to the end of the buffer is
+//! tailroom. Headers are added and removed by moving `data`, the equivalent of
+//! `bpf_xdp_adjust_head`, and the packet is trimmed or extended by moving
+//! `tail`, the equivalent of `bpf_xdp_adjust_tail`. No bytes are ever copied.
+
+use crate::link::{eth, pppoe, Ethernet, PPPoE, Ppp};
+use crate::network::{ipv4, ipv6, IPv4, IPv6};
+use crate::transport::icmp::Icmp;
+use crate::transport::icmpv6::Icmpv6;
+use crate::transport::tcp::{self, Tcp};
+use crate::transport::udp::{self, Udp};
+
+/// Length of the fixed part of a header, used to push and pull it as a whole.
+pub trait HeaderLen {
+    const LEN: usize;
+}
+
+impl HeaderLen for Ethernet {
+    const LEN: usize = Ethernet::MIN_LEN;
+}
+
+impl HeaderLen for PPPoE {
+    const LEN: usize = PPPoE::SIZE;
+}
+
+impl HeaderLen for Ppp {
+    const LEN: usize = Ppp::SIZE;
+}
+
+impl HeaderLen for IPv4 {
+    const LEN: usize = IPv4::MIN_LEN;
+}
+
+impl HeaderLen for IPv6 {
+    const LEN: usize = IPv6::SIZE;
+}
+
+impl HeaderLen for Tcp {
+    const LEN: usize = Tcp::MIN_LEN;
+}
+
+impl HeaderLen for Udp {
+    const LEN: usize = Udp::SIZE;
+}
+
+impl HeaderLen for Icmp {
+    const LEN: usize = Icmp::SIZE;
+}
+
+impl HeaderLen for Icmpv6 {
+    const LEN: usize = Icmpv6::SIZE;
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// `data <= tail <= buffer length` does not hold.
+    InvalidOffsets(usize, usize),
+    NoHeadroom(usize),
+    NoTailroom(usize),
+    /// The packet is shorter than the amount to remove.
+    TooShort(usize),
+}
+
+pub struct PacketBuf<'a> {
+    buf: &'a mut [u8],
+    data: usize,
+    tail: usize,
+}
+
+impl<'a> PacketBuf<'a> {
+    /// Packet in `buf[data..tail]`.
+    pub fn new(buf: &'a mut [u8], data: usize, tail: usize) -> Result<Self, Error> {
+        if data > tail || tail > buf.len() {
+            return Err(Error::InvalidOffsets(data, tail));
+        }
+        Ok(Self { buf, data, tail })
+    }
+
+    /// Packet filling `buf` after `headroom` bytes, without tailroom.
+    pub fn with_headroom(buf: &'a mut [u8], headroom: usize) -> Result<Self, Error> {
+        let tail = buf.len();
+        Self::new(buf, headroom, tail)
+    }
+
+    pub fn headroom(&self) -> usize {
+        self.data
+    }
+
+    pub fn tailroom(&self) -> usize {
+        self.buf.len() - self.tail
+    }
+
+    /// Offset of the first packet byte from the start of the buffer.
+    pub fn data_offset(&self) -> usize {
+        self.data
+    }
+
+    /// Offset one past the last packet byte from the start of the buffer.
+    pub fn tail_offset(&self) -> usize {
+        self.tail
+    }
+
+    pub fn len(&self) -> usize {
+        self.tail - self.data
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data == self.tail
+    }
+
+    pub fn data(&self) -> &[u8] {
+        self.buf.get(self.data..self.tail).unwrap_or(&[])
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        self.buf.get_mut(self.data..self.tail).unwrap_or(&mut [])
+    }
+
+    /// The whole underlying buffer, including headroom and tailroom.
+    pub fn buffer(&self) -> &[u8] {
+        self.buf
+    }
+
+    /// Moves the start of the packet by `delta` bytes, a negative value grows
+    /// the packet into the headroom and a positive one shrinks it.
+    pub fn adjust_head(&mut self, delta: isize) -> Result<(), Error> {
+        let n = delta.unsigned_abs();
+        if delta < 0 {
+            self.data = self.data.checked_sub(n).ok_or(Error::NoHeadroom(n))?;
+        } else {
+            if n > self.len() {
+                return Err(Error::TooShort(n));
+            }
+            self.data += n;
+        }
+        Ok(())
+    }
+
+    /// Moves the end of the packet by `delta` bytes, a positive value grows
+    /// the packet into the tailroom and a negative one shrinks it.
+    pub fn adjust_tail(&mut self, delta: isize) -> Result<(), Error> {
+        let n = delta.unsigned_abs();
+        if delta < 0 {
+            if n > self.len() {
+                return Err(Error::TooShort(n));
+            }
+            self.tail -= n;
+        } else {
+            if n > self.tailroom() {
+                return Err(Error::NoTailroom(n));
+            }
+            self.tail += n;
+        }
+        Ok(())
+    }
+
+    /// Prepends `len` zeroed bytes to the packet and returns them.
+    pub fn push(&mut self, len: usize) -> Result<&mut [u8], Error> {
+        let data = self.data.checked_sub(len).ok_or(Error::NoHeadroom(len))?;
+        let header = self.buf.get_mut(data..self.data).unwrap_or(&mut []);
+        header.fill(0);
+        self.data = data;
+        Ok(header)
+    }
+
+    /// Removes `len` bytes from the front of the packet and returns them.
+    pub fn pull(&mut self, len: usize) -> Result<&mut [u8], Error> {
+        if len > self.len() {
+            return Err(Error::TooShort(len));
+        }
+        let data = self.data;
+        self.data += len;
+        Ok(self.buf.get_mut(data..self.data).unwrap_or(&mut []))
+    }
+
+    /// Prepends a zeroed `T` header, e.g. `buf.push_header::<Ethernet>()`.
+    pub fn push_header<T: HeaderLen>(&mut self) -> Result<&mut [u8], Error> {
+        self.push(T::LEN)
+    }
+
+    /// Removes the fixed part of a `T` header from the front of the packet.
+    pub fn pull_header<T: HeaderLen>(&mut self) -> Result<&mut [u8], Error> {
+        self.pull(T::LEN)
+    }
+
+    /// Packet bytes starting `offset` bytes after `data`.
+    fn at(&mut self, offset: usize) -> &mut [u8] {
+        self.data_mut().get_mut(offset..).unwrap_or(&mut [])
+    }
+}
+
+impl<'a> Ethernet<&'a mut [u8]> {
+    /// Mutable view at `offset` bytes into the packet, also returning the
+    /// offset of the next header.
+    pub fn from_buf(
+        buf: &'a mut PacketBuf<'_>,
+        offset: usize,
+    ) -> Result<(Self, usize), eth::Error> {
+        let (eth, _) = Ethernet::new_mut(buf.at(offset))?;
+        let next = offset + eth.size_usize();
+        Ok((eth, next))
+    }
+}
+
+impl<'a> PPPoE<&'a mut [u8]> {
+    pub fn from_buf(
+        buf: &'a mut PacketBuf<'_>,
+        offset: usize,
+    ) -> Result<(Self, usize), pppoe::Error> {
+        let (pppoe, _) = PPPoE::new_mut(buf.at(offset))?;
+        Ok((pppoe, offset + PPPoE::SIZE))
+    }
+}
+
+impl<'a> IPv4<&'a mut [u8]> {
+    pub fn from_buf(
+        buf: &'a mut PacketBuf<'_>,
+        offset: usize,
+    ) -> Result<(Self, usize), ipv4::Error> {
+        let (ip, _) = IPv4::new_mut(buf.at(offset))?;
+        let next = offset + ip.size() as usize;
+        Ok((ip, next))
+    }
+}
+
+impl<'a> IPv6<&'a mut [u8]> {
+    pub fn from_buf(
+        buf: &'a mut PacketBuf<'_>,
+        offset: usize,
+    ) -> Result<(Self, usize), ipv6::Error> {
+        let (ip, _) = IPv6::new_mut(buf.at(offset))?;
+        Ok((ip, offset + IPv6::SIZE))
+    }
+}
+
+impl<'a> Tcp<&'a mut [u8]> {
+    pub fn from_buf(
+        buf: &'a mut PacketBuf<'_>,
+        offset: usize,
+    ) -> Result<(Self, usize), tcp::Error> {
+        let (tcp, _) = Tcp::new_mut(buf.at(offset))?;
+        let next = offset + tcp.size() as usize;
+        Ok((tcp, next))
+    }
+}
+
+impl<'a> Udp<&'a mut [u8]> {
+    pub fn from_buf(
+        buf: &'a mut PacketBuf<'_>,
+        offset: usize,
+    ) -> Result<(Self, usize), udp::Error> {
+        let (udp, _) = Udp::new_mut(buf.at(offset))?;
+        Ok((udp, offset + Udp::SIZE))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link::EtherType;
+    use crate::network::InetProtocol;
+
+    #[test]
+    fn push_pull_ethernet() {
+        let mut frame = [0u8; 64];
+        frame[14] = 0x45;
+        frame[23] = 17;
+        let mut buf = PacketBuf::new(&mut frame, 14, 14 + 28).unwrap();
+        assert_eq!((buf.headroom(), buf.len(), buf.tailroom()), (14, 28, 22));
+
+        let (ip, next) = IPv4::from_buf(&mut buf, 0).unwrap();
+        assert_eq!(ip.protocol(), InetProtocol::UDP);
+        assert_eq!(next, 20);
+
+        assert_eq!(buf.push_header::<Ethernet>().unwrap().len(), 14);
+        let (mut eth, next) = Ethernet::from_buf(&mut buf, 0).unwrap();
+        eth.set_ethertype(EtherType::IPv4);
+        eth.set_source(&[0x02, 0, 0, 0, 0, 1]);
+        assert_eq!(next, 14);
+        assert_eq!(buf.headroom(), 0);
+        assert!(matches!(buf.push(1), Err(Error::NoHeadroom(1))));
+
+        let (_, next) = IPv4::from_buf(&mut buf, 14).unwrap();
+        let (udp, _) = Udp::from_buf(&mut buf, next).unwrap();
+        assert_eq!(udp.length(), 0);
+
+        let eth = buf.pull_header::<Ethernet>().unwrap();
+        assert_eq!(&eth[6..14], &[0x02, 0, 0, 0, 0, 1, 0x08, 0x00]);
+        assert_eq!(buf.data()[0], 0x45);
+    }
+
+    #[test]
+    fn adjust() {
+        let mut frame = [0u8; 32];
+        let mut buf = PacketBuf::with_headroom(&mut frame, 8).unwrap();
+        assert_eq!(buf.len(), 24);
+        assert!(matches!(buf.adjust_tail(1), Err(Error::NoTailroom(1))));
+
+        buf.adjust_tail(-4).unwrap();
+        buf.adjust_tail(2).unwrap();
+        assert_eq!((buf.tail_offset(), buf.tailroom()), (30, 2));
+
+        buf.adjust_head(-8).unwrap();
+        assert!(matches!(buf.adjust_head(-1), Err(Error::NoHeadroom(1))));
+        buf.adjust_head(30).unwrap();
+        assert!(buf.is_empty());
+        assert!(matches!(buf.pull(1), Err(Error::TooShort(1))));
+        assert!(Ethernet::from_buf(&mut buf, 0).is_err());
+
+        assert!(PacketBuf::new(&mut frame, 4, 2).is_err());
+    }
+}
@@ -0,0 +1,86 @@
+//! Incremental updates of the Internet checksum \[[RFC1624](https://datatracker.ietf.org/doc/html/rfc1624)\].
+//!
+//! All helpers assume the changed bytes start at an even offset from the
+//! beginning of the checksummed data, which holds for every address, port
+//! and checksum field in the IPv4, TCP, UDP and ICMP headers.
+
+#[inline(always)]
+fn fold(mut sum: u32) -> u16 {
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum as u16
+}
+
+#[inline(always)]
+fn add_words(mut sum: u32, bytes: &[u8], negate: bool) -> u32 {
+    let mut chunks = bytes.chunks_exact(2);
+    for chunk in &mut chunks {
+        let word = u16::from_be_bytes([chunk[0], chunk[1]]);
+        sum += if negate { !word } else { word } as u32;
+    }
+    if let [last] = chunks.remainder() {
+        let word = u16::from_be_bytes([*last, 0]);
+        sum += if negate { !word } else { word } as u32;
+    }
+    sum
+}
+
+/// Checksum after the bytes `old` were replaced by `new`, `HC' = ~(~HC + ~m + m')`.
+pub fn update(csum: u16, old: &[u8], new: &[u8]) -> u16 {
+    let sum = add_words(!csum as u32, old, true);
+    !fold(add_words(sum, new, false))
+}
+
+pub fn update_u16(csum: u16, old: u16, new: u16) -> u16 {
+    update(csum, &old.to_be_bytes(), &new.to_be_bytes())
+}
+
+pub fn update_u32(csum: u16, old: u32, new: u32) -> u16 {
+    update(csum, &old.to_be_bytes(), &new.to_be_bytes())
+}
+
+/// Ones' complement sum of `bytes` added to `sum`, not yet folded nor complemented.
+pub fn sum(sum: u32, bytes: &[u8]) -> u32 {
+    add_words(sum, bytes, false)
+}
+
+/// Unfolded sum of the IPv4 pseudo header used by the TCP and UDP checksums.
+pub fn pseudo_ipv4(source: &[u8; 4], destination: &[u8; 4], protocol: u8, length: u16) -> u32 {
+    let sum = add_words(0, source, false);
+    let sum = add_words(sum, destination, false);
+    sum + protocol as u32 + length as u32
+}
+
+/// Unfolded sum of the IPv6 pseudo header used by the TCP, UDP and ICMPv6 checksums.
+pub fn pseudo_ipv6(source: &[u8; 16], destination: &[u8; 16], next_header: u8, length: u32) -> u32 {
+    let sum = add_words(0, source, false);
+    let sum = add_words(sum, destination, false);
+    let sum = add_words(sum, &length.to_be_bytes(), false);
+    sum + next_header as u32
+}
+
+/// Folds and complements a running `sum` into the value stored in a header.
+pub fn finish(sum: u32) -> u16 {
+    !fold(sum)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn incremental_matches_full() {
+        let mut header = [
+            0x45, 0x00, 0x00, 0x73, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11, 0x00, 0x00, 0xc0, 0xa8,
+            0x00, 0x01, 0xc0, 0xa8, 0x00, 0xc7,
+        ];
+        let csum = finish(sum(0, &header));
+        assert_eq!(csum, 0xb861);
+
+        let new = [10, 1, 2, 3];
+        let updated = update(csum, &header[12..16], &new);
+        header[12..16].copy_from_slice(&new);
+        assert_eq!(updated, finish(sum(0, &header)));
+    }
+}
@@ -0,0 +1,682 @@
+//! TCP connection tracking.
+//!
+//! The handshake is followed as seen on the wire (`SynSent` after the SYN,
+//! `SynReceived` after the SYN/ACK, `Established` after the final ACK) and
+//! the teardown by the RFC 793 state of the side that opened the connection.
+//!
+//! Every segment is checked against the windows advertised by both ends with
+//! the algorithm of Linux nf_conntrack (Guido van Rooij, "Real Stateful TCP
+//! Packet Filtering in IP Filter"), so spoofed RSTs and stale segments are
+//! rejected instead of tearing down or confusing the state.
+//!
+//! Storage is pluggable through `Table`: `SlotTable` is a bounded open
+//! addressing table over a caller provided slice, fit for eBPF array maps, and
+//! with the `std` feature a `HashMap` can be used directly. `Connection` is
+//! `#[repr(C)]` so it can also be stored as the value of an eBPF hash map and
+//! updated in place with `Connection::update`.
+
+use crate::flow::FlowKey;
+use crate::transport::tcp::Tcp;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[repr(u8)]
+pub enum TcpState {
+    /// No connection, marks free slots.
+    #[default]
+    None = 0,
+    SynSent = 1,
+    SynReceived = 2,
+    Established = 3,
+    FinWait1 = 4,
+    FinWait2 = 5,
+    CloseWait = 6,
+    Closing = 7,
+    LastAck = 8,
+    TimeWait = 9,
+    /// Reset by either end.
+    Closed = 10,
+}
+
+impl TcpState {
+    /// Idle timeout in seconds, the defaults of nf_conntrack.
+    pub fn timeout(&self) -> u64 {
+        match self {
+            TcpState::None => 0,
+            TcpState::SynSent => 120,
+            TcpState::SynReceived => 60,
+            TcpState::Established => 5 * 24 * 60 * 60,
+            TcpState::FinWait1 | TcpState::FinWait2 | TcpState::Closing => 120,
+            TcpState::CloseWait => 60,
+            TcpState::LastAck => 30,
+            TcpState::TimeWait => 120,
+            TcpState::Closed => 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// From the end that sent the first SYN.
+    Original,
+    Reply,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// Not a SYN and no connection exists.
+    NotTracked,
+    /// Sequence or acknowledgment number outside the tracked windows.
+    OutOfWindow,
+    /// Flags not valid in the current state, e.g. a SYN on an established connection.
+    InvalidFlags(TcpState),
+    /// No room left in the table.
+    Full,
+}
+
+const FLAG_WINDOW_SCALE: u8 = 1 << 0;
+const FLAG_FIN_SENT: u8 = 1 << 1;
+const FLAG_FIN_ACKED: u8 = 1 << 2;
+
+/// Window tracking data of one end, the `ip_ct_tcp_state` of nf_conntrack.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Peer {
+    /// Highest sequence number sent plus one.
+    end: u32,
+    /// Highest sequence number the other end allowed this one to send.
+    maxend: u32,
+    /// Largest window advertised.
+    maxwin: u32,
+    scale: u8,
+    flags: u8,
+    _pad: [u8; 2],
+}
+
+/// Whether `a` comes before `b` in sequence space.
+#[inline(always)]
+fn before(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+#[inline(always)]
+fn after(a: u32, b: u32) -> bool {
+    before(b, a)
+}
+
+/// Window scale option from the options of a SYN.
+fn window_scale(options: &[u8]) -> Option<u8> {
+    let mut rest = options;
+    loop {
+        match rest {
+            [] | [0, ..] => return None,
+            [1, tail @ ..] => rest = tail,
+            [3, 3, shift, ..] => return Some((*shift).min(14)),
+            [_, len, ..] if *len >= 2 => rest = rest.get(*len as usize..)?,
+            _ => return None,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Connection {
+    /// Time of the last valid segment, in the unit of `now` (seconds).
+    pub last_seen: u64,
+    peers: [Peer; 2],
+    state: TcpState,
+    /// Direction of the first FIN, 0 for original, 1 for reply.
+    first_fin: u8,
+    _pad: [u8; 6],
+}
+
+impl Connection {
+    pub fn state(&self) -> TcpState {
+        self.state
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        now.saturating_sub(self.last_seen) > self.state.timeout()
+    }
+
+    /// Validates a segment and advances the state, `payload_len` is the
+    /// length of the TCP payload. The connection is left untouched when an
+    /// error is returned.
+    pub fn update<P: AsRef<[u8]>>(
+        &mut self,
+        direction: Direction,
+        tcp: &Tcp<P>,
+        payload_len: u32,
+        now: u64,
+    ) -> Result<TcpState, Error> {
+        let mut next = *self;
+        if tcp.syn() && !tcp.ack() && direction == Direction::Original {
+            match self.state {
+                TcpState::None | TcpState::TimeWait | TcpState::Closed => next = Self::default(),
+                TcpState::SynSent => {}
+                x => return Err(Error::InvalidFlags(x)),
+            }
+        } else if self.state == TcpState::None {
+            return Err(Error::NotTracked);
+        }
+
+        if !next.in_window(direction, tcp, payload_len) {
+            return Err(Error::OutOfWindow);
+        }
+        next.state = next.transition(direction, tcp)?;
+        next.last_seen = now;
+        *self = next;
+        Ok(self.state)
+    }
+
+    fn in_window<P: AsRef<[u8]>>(&mut self, direction: Direction, tcp: &Tcp<P>, len: u32) -> bool {
+        let [original, reply] = &mut self.peers;
+        let (sender, receiver) = match direction {
+            Direction::Original => (original, reply),
+            Direction::Reply => (reply, original),
+        };
+
+        let mut seq = tcp.sequence_num();
+        let mut end = seq
+            .wrapping_add(len)
+            .wrapping_add(tcp.syn() as u32)
+            .wrapping_add(tcp.fin() as u32);
+        let mut win = tcp.window_size() as u32;
+
+        if sender.maxwin == 0 {
+            if !tcp.syn() {
+                return false;
+            }
+            sender.end = end;
+            sender.maxend = end;
+            sender.maxwin = win.max(1);
+            if let Some(scale) = window_scale(tcp.options()) {
+                sender.flags |= FLAG_WINDOW_SCALE;
+                sender.scale = scale;
+            }
+            // Scaling is on only if both ends asked for it, which is known
+            // once the SYN/ACK is seen.
+            if tcp.ack() && sender.flags & receiver.flags & FLAG_WINDOW_SCALE == 0 {
+                sender.scale = 0;
+                receiver.scale = 0;
+            }
+            if !tcp.ack() {
+                // SYN of a new connection or of a simultaneous open.
+                return true;
+            }
+        }
+
+        let mut ack = tcp.ack_num();
+        if !tcp.ack() || (tcp.rst() && ack == 0) {
+            ack = receiver.end;
+        }
+        if tcp.rst() && seq == 0 && self.state == TcpState::SynSent {
+            seq = sender.end;
+            end = sender.end;
+        }
+        if !tcp.syn() {
+            win <<= sender.scale;
+        }
+
+        let max_ack_window = sender.maxwin.max(66000);
+        let valid = before(seq, sender.maxend.wrapping_add(1))
+            && after(
+                end,
+                sender.end.wrapping_sub(receiver.maxwin).wrapping_sub(1),
+            )
+            && before(ack, receiver.end.wrapping_add(1))
+            && after(
+                ack,
+                receiver.end.wrapping_sub(max_ack_window).wrapping_sub(1),
+            );
+        if !valid {
+            return false;
+        }
+
+        sender.maxwin = sender.maxwin.max(win);
+        if after(end, sender.end) {
+            sender.end = end;
+        }
+        if receiver.maxwin != 0 && after(end, sender.maxend) {
+            receiver.maxwin = receiver
+                .maxwin
+                .wrapping_add(end.wrapping_sub(sender.maxend));
+        }
+        if after(ack.wrapping_add(win), receiver.maxend.wrapping_sub(1)) {
+            receiver.maxend = ack.wrapping_add(win);
+            if win == 0 {
+                receiver.maxend = receiver.maxend.wrapping_add(1);
+            }
+        }
+
+        if tcp.fin() {
+            sender.flags |= FLAG_FIN_SENT;
+        }
+        if tcp.ack() && receiver.flags & FLAG_FIN_SENT != 0 && !before(ack, receiver.end) {
+            receiver.flags |= FLAG_FIN_ACKED;
+        }
+        true
+    }
+
+    fn transition<P: AsRef<[u8]>>(
+        &mut self,
+        direction: Direction,
+        tcp: &Tcp<P>,
+    ) -> Result<TcpState, Error> {
+        use Direction::*;
+        use TcpState::*;
+
+        if tcp.rst() {
+            return Ok(Closed);
+        }
+        let state = match (self.state, direction, tcp.syn(), tcp.ack()) {
+            (None, Original, true, false) | (SynSent, Original, true, false) => SynSent,
+            // Simultaneous open.
+            (SynSent, Reply, true, false) => SynReceived,
+            (SynSent | SynReceived, _, true, true) => SynReceived,
+            (SynReceived, Original, false, true) => Established,
+            (SynReceived, _, false, _) => SynReceived,
+            (x, _, true, _) | (x @ (SynSent | Closed), _, _, _) => {
+                return Err(Error::InvalidFlags(x))
+            }
+            (x, _, false, _) => x,
+        };
+        if matches!(state, None | SynSent | SynReceived) {
+            return Ok(state);
+        }
+
+        let [original, reply] = self
+            .peers
+            .map(|p| (p.flags & FLAG_FIN_SENT != 0, p.flags & FLAG_FIN_ACKED != 0));
+        if (original.0 || reply.0) && !(original.0 && reply.0) {
+            self.first_fin = reply.0 as u8;
+        }
+        Ok(match (self.first_fin, original, reply) {
+            (_, (false, _), (false, _)) => Established,
+            (0, (true, false), (false, _)) => FinWait1,
+            (0, (true, true), (false, _)) => FinWait2,
+            (0, (true, false), (true, _)) => Closing,
+            (0, (true, true), (true, _)) => TimeWait,
+            (_, (false, _), (true, _)) => CloseWait,
+            (_, (true, false), (true, _)) => LastAck,
+            (_, (true, true), (true, _)) => TimeWait,
+            (_, (true, _), (false, _)) => FinWait1,
+        })
+    }
+}
+
+/// Storage of connections keyed by the flow key of their original direction.
+pub trait Table {
+    fn get_mut(&mut self, key: &FlowKey) -> Option<&mut Connection>;
+
+    /// Stores a new connection, possibly evicting one expired at `now`.
+    fn insert(
+        &mut self,
+        key: FlowKey,
+        connection: Connection,
+        now: u64,
+    ) -> Result<&mut Connection, Error>;
+
+    fn remove(&mut self, key: &FlowKey);
+}
+
+/// Tracks a segment of the flow `key`, as seen on the wire (not canonical).
+/// Connections are created by a SYN, expired ones are dropped on access.
+pub fn track<T: Table, P: AsRef<[u8]>>(
+    table: &mut T,
+    key: &FlowKey,
+    tcp: &Tcp<P>,
+    payload_len: u32,
+    now: u64,
+) -> Result<(Direction, TcpState), Error> {
+    let reversed = key.reversed();
+    let (direction, stored) = if table.get_mut(key).is_some() {
+        (Direction::Original, key)
+    } else if table.get_mut(&reversed).is_some() {
+        (Direction::Reply, &reversed)
+    } else {
+        (Direction::Original, key)
+    };
+
+    if let Some(connection) = table.get_mut(stored) {
+        if !connection.is_expired(now) {
+            return connection
+                .update(direction, tcp, payload_len, now)
+                .map(|state| (direction, state));
+        }
+        table.remove(stored);
+    }
+
+    let mut connection = Connection::default();
+    let state = connection.update(Direction::Original, tcp, payload_len, now)?;
+    table.insert(*key, connection, now)?;
+    Ok((Direction::Original, state))
+}
+
+/// Entry of a `SlotTable`, free when the state is `TcpState::None`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Slot {
+    pub key: FlowKey,
+    pub connection: Connection,
+}
+
+/// Open addressing table over a fixed slice of slots, probing a bounded
+/// number of slots from the flow hash so lookups stay within eBPF limits.
+pub struct SlotTable<'a> {
+    slots: &'a mut [Slot],
+}
+
+impl<'a> SlotTable<'a> {
+    pub const MAX_PROBES: usize = 8;
+
+    pub fn new(slots: &'a mut [Slot]) -> Self {
+        Self { slots }
+    }
+
+    fn probe(&self, key: &FlowKey) -> impl Iterator<Item = usize> {
+        let len = self.slots.len();
+        let start = (key.hash(0) as usize).checked_rem(len).unwrap_or(0);
+        (0..Self::MAX_PROBES.min(len)).map(move |i| (start + i) % len)
+    }
+
+    fn find(&self, key: &FlowKey) -> Option<usize> {
+        self.probe(key).find(|&i| {
+            self.slots
+                .get(i)
+                .is_some_and(|s| s.connection.state != TcpState::None && s.key == *key)
+        })
+    }
+}
+
+impl Table for SlotTable<'_> {
+    fn get_mut(&mut self, key: &FlowKey) -> Option<&mut Connection> {
+        let i = self.find(key)?;
+        self.slots.get_mut(i).map(|s| &mut s.connection)
+    }
+
+    fn insert(
+        &mut self,
+        key: FlowKey,
+        connection: Connection,
+        now: u64,
+    ) -> Result<&mut Connection, Error> {
+        let i = match self.find(&key) {
+            Some(i) => i,
+            None => self
+                .probe(&key)
+                .find(|&i| {
+                    self.slots.get(i).is_some_and(|s| {
+                        s.connection.state == TcpState::None || s.connection.is_expired(now)
+                    })
+                })
+                .ok_or(Error::Full)?,
+        };
+        let slot = self.slots.get_mut(i).ok_or(Error::Full)?;
+        *slot = Slot { key, connection };
+        Ok(&mut slot.connection)
+    }
+
+    fn remove(&mut self, key: &FlowKey) {
+        if let Some(slot) = self.find(key).and_then(|i| self.slots.get_mut(i)) {
+            *slot = Slot::default();
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<S: std::hash::BuildHasher> Table for std::collections::HashMap<FlowKey, Connection, S> {
+    fn get_mut(&mut self, key: &FlowKey) -> Option<&mut Connection> {
+        std::collections::HashMap::get_mut(self, key)
+    }
+
+    fn insert(
+        &mut self,
+        key: FlowKey,
+        connection: Connection,
+        _now: u64,
+    ) -> Result<&mut Connection, Error> {
+        use std::collections::hash_map::Entry;
+
+        Ok(match self.entry(key) {
+            Entry::Occupied(mut e) => {
+                e.insert(connection);
+                e.into_mut()
+            }
+            Entry::Vacant(e) => e.insert(connection),
+        })
+    }
+
+    fn remove(&mut self, key: &FlowKey) {
+        std::collections::HashMap::remove(self, key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::InetProtocol;
+
+    const SYN: u8 = 0x02;
+    const RST: u8 = 0x04;
+    const ACK: u8 = 0x10;
+    const FIN: u8 = 0x01;
+
+    fn segment(seq: u32, ack: u32, flags: u8, window: u16) -> [u8; 20] {
+        let mut header = [0u8; 20];
+        header[4..8].copy_from_slice(&seq.to_be_bytes());
+        header[8..12].copy_from_slice(&ack.to_be_bytes());
+        header[12] = 5 << 4;
+        header[13] = flags;
+        header[14..16].copy_from_slice(&window.to_be_bytes());
+        header
+    }
+
+    fn send<T: Table>(
+        table: &mut T,
+        key: &FlowKey,
+        header: [u8; 20],
+        len: u32,
+    ) -> Result<(Direction, TcpState), Error> {
+        let (tcp, _) = Tcp::new(&header).unwrap();
+        track(table, key, &tcp, len, 1)
+    }
+
+    fn keys() -> (FlowKey, FlowKey) {
+        let key = FlowKey::ipv4(&[10, 0, 0, 1], &[10, 0, 0, 2], InetProtocol::TCP, 40000, 80);
+        (key, key.reversed())
+    }
+
+    fn handshake<T: Table>(table: &mut T) {
+        let (c, s) = keys();
+        assert_eq!(
+            send(table, &c, segment(100, 0, SYN, 1000), 0).unwrap().1,
+            TcpState::SynSent
+        );
+        assert_eq!(
+            send(table, &s, segment(5000, 101, SYN | ACK, 1000), 0).unwrap(),
+            (Direction::Reply, TcpState::SynReceived)
+        );
+        assert_eq!(
+            send(table, &c, segment(101, 5001, ACK, 1000), 0).unwrap().1,
+            TcpState::Established
+        );
+    }
+
+    #[test]
+    fn lifecycle_client_close() {
+        let mut slots = [Slot::default(); 16];
+        let mut table = SlotTable::new(&mut slots);
+        let (c, s) = keys();
+        assert!(matches!(
+            send(&mut table, &c, segment(1, 0, ACK, 1000), 0),
+            Err(Error::NotTracked)
+        ));
+
+        handshake(&mut table);
+        assert_eq!(
+            send(&mut table, &c, segment(101, 5001, ACK, 1000), 100)
+                .unwrap()
+                .1,
+            TcpState::Established
+        );
+        // Far beyond what the server allowed.
+        assert!(matches!(
+            send(&mut table, &c, segment(900_000, 5001, ACK, 1000), 10),
+            Err(Error::OutOfWindow)
+        ));
+        assert!(matches!(
+            send(&mut table, &c, segment(101, 5001, SYN, 1000), 0),
+            Err(Error::InvalidFlags(TcpState::Established))
+        ));
+
+        assert_eq!(
+            send(&mut table, &c, segment(201, 5001, FIN | ACK, 1000), 0)
+                .unwrap()
+                .1,
+            TcpState::FinWait1
+        );
+        assert_eq!(
+            send(&mut table, &s, segment(5001, 202, ACK, 1000), 0)
+                .unwrap()
+                .1,
+            TcpState::FinWait2
+        );
+        assert_eq!(
+            send(&mut table, &s, segment(5001, 202, FIN | ACK, 1000), 0)
+                .unwrap()
+                .1,
+            TcpState::TimeWait
+        );
+        assert_eq!(
+            send(&mut table, &c, segment(202, 5002, ACK, 1000), 0)
+                .unwrap()
+                .1,
+            TcpState::TimeWait
+        );
+
+        // A new SYN reuses the tuple.
+        assert_eq!(
+            send(&mut table, &c, segment(7000, 0, SYN, 1000), 0)
+                .unwrap()
+                .1,
+            TcpState::SynSent
+        );
+    }
+
+    #[test]
+    fn server_close_and_reset() {
+        let mut slots = [Slot::default(); 16];
+        let mut table = SlotTable::new(&mut slots);
+        let (c, s) = keys();
+        handshake(&mut table);
+
+        assert_eq!(
+            send(&mut table, &s, segment(5001, 101, FIN | ACK, 1000), 0)
+                .unwrap()
+                .1,
+            TcpState::CloseWait
+        );
+        assert_eq!(
+            send(&mut table, &c, segment(101, 5002, FIN | ACK, 1000), 0)
+                .unwrap()
+                .1,
+            TcpState::LastAck
+        );
+        assert_eq!(
+            send(&mut table, &s, segment(5002, 102, ACK, 1000), 0)
+                .unwrap()
+                .1,
+            TcpState::TimeWait
+        );
+
+        let c2 = c.with_vlan(2);
+        let s2 = c2.reversed();
+        handshake_on(&mut table, &c2, &s2);
+        // RST outside the window is ignored, inside it closes.
+        assert!(matches!(
+            send(&mut table, &s2, segment(3_000_000, 0, RST, 0), 0),
+            Err(Error::OutOfWindow)
+        ));
+        assert_eq!(
+            send(&mut table, &s2, segment(5001, 0, RST, 0), 0)
+                .unwrap()
+                .1,
+            TcpState::Closed
+        );
+    }
+
+    fn handshake_on<T: Table>(table: &mut T, c: &FlowKey, s: &FlowKey) {
+        send(table, c, segment(100, 0, SYN, 1000), 0).unwrap();
+        send(table, s, segment(5000, 101, SYN | ACK, 1000), 0).unwrap();
+        send(table, c, segment(101, 5001, ACK, 1000), 0).unwrap();
+    }
+
+    #[test]
+    fn window_scale_option() {
+        assert_eq!(window_scale(&[1, 1, 3, 3, 7, 0]), Some(7));
+        assert_eq!(window_scale(&[2, 4, 5, 0xb4, 3, 3, 20]), Some(14));
+        assert_eq!(window_scale(&[2, 4, 5, 0xb4]), None);
+        assert_eq!(window_scale(&[2, 0]), None);
+    }
+
+    #[test]
+    fn window_scaled_handshake() {
+        let scaled = |seq, ack, flags, window| {
+            let mut header = [0u8; 24];
+            header[..20].copy_from_slice(&segment(seq, ack, flags, window));
+            header[12] = 6 << 4;
+            header[20..].copy_from_slice(&[1, 3, 3, 7]);
+            header
+        };
+        let mut slots = [Slot::default(); 16];
+        let mut table = SlotTable::new(&mut slots);
+        let (c, s) = keys();
+        let mut send = |key, header: &[u8], len| {
+            let (tcp, _) = Tcp::new(header).unwrap();
+            track(&mut table, key, &tcp, len, 1).map(|(_, state)| state)
+        };
+
+        send(&c, &scaled(100, 0, SYN, 64240), 0).unwrap();
+        send(&s, &scaled(5000, 101, SYN | ACK, 65535), 0).unwrap();
+        // 502 << 7 lets the server send 64256 bytes.
+        send(&c, &segment(101, 5001, ACK, 502), 0).unwrap();
+        assert_eq!(
+            send(&s, &segment(5001, 101, ACK, 502), 10000).unwrap(),
+            TcpState::Established
+        );
+        assert_eq!(
+            send(&s, &segment(15001, 101, ACK, 502), 10000).unwrap(),
+            TcpState::Established
+        );
+        assert!(matches!(
+            send(&s, &segment(70000, 101, ACK, 502), 1000),
+            Err(Error::OutOfWindow)
+        ));
+    }
+
+    #[test]
+    fn slot_table_full() {
+        let mut slots = [Slot::default(); 1];
+        let mut table = SlotTable::new(&mut slots);
+        let (c, _) = keys();
+        send(&mut table, &c, segment(100, 0, SYN, 1000), 0).unwrap();
+        let other = c.with_vlan(3);
+        assert!(matches!(
+            send(&mut table, &other, segment(1, 0, SYN, 1000), 0),
+            Err(Error::Full)
+        ));
+
+        let header = segment(1, 0, SYN, 1000);
+        let (tcp, _) = Tcp::new(&header).unwrap();
+        let timeout = TcpState::SynSent.timeout();
+        assert!(track(&mut table, &other, &tcp, 0, 2 + timeout).is_ok());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn hash_map() {
+        let mut table = std::collections::HashMap::new();
+        handshake(&mut table);
+        assert_eq!(table.len(), 1);
+    }
+}
@@ -0,0 +1,285 @@
+//! Layered decoding of captured frames, from the link type of the capture
+//! down to the transport header.
+//!
+//! Decoding stops quietly at the first layer it does not know (e.g. ARP or
+//! an unknown IP protocol), leaving the rest in `payload`, and fails only on
+//! malformed headers of the layers it does know.
+
+use crate::link::{eth, null, sll, EtherType, Ethernet, Null, Sll, Sll2};
+use crate::network::{ipv4, ipv6, IPv4, IPv6, InetProtocol};
+use crate::pcap::LinkType;
+use crate::transport::icmp::{self, Icmp};
+use crate::transport::icmpv6::{self, Icmpv6};
+use crate::transport::tcp::{self, Tcp};
+use crate::transport::udp::{self, Udp};
+
+#[derive(Debug)]
+pub enum Error {
+    Ethernet(eth::Error),
+    Sll(sll::Error),
+    Null(null::Error),
+    IPv4(ipv4::Error),
+    IPv6(ipv6::Error),
+    Tcp(tcp::Error),
+    Udp(udp::Error),
+    Icmp(icmp::Error),
+    Icmpv6(icmpv6::Error),
+    UnsupportedLink(LinkType),
+}
+
+impl From<eth::Error> for Error {
+    fn from(value: eth::Error) -> Self {
+        Error::Ethernet(value)
+    }
+}
+
+impl From<sll::Error> for Error {
+    fn from(value: sll::Error) -> Self {
+        Error::Sll(value)
+    }
+}
+
+impl From<null::Error> for Error {
+    fn from(value: null::Error) -> Self {
+        Error::Null(value)
+    }
+}
+
+impl From<ipv4::Error> for Error {
+    fn from(value: ipv4::Error) -> Self {
+        Error::IPv4(value)
+    }
+}
+
+impl From<ipv6::Error> for Error {
+    fn from(value: ipv6::Error) -> Self {
+        Error::IPv6(value)
+    }
+}
+
+impl From<tcp::Error> for Error {
+    fn from(value: tcp::Error) -> Self {
+        Error::Tcp(value)
+    }
+}
+
+impl From<udp::Error> for Error {
+    fn from(value: udp::Error) -> Self {
+        Error::Udp(value)
+    }
+}
+
+impl From<icmp::Error> for Error {
+    fn from(value: icmp::Error) -> Self {
+        Error::Icmp(value)
+    }
+}
+
+impl From<icmpv6::Error> for Error {
+    fn from(value: icmpv6::Error) -> Self {
+        Error::Icmpv6(value)
+    }
+}
+
+pub enum Link<'a> {
+    Ethernet(Ethernet<&'a [u8]>),
+    Sll(Sll<&'a [u8]>),
+    Sll2(Sll2<&'a [u8]>),
+    Null(Null<&'a [u8]>),
+    /// No link-layer header, the network layer type as sniffed from the IP
+    /// version or given by the link type.
+    Raw(EtherType),
+}
+
+impl Link<'_> {
+    /// Type of the network layer header that follows.
+    pub fn ethertype(&self) -> EtherType {
+        match self {
+            Link::Ethernet(eth) => eth.ethertype(),
+            Link::Sll(sll) => sll.protocol(),
+            Link::Sll2(sll) => sll.protocol(),
+            Link::Null(null) => EtherType::from(null.family()),
+            Link::Raw(ethertype) => *ethertype,
+        }
+    }
+}
+
+pub enum Network<'a> {
+    IPv4(IPv4<&'a [u8]>),
+    IPv6(IPv6<&'a [u8]>),
+}
+
+impl Network<'_> {
+    /// Protocol of the transport header that follows, extension headers are not followed.
+    pub fn protocol(&self) -> InetProtocol {
+        match self {
+            Network::IPv4(ip) => ip.protocol(),
+            Network::IPv6(ip) => ip.next_header(),
+        }
+    }
+}
+
+pub enum Transport<'a> {
+    Tcp(Tcp<&'a [u8]>),
+    Udp(Udp<&'a [u8]>),
+    Icmp(Icmp<&'a [u8]>),
+    Icmpv6(Icmpv6<&'a [u8]>),
+}
+
+pub struct Decoded<'a> {
+    pub link: Link<'a>,
+    pub network: Option<Network<'a>>,
+    /// `None` for unknown protocols and non-first fragments.
+    pub transport: Option<Transport<'a>>,
+    /// Bytes after the last decoded header, without link layer padding.
+    pub payload: &'a [u8],
+}
+
+/// Decodes a frame captured on a link of type `link_type`.
+pub fn decode(link_type: LinkType, data: &[u8]) -> Result<Decoded<'_>, Error> {
+    let (link, rem) = match link_type {
+        LinkType::Ethernet => {
+            let (eth, rem) = Ethernet::new(data)?;
+            (Link::Ethernet(eth), rem)
+        }
+        LinkType::LinuxSll => {
+            let (sll, rem) = Sll::new(data)?;
+            (Link::Sll(sll), rem)
+        }
+        LinkType::LinuxSll2 => {
+            let (sll, rem) = Sll2::new(data)?;
+            (Link::Sll2(sll), rem)
+        }
+        LinkType::Null | LinkType::Loop => {
+            let (null, rem) = Null::new(data)?;
+            (Link::Null(null), rem)
+        }
+        LinkType::Raw => (Link::Raw(ip_version(data)), data),
+        LinkType::Ipv4 => (Link::Raw(EtherType::IPv4), data),
+        LinkType::Ipv6 => (Link::Raw(EtherType::IPv6), data),
+        x => return Err(Error::UnsupportedLink(x)),
+    };
+    let ethertype = link.ethertype();
+    let mut decoded = Decoded {
+        link,
+        network: None,
+        transport: None,
+        payload: rem,
+    };
+    decode_network(&mut decoded, ethertype, rem)?;
+    Ok(decoded)
+}
+
+/// Network layer type from the version nibble of an IP header.
+fn ip_version(data: &[u8]) -> EtherType {
+    match data.first().map(|b| b >> 4) {
+        Some(4) => EtherType::IPv4,
+        Some(6) => EtherType::IPv6,
+        x => EtherType::Other(x.unwrap_or(0) as u16),
+    }
+}
+
+pub(crate) fn decode_network<'a>(
+    decoded: &mut Decoded<'a>,
+    ethertype: EtherType,
+    data: &'a [u8],
+) -> Result<(), Error> {
+    let (network, rem, first_fragment) = match ethertype {
+        EtherType::IPv4 => {
+            let (ip, rem) = IPv4::new(data)?;
+            let len = (ip.total_length() as usize).saturating_sub(ip.size() as usize);
+            let first_fragment = ip.fragment_offset() == [0, 0];
+            (
+                Network::IPv4(ip),
+                rem.get(..len).unwrap_or(rem),
+                first_fragment,
+            )
+        }
+        EtherType::IPv6 => {
+            let (ip, rem) = IPv6::new(data)?;
+            let len = ip.payload_length() as usize;
+            (Network::IPv6(ip), rem.get(..len).unwrap_or(rem), true)
+        }
+        _ => return Ok(()),
+    };
+    let protocol = network.protocol();
+    decoded.network = Some(network);
+    decoded.payload = rem;
+    if !first_fragment {
+        return Ok(());
+    }
+
+    let (transport, rem) = match protocol {
+        InetProtocol::TCP => {
+            let (tcp, rem) = Tcp::new(rem)?;
+            (Transport::Tcp(tcp), rem)
+        }
+        InetProtocol::UDP => {
+            let (udp, rem) = Udp::new(rem)?;
+            (Transport::Udp(udp), rem)
+        }
+        InetProtocol::ICMP => {
+            let (icmp, rem) = Icmp::new(rem)?;
+            (Transport::Icmp(icmp), rem)
+        }
+        InetProtocol::IPV6_ICMP => {
+            let (icmp, rem) = Icmpv6::new(rem)?;
+            (Transport::Icmpv6(icmp), rem)
+        }
+        _ => return Ok(()),
+    };
+    decoded.transport = Some(transport);
+    decoded.payload = rem;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ethernet_udp_padded() {
+        let frame = [
+            0x02, 0, 0, 0, 0, 0x02, 0x02, 0, 0, 0, 0, 0x01, 0x08, 0x00, // ethernet
+            0x45, 0, 0, 30, 0, 0, 0, 0, 64, 17, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2, // ipv4
+            0x30, 0x39, 0x00, 0x35, 0, 10, 0, 0, // udp
+            b'h', b'i', 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // payload and padding
+        ];
+        let decoded = decode(LinkType::Ethernet, &frame).unwrap();
+        assert!(matches!(decoded.network, Some(Network::IPv4(_))));
+        let Some(Transport::Udp(udp)) = decoded.transport else {
+            panic!("not udp");
+        };
+        assert_eq!(udp.destination(), 53);
+        assert_eq!(decoded.payload, b"hi");
+
+        let mut sll = [0; 16 + 30];
+        sll[14..16].copy_from_slice(&[0x08, 0x00]);
+        sll[16..].copy_from_slice(&frame[14..44]);
+        let decoded = decode(LinkType::LinuxSll, &sll).unwrap();
+        assert!(matches!(decoded.link, Link::Sll(_)));
+        assert!(matches!(decoded.transport, Some(Transport::Udp(_))));
+        assert_eq!(decoded.payload, b"hi");
+
+        let ip = &frame[14..44];
+        let decoded = decode(LinkType::Raw, ip).unwrap();
+        assert!(matches!(decoded.link, Link::Raw(EtherType::IPv4)));
+        assert!(matches!(decoded.transport, Some(Transport::Udp(_))));
+        let null = [&[2, 0, 0, 0][..], ip].concat();
+        let decoded = decode(LinkType::Null, &null).unwrap();
+        assert!(matches!(decoded.network, Some(Network::IPv4(_))));
+        assert_eq!(decoded.payload, b"hi");
+        let decoded = decode(LinkType::Raw, &[0x10, 0]).unwrap();
+        assert!(matches!(decoded.link, Link::Raw(EtherType::Other(1))));
+        assert!(decoded.network.is_none());
+
+        assert!(matches!(
+            decode(LinkType::Ieee80211, &frame),
+            Err(Error::UnsupportedLink(LinkType::Ieee80211))
+        ));
+        assert!(matches!(
+            decode(LinkType::Ethernet, &frame[..40]),
+            Err(Error::Udp(_))
+        ));
+    }
+}
@@ -0,0 +1,580 @@
+//! Wireshark-like field trees of decoded packets: for every header the name,
+//! byte offset in the frame, bit mask, raw bytes and interpreted value of
+//! each field.
+//!
+//! [`dissect`] walks the fields of a [`Decoded`] stack and hands them to a
+//! [`Visitor`]. [`write_text`] renders them as indented text and, with `std`,
+//! [`Tree`] collects them into structures that serialize with the `serde`
+//! feature.
+
+use core::fmt::{self, Display, Write};
+use core::net::{Ipv4Addr, Ipv6Addr};
+
+use crate::decode::{Decoded, Link, Network, Transport};
+use crate::field;
+use crate::link::null::AddressFamily;
+use crate::link::sll::{ArphrdType, PacketType};
+use crate::link::EtherType;
+use crate::network::InetProtocol;
+use crate::transport::icmp::IcmpType;
+use crate::transport::icmpv6::Icmpv6Type;
+
+/// Interpreted value of a field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Value<'a> {
+    Unsigned(u64),
+    Flag(bool),
+    Mac([u8; 6]),
+    Ipv4(Ipv4Addr),
+    Ipv6(Ipv6Addr),
+    Bytes(&'a [u8]),
+    EtherType(EtherType),
+    Protocol(InetProtocol),
+    Icmp(IcmpType),
+    Icmpv6(Icmpv6Type),
+    PacketType(PacketType),
+    ArphrdType(ArphrdType),
+    AddressFamily(AddressFamily),
+}
+
+impl Display for Value<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Unsigned(x) => write!(f, "{}", x),
+            Value::Flag(true) => f.write_str("set"),
+            Value::Flag(false) => f.write_str("not set"),
+            Value::Mac(mac) => {
+                let [a, b, c, d, e, g] = mac;
+                write!(
+                    f,
+                    "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+                    a, b, c, d, e, g
+                )
+            }
+            Value::Ipv4(addr) => write!(f, "{}", addr),
+            Value::Ipv6(addr) => write!(f, "{}", addr),
+            Value::Bytes(bytes) => write_hex(f, bytes),
+            Value::EtherType(x) => write!(f, "{:?} (0x{:04x})", x, u16::from(*x)),
+            Value::Protocol(x) => write!(f, "{:?} ({})", x, u8::from(*x)),
+            Value::Icmp(x) => write!(f, "{:?} ({})", x, u8::from(*x)),
+            Value::Icmpv6(x) => write!(f, "{:?} ({})", x, u8::from(*x)),
+            Value::PacketType(x) => write!(f, "{:?} ({})", x, u16::from(*x)),
+            Value::ArphrdType(x) => write!(f, "{:?} ({})", x, u16::from(*x)),
+            Value::AddressFamily(x) => write!(f, "{:?}", x),
+        }
+    }
+}
+
+/// Numbers and flags serialize as such, everything else as its text form.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Value<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Unsigned(x) => serializer.serialize_u64(*x),
+            Value::Flag(x) => serializer.serialize_bool(*x),
+            x => serializer.collect_str(x),
+        }
+    }
+}
+
+#[cfg(feature = "schema")]
+#[derive(schemars::JsonSchema)]
+#[schemars(untagged)]
+#[allow(dead_code)]
+enum ValueSchema {
+    Unsigned(u64),
+    Flag(bool),
+    Text(String),
+}
+
+#[cfg(feature = "schema")]
+impl schemars::JsonSchema for Value<'_> {
+    fn schema_name() -> String {
+        "Value".into()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        ValueSchema::json_schema(gen)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Field<'a> {
+    pub name: &'static str,
+    /// Offset of the first byte in the frame.
+    pub offset: usize,
+    /// Bytes of the frame holding the field.
+    pub raw: &'a [u8],
+    /// Bits of `raw`, read big endian, that make up the field; `None` if all.
+    pub mask: Option<u32>,
+    pub value: Value<'a>,
+}
+
+/// Receives the layers of a frame in order, each followed by its fields.
+pub trait Visitor<'a> {
+    fn layer(&mut self, name: &'static str, offset: usize, len: usize) -> fmt::Result;
+
+    fn field(&mut self, field: Field<'a>) -> fmt::Result;
+}
+
+/// Fields of one header starting at `base` in the frame.
+struct Header<'v, 'a, V> {
+    visitor: &'v mut V,
+    slice: &'a [u8],
+    base: usize,
+}
+
+impl<'v, 'a, V: Visitor<'a>> Header<'v, 'a, V> {
+    fn new(
+        visitor: &'v mut V,
+        name: &'static str,
+        slice: &'a [u8],
+        base: usize,
+    ) -> Result<Self, fmt::Error> {
+        visitor.layer(name, base, slice.len())?;
+        Ok(Self {
+            visitor,
+            slice,
+            base,
+        })
+    }
+
+    fn field(
+        &mut self,
+        name: &'static str,
+        range: (usize, usize),
+        value: Value<'a>,
+    ) -> fmt::Result {
+        self.masked(name, range, None, value)
+    }
+
+    fn masked(
+        &mut self,
+        name: &'static str,
+        (start, len): (usize, usize),
+        mask: Option<u32>,
+        value: Value<'a>,
+    ) -> fmt::Result {
+        let raw = field::range(self.slice, start, start + len);
+        self.visitor.field(Field {
+            name,
+            offset: self.base + start,
+            raw,
+            mask,
+            value,
+        })
+    }
+
+    fn u8(&mut self, name: &'static str, at: usize) -> fmt::Result {
+        let value = field::read_u8(self.slice, at);
+        self.field(name, (at, 1), Value::Unsigned(value.into()))
+    }
+
+    fn u16(&mut self, name: &'static str, at: usize) -> fmt::Result {
+        let value = field::read_u16(self.slice, at);
+        self.field(name, (at, 2), Value::Unsigned(value.into()))
+    }
+
+    fn u32(&mut self, name: &'static str, at: usize) -> fmt::Result {
+        let value = field::read_u32(self.slice, at);
+        self.field(name, (at, 4), Value::Unsigned(value.into()))
+    }
+
+    /// Bits under `mask` of the `len` bytes at `at`, shifted down.
+    fn bits(&mut self, name: &'static str, (at, len): (usize, usize), mask: u32) -> fmt::Result {
+        let raw = match len {
+            1 => field::read_u8(self.slice, at).into(),
+            2 => field::read_u16(self.slice, at).into(),
+            _ => field::read_u32(self.slice, at),
+        };
+        let value = (raw & mask) >> mask.trailing_zeros();
+        self.masked(name, (at, len), Some(mask), Value::Unsigned(value.into()))
+    }
+
+    fn flag(&mut self, name: &'static str, (at, len): (usize, usize), mask: u32) -> fmt::Result {
+        let raw = match len {
+            1 => field::read_u8(self.slice, at).into(),
+            _ => u32::from(field::read_u16(self.slice, at)),
+        };
+        self.masked(name, (at, len), Some(mask), Value::Flag(raw & mask != 0))
+    }
+}
+
+/// Walks the headers of `decoded` in order, then the payload as a layer of
+/// its own without fields.
+pub fn dissect<'a, V: Visitor<'a>>(decoded: &'a Decoded, visitor: &mut V) -> fmt::Result {
+    let mut offset = dissect_link(&decoded.link, visitor)?;
+    match &decoded.network {
+        Some(Network::IPv4(ip)) => {
+            let mut h = Header::new(visitor, "IPv4", ip.slice(), offset)?;
+            h.bits("version", (0, 1), 0xf0)?;
+            h.bits("ihl", (0, 1), 0x0f)?;
+            h.bits("dscp", (1, 1), 0xfc)?;
+            h.bits("ecn", (1, 1), 0x03)?;
+            h.u16("total length", 2)?;
+            h.u16("identification", 4)?;
+            h.flag("don't fragment", (6, 2), 0x4000)?;
+            h.flag("more fragments", (6, 2), 0x2000)?;
+            h.bits("fragment offset", (6, 2), 0x1fff)?;
+            h.u8("ttl", 8)?;
+            h.field("protocol", (9, 1), Value::Protocol(ip.protocol()))?;
+            h.u16("checksum", 10)?;
+            h.field("source", (12, 4), Value::Ipv4((*ip.source()).into()))?;
+            h.field(
+                "destination",
+                (16, 4),
+                Value::Ipv4((*ip.destination()).into()),
+            )?;
+            if !ip.options().is_empty() {
+                h.field(
+                    "options",
+                    (20, ip.options().len()),
+                    Value::Bytes(ip.options()),
+                )?;
+            }
+            offset += ip.slice().len();
+        }
+        Some(Network::IPv6(ip)) => {
+            let mut h = Header::new(visitor, "IPv6", ip.slice(), offset)?;
+            h.bits("version", (0, 4), 0xf000_0000)?;
+            h.bits("traffic class", (0, 4), 0x0ff0_0000)?;
+            h.bits("flow label", (0, 4), 0x000f_ffff)?;
+            h.u16("payload length", 4)?;
+            h.field("next header", (6, 1), Value::Protocol(ip.next_header()))?;
+            h.u8("hop limit", 7)?;
+            h.field("source", (8, 16), Value::Ipv6((*ip.source()).into()))?;
+            h.field(
+                "destination",
+                (24, 16),
+                Value::Ipv6((*ip.destination()).into()),
+            )?;
+            offset += ip.slice().len();
+        }
+        None => {}
+    }
+    match &decoded.transport {
+        Some(Transport::Tcp(tcp)) => {
+            let mut h = Header::new(visitor, "TCP", tcp.slice(), offset)?;
+            h.u16("source port", 0)?;
+            h.u16("destination port", 2)?;
+            h.u32("sequence number", 4)?;
+            h.u32("acknowledgment number", 8)?;
+            h.bits("data offset", (12, 1), 0xf0)?;
+            for (name, mask) in [
+                ("ns", 0x0100),
+                ("cwr", 0x80),
+                ("ece", 0x40),
+                ("urg", 0x20),
+                ("ack", 0x10),
+                ("psh", 0x08),
+                ("rst", 0x04),
+                ("syn", 0x02),
+                ("fin", 0x01),
+            ] {
+                h.flag(name, (12, 2), mask)?;
+            }
+            h.u16("window", 14)?;
+            h.u16("checksum", 16)?;
+            h.u16("urgent pointer", 18)?;
+            if !tcp.options().is_empty() {
+                h.field(
+                    "options",
+                    (20, tcp.options().len()),
+                    Value::Bytes(tcp.options()),
+                )?;
+            }
+            offset += tcp.slice().len();
+        }
+        Some(Transport::Udp(udp)) => {
+            let mut h = Header::new(visitor, "UDP", udp.slice(), offset)?;
+            h.u16("source port", 0)?;
+            h.u16("destination port", 2)?;
+            h.u16("length", 4)?;
+            h.u16("checksum", 6)?;
+            offset += udp.slice().len();
+        }
+        Some(Transport::Icmp(icmp)) => {
+            let mut h = Header::new(visitor, "ICMP", icmp.slice(), offset)?;
+            h.field("type", (0, 1), Value::Icmp(icmp.icmp_type()))?;
+            h.u8("code", 1)?;
+            h.u16("checksum", 2)?;
+            if icmp.icmp_type().is_query() {
+                h.u16("identifier", 4)?;
+                h.u16("sequence number", 6)?;
+            } else {
+                h.field(
+                    "rest of header",
+                    (4, 4),
+                    Value::Bytes(icmp.rest_of_header()),
+                )?;
+            }
+            offset += icmp.slice().len();
+        }
+        Some(Transport::Icmpv6(icmp)) => {
+            let mut h = Header::new(visitor, "ICMPv6", icmp.slice(), offset)?;
+            h.field("type", (0, 1), Value::Icmpv6(icmp.icmp_type()))?;
+            h.u8("code", 1)?;
+            h.u16("checksum", 2)?;
+            if matches!(
+                icmp.icmp_type(),
+                Icmpv6Type::EchoRequest | Icmpv6Type::EchoReply
+            ) {
+                h.u16("identifier", 4)?;
+                h.u16("sequence number", 6)?;
+            } else {
+                h.field(
+                    "rest of header",
+                    (4, 4),
+                    Value::Bytes(icmp.rest_of_header()),
+                )?;
+            }
+            offset += icmp.slice().len();
+        }
+        None => {}
+    }
+    if !decoded.payload.is_empty() {
+        visitor.layer("Payload", offset, decoded.payload.len())?;
+    }
+    Ok(())
+}
+
+/// Returns the length of the link-layer header.
+fn dissect_link<'a, V: Visitor<'a>>(link: &'a Link, visitor: &mut V) -> Result<usize, fmt::Error> {
+    match link {
+        Link::Ethernet(eth) => {
+            let size = eth.size_usize();
+            let mut h = Header::new(visitor, "Ethernet", eth.slice(), 0)?;
+            h.field("destination", (0, 6), Value::Mac(*eth.destination()))?;
+            h.field("source", (6, 6), Value::Mac(*eth.source()))?;
+            if size > 14 {
+                let tags = field::range(eth.slice(), 12, size - 2);
+                h.field("vlan tags", (12, size - 14), Value::Bytes(tags))?;
+            }
+            h.field("type", (size - 2, 2), Value::EtherType(eth.ethertype()))?;
+            Ok(size)
+        }
+        Link::Sll(sll) => {
+            let mut h = Header::new(visitor, "Linux cooked", sll.slice(), 0)?;
+            h.field("packet type", (0, 2), Value::PacketType(sll.packet_type()))?;
+            h.field("arphrd type", (2, 2), Value::ArphrdType(sll.arphrd_type()))?;
+            h.u16("address length", 4)?;
+            h.field(
+                "address",
+                (6, sll.address().len()),
+                Value::Bytes(sll.address()),
+            )?;
+            h.field("protocol", (14, 2), Value::EtherType(sll.protocol()))?;
+            Ok(sll.slice().len())
+        }
+        Link::Sll2(sll) => {
+            let mut h = Header::new(visitor, "Linux cooked v2", sll.slice(), 0)?;
+            h.field("protocol", (0, 2), Value::EtherType(sll.protocol()))?;
+            h.u32("interface index", 4)?;
+            h.field("arphrd type", (8, 2), Value::ArphrdType(sll.arphrd_type()))?;
+            h.field("packet type", (10, 1), Value::PacketType(sll.packet_type()))?;
+            h.u8("address length", 11)?;
+            h.field(
+                "address",
+                (12, sll.address().len()),
+                Value::Bytes(sll.address()),
+            )?;
+            Ok(sll.slice().len())
+        }
+        Link::Null(null) => {
+            let mut h = Header::new(visitor, "Null/Loopback", null.slice(), 0)?;
+            h.field("family", (0, 4), Value::AddressFamily(null.family()))?;
+            Ok(null.slice().len())
+        }
+        Link::Raw(_) => Ok(0),
+    }
+}
+
+fn write_hex(out: &mut impl Write, bytes: &[u8]) -> fmt::Result {
+    bytes.iter().try_for_each(|b| write!(out, "{:02x}", b))
+}
+
+struct Text<'w, W> {
+    out: &'w mut W,
+}
+
+impl<'a, W: Write> Visitor<'a> for Text<'_, W> {
+    fn layer(&mut self, name: &'static str, offset: usize, len: usize) -> fmt::Result {
+        writeln!(self.out, "{}, {} bytes at {}", name, len, offset)
+    }
+
+    fn field(&mut self, field: Field<'a>) -> fmt::Result {
+        write!(
+            self.out,
+            "    {}: {} [{}: ",
+            field.name, field.value, field.offset
+        )?;
+        write_hex(self.out, field.raw)?;
+        if let Some(mask) = field.mask {
+            write!(
+                self.out,
+                " & {:0width$x}",
+                mask,
+                width = field.raw.len() * 2
+            )?;
+        }
+        writeln!(self.out, "]")
+    }
+}
+
+/// Writes the field tree of `decoded` as text, a line per layer and an
+/// indented line per field with its offset, raw bytes and mask.
+pub fn write_text<W: Write>(out: &mut W, decoded: &Decoded) -> fmt::Result {
+    dissect(decoded, &mut Text { out })
+}
+
+#[cfg(feature = "std")]
+pub use tree::{Layer, Tree};
+
+#[cfg(feature = "std")]
+mod tree {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize))]
+    #[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+    pub struct Layer<'a> {
+        pub name: &'static str,
+        pub offset: usize,
+        pub len: usize,
+        pub fields: Vec<Field<'a>>,
+    }
+
+    /// Collected field tree of a frame.
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize))]
+    #[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+    pub struct Tree<'a> {
+        pub layers: Vec<Layer<'a>>,
+    }
+
+    impl<'a> Tree<'a> {
+        pub fn new(decoded: &'a Decoded) -> Self {
+            let mut tree = Tree::default();
+            // collecting never fails
+            let _ = dissect(decoded, &mut tree);
+            tree
+        }
+    }
+
+    impl<'a> Visitor<'a> for Tree<'a> {
+        fn layer(&mut self, name: &'static str, offset: usize, len: usize) -> fmt::Result {
+            self.layers.push(Layer {
+                name,
+                offset,
+                len,
+                fields: Vec::new(),
+            });
+            Ok(())
+        }
+
+        fn field(&mut self, field: Field<'a>) -> fmt::Result {
+            if let Some(layer) = self.layers.last_mut() {
+                layer.fields.push(field);
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::decode::decode;
+    use crate::pcap::LinkType;
+
+    const FRAME: [u8; 44] = [
+        0x02, 0, 0, 0, 0, 0x02, 0x02, 0, 0, 0, 0, 0x01, 0x08, 0x00, // ethernet
+        0x45, 0, 0, 30, 0, 1, 0x40, 0, 64, 17, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2, // ipv4
+        0x30, 0x39, 0x00, 0x35, 0, 10, 0, 0, // udp
+        b'h', b'i',
+    ];
+
+    #[test]
+    fn text() {
+        let decoded = decode(LinkType::Ethernet, &FRAME).unwrap();
+        let mut text = String::new();
+        write_text(&mut text, &decoded).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "Ethernet, 14 bytes at 0");
+        assert_eq!(
+            lines[1],
+            "    destination: 02:00:00:00:00:02 [0: 020000000002]"
+        );
+        assert_eq!(lines[3], "    type: IPv4 (0x0800) [12: 0800]");
+        assert_eq!(lines[4], "IPv4, 20 bytes at 14");
+        assert_eq!(lines[5], "    version: 4 [14: 45 & f0]");
+        assert_eq!(lines[11], "    don't fragment: set [20: 4000 & 4000]");
+        assert_eq!(lines[15], "    protocol: UDP (17) [23: 11]");
+        assert_eq!(lines[17], "    source: 10.0.0.1 [26: 0a000001]");
+        assert_eq!(lines[19], "UDP, 8 bytes at 34");
+        assert_eq!(lines[21], "    destination port: 53 [36: 0035]");
+        assert_eq!(lines.last(), Some(&"Payload, 2 bytes at 42"));
+    }
+
+    #[test]
+    fn tree() {
+        let decoded = decode(LinkType::Ethernet, &FRAME).unwrap();
+        let tree = Tree::new(&decoded);
+        let names: Vec<_> = tree.layers.iter().map(|l| l.name).collect();
+        assert_eq!(names, ["Ethernet", "IPv4", "UDP", "Payload"]);
+        let ttl = tree.layers[1]
+            .fields
+            .iter()
+            .find(|f| f.name == "ttl")
+            .unwrap();
+        assert_eq!(ttl.offset, 22);
+        assert_eq!(ttl.value, Value::Unsigned(64));
+        assert_eq!(ttl.raw, [64]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn tree_json() {
+        let decoded = decode(LinkType::Ethernet, &FRAME).unwrap();
+        let json = serde_json::to_value(Tree::new(&decoded)).unwrap();
+        let ipv4 = &json["layers"][1];
+        assert_eq!(ipv4["name"], "IPv4");
+        let field = |name: &str| {
+            ipv4["fields"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|f| f["name"] == name)
+                .unwrap()
+                .clone()
+        };
+        assert_eq!(
+            field("version"),
+            serde_json::json!({
+                "name": "version",
+                "offset": 14,
+                "raw": [0x45],
+                "mask": 0xf0,
+                "value": 4,
+            })
+        );
+        assert_eq!(field("ttl")["mask"], serde_json::Value::Null);
+        assert_eq!(field("don't fragment")["value"], true);
+        assert_eq!(field("source")["value"], "10.0.0.1");
+    }
+
+    #[cfg(feature = "schema")]
+    #[test]
+    fn tree_schema() {
+        let schema = serde_json::to_value(schemars::schema_for!(Tree)).unwrap();
+        let types: Vec<_> = schema["definitions"]["Value"]["anyOf"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|variant| variant["type"].as_str().unwrap())
+            .collect();
+        assert_eq!(types, ["integer", "boolean", "string"]);
+    }
+}
@@ -0,0 +1,71 @@
+//! Panic-free access to fixed offsets of a header slice.
+//!
+//! Views check their length once in `new`, so every field they expose is in
+//! range. Going through `get` instead of indexing keeps that guarantee without
+//! leaving bounds-check panics in the generated code, which matters for eBPF
+//! where `core::panicking` cannot be linked. Should a field ever be out of
+//! range, reads yield zero and writes are dropped.
+
+#[inline(always)]
+pub(crate) fn array<const N: usize>(slice: &[u8], offset: usize) -> Option<&[u8; N]> {
+    slice.get(offset..)?.first_chunk::<N>()
+}
+
+#[inline(always)]
+pub(crate) fn array_mut<const N: usize>(slice: &mut [u8], offset: usize) -> Option<&mut [u8; N]> {
+    slice.get_mut(offset..)?.first_chunk_mut::<N>()
+}
+
+#[inline(always)]
+pub(crate) fn read_u8(slice: &[u8], offset: usize) -> u8 {
+    slice.get(offset).copied().unwrap_or(0)
+}
+
+#[inline(always)]
+pub(crate) fn read_u16(slice: &[u8], offset: usize) -> u16 {
+    array::<2>(slice, offset).map_or(0, |b| u16::from_be_bytes(*b))
+}
+
+#[inline(always)]
+pub(crate) fn read_u32(slice: &[u8], offset: usize) -> u32 {
+    array::<4>(slice, offset).map_or(0, |b| u32::from_be_bytes(*b))
+}
+
+#[inline(always)]
+pub(crate) fn write_u8(slice: &mut [u8], offset: usize, value: u8) {
+    if let Some(b) = slice.get_mut(offset) {
+        *b = value;
+    }
+}
+
+#[inline(always)]
+pub(crate) fn write<const N: usize>(slice: &mut [u8], offset: usize, value: &[u8; N]) {
+    if let Some(b) = array_mut::<N>(slice, offset) {
+        *b = *value;
+    }
+}
+
+/// Bytes in `start..end`, or an empty slice when out of range.
+#[inline(always)]
+pub(crate) fn range(slice: &[u8], start: usize, end: usize) -> &[u8] {
+    slice.get(start..end).unwrap_or(&[])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn out_of_range() {
+        let mut slice = [0x12, 0x34, 0x56];
+        assert_eq!(read_u16(&slice, 1), 0x3456);
+        assert_eq!(read_u16(&slice, 2), 0);
+        assert_eq!(read_u32(&slice, 0), 0);
+        assert_eq!(read_u8(&slice, usize::MAX), 0);
+        assert!(range(&slice, 2, 8).is_empty());
+
+        write(&mut slice, 2, &[0xff, 0xff]);
+        write_u8(&mut slice, 3, 0xff);
+        assert_eq!(slice, [0x12, 0x34, 0x56]);
+    }
+}
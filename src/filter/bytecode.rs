@@ -0,0 +1,214 @@
+//! Flat filter programs with plain-data instructions, so they can be stored
+//! in an eBPF map and interpreted there.
+//!
+//! Each instruction is an accept, a reject or a [`Primitive`] test with a
+//! jump target for either outcome. Targets have to lie after the
+//! instruction, which together with `Program::MAX_LEN` bounds the loop in
+//! [`Program::matches`].
+
+use core::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use super::{Dir, Primitive};
+use crate::decode::Decoded;
+use crate::link::EtherType;
+use crate::network::InetProtocol;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Insn {
+    pub op: u8,
+    /// `Insn::SRC`, `Insn::DST` or `Insn::SRC_OR_DST` for nets and ports.
+    pub dir: u8,
+    /// Prefix length for nets, 1 for a VLAN test with identifier.
+    pub arg: u8,
+    pub _pad: u8,
+    /// Next instruction if the test matches.
+    pub jt: u16,
+    /// Next instruction otherwise.
+    pub jf: u16,
+    /// Address, or a big endian number in the first bytes.
+    pub value: [u8; 16],
+}
+
+impl Insn {
+    pub const REJECT: u8 = 0;
+    pub const ACCEPT: u8 = 1;
+    pub const ETHERTYPE: u8 = 2;
+    pub const PROTOCOL: u8 = 3;
+    pub const NET4: u8 = 4;
+    pub const NET6: u8 = 5;
+    pub const PORT: u8 = 6;
+    pub const VLAN: u8 = 7;
+
+    pub const SRC: u8 = 0;
+    pub const DST: u8 = 1;
+    pub const SRC_OR_DST: u8 = 2;
+
+    pub fn accept() -> Self {
+        Self {
+            op: Insn::ACCEPT,
+            ..Default::default()
+        }
+    }
+
+    pub fn reject() -> Self {
+        Self::default()
+    }
+
+    pub fn test(primitive: &Primitive, jt: u16, jf: u16) -> Self {
+        let mut insn = Self {
+            jt,
+            jf,
+            ..Default::default()
+        };
+        let dir = |dir| match dir {
+            Dir::Src => Insn::SRC,
+            Dir::Dst => Insn::DST,
+            Dir::SrcOrDst => Insn::SRC_OR_DST,
+        };
+        match *primitive {
+            Primitive::EtherType(ethertype) => {
+                insn.op = Insn::ETHERTYPE;
+                insn.value[..2].copy_from_slice(&u16::from(ethertype).to_be_bytes());
+            }
+            Primitive::Protocol(protocol) => {
+                insn.op = Insn::PROTOCOL;
+                insn.value[0] = u8::from(protocol);
+            }
+            Primitive::Net(d, IpAddr::V4(net), prefix) => {
+                insn.op = Insn::NET4;
+                insn.dir = dir(d);
+                insn.arg = prefix;
+                insn.value[..4].copy_from_slice(&net.octets());
+            }
+            Primitive::Net(d, IpAddr::V6(net), prefix) => {
+                insn.op = Insn::NET6;
+                insn.dir = dir(d);
+                insn.arg = prefix;
+                insn.value = net.octets();
+            }
+            Primitive::Port(d, port) => {
+                insn.op = Insn::PORT;
+                insn.dir = dir(d);
+                insn.value[..2].copy_from_slice(&port.to_be_bytes());
+            }
+            Primitive::Vlan(id) => {
+                insn.op = Insn::VLAN;
+                insn.arg = id.is_some() as u8;
+                insn.value[..2].copy_from_slice(&id.unwrap_or(0).to_be_bytes());
+            }
+        }
+        insn
+    }
+
+    /// The test of the instruction, `None` for accept, reject and unknown
+    /// opcodes.
+    pub fn primitive(&self) -> Option<Primitive> {
+        let dir = match self.dir {
+            Insn::SRC => Dir::Src,
+            Insn::DST => Dir::Dst,
+            Insn::SRC_OR_DST => Dir::SrcOrDst,
+            _ => return None,
+        };
+        let [a, b, ..] = self.value;
+        let u16 = u16::from_be_bytes([a, b]);
+        let [a, b, c, d, ..] = self.value;
+        Some(match self.op {
+            Insn::ETHERTYPE => Primitive::EtherType(EtherType::from(u16)),
+            Insn::PROTOCOL => Primitive::Protocol(InetProtocol::from(a)),
+            Insn::NET4 => Primitive::Net(dir, Ipv4Addr::new(a, b, c, d).into(), self.arg),
+            Insn::NET6 => Primitive::Net(dir, Ipv6Addr::from(self.value).into(), self.arg),
+            Insn::PORT => Primitive::Port(dir, u16),
+            Insn::VLAN => Primitive::Vlan((self.arg != 0).then_some(u16)),
+            _ => return None,
+        })
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Program {
+    /// Number of used instructions.
+    pub len: u32,
+    pub insns: [Insn; Program::MAX_LEN],
+}
+
+impl Default for Program {
+    fn default() -> Self {
+        Self {
+            len: 0,
+            insns: [Insn::default(); Program::MAX_LEN],
+        }
+    }
+}
+
+impl Program {
+    pub const MAX_LEN: usize = 64;
+
+    /// Runs the program, anything malformed (backward jumps, unknown
+    /// opcodes, running off the end) rejects.
+    pub fn matches(&self, decoded: &Decoded) -> bool {
+        let len = (self.len as usize).min(Program::MAX_LEN);
+        let mut pc = 0;
+        for _ in 0..Program::MAX_LEN {
+            let Some(insn) = self.insns.get(pc).filter(|_| pc < len) else {
+                return false;
+            };
+            match insn.op {
+                Insn::ACCEPT => return true,
+                Insn::REJECT => return false,
+                _ => {}
+            }
+            let Some(primitive) = insn.primitive() else {
+                return false;
+            };
+            // a VLAN test shifts all later ones, taken or not
+            let vlans = self.insns[..pc]
+                .iter()
+                .filter(|insn| insn.op == Insn::VLAN)
+                .count();
+            let next = if primitive.matches_behind(decoded, vlans) {
+                insn.jt
+            } else {
+                insn.jf
+            };
+            if usize::from(next) <= pc {
+                return false;
+            }
+            pc = usize::from(next);
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoding() {
+        for primitive in [
+            Primitive::EtherType(EtherType::IPv6),
+            Primitive::Protocol(InetProtocol::UDP),
+            Primitive::Net(Dir::Src, Ipv4Addr::new(10, 0, 0, 0).into(), 8),
+            Primitive::Net(Dir::SrcOrDst, Ipv6Addr::LOCALHOST.into(), 128),
+            Primitive::Port(Dir::Dst, 443),
+            Primitive::Vlan(Some(100)),
+            Primitive::Vlan(None),
+        ] {
+            assert_eq!(Insn::test(&primitive, 1, 2).primitive(), Some(primitive));
+        }
+        assert_eq!(Insn::accept().primitive(), None);
+
+        let decoded = crate::decode::decode(crate::pcap::LinkType::Raw, &[]).unwrap();
+        let mut program = Program {
+            len: 2,
+            ..Default::default()
+        };
+        program.insns[0] = Insn::test(&Primitive::Vlan(None), 0, 0);
+        program.insns[1] = Insn::accept();
+        assert!(!program.matches(&decoded));
+        program.insns[0] = Insn::test(&Primitive::Vlan(None), 1, 1);
+        assert!(program.matches(&decoded));
+    }
+}
@@ -0,0 +1,752 @@
+//! Classic BPF, the `struct sock_filter` programs attached with
+//! `SO_ATTACH_FILTER`.
+//!
+//! [`compile`] generates code for a filter [`Expr`] the way libpcap does,
+//! including the shift of all later offsets by 4 bytes after each `vlan`.
+//! [`run`] interprets a program against a buffer, to test filters without
+//! a socket.
+
+#[cfg(feature = "std")]
+use super::{Dir, Error, Expr, Primitive};
+#[cfg(feature = "std")]
+use crate::link::EtherType;
+#[cfg(feature = "std")]
+use crate::pcap::LinkType;
+#[cfg(feature = "std")]
+use core::net::IpAddr;
+
+/// `struct sock_filter`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Insn {
+    pub code: u16,
+    pub jt: u8,
+    pub jf: u8,
+    pub k: u32,
+}
+
+impl Insn {
+    pub const fn stmt(code: u16, k: u32) -> Self {
+        Self {
+            code,
+            jt: 0,
+            jf: 0,
+            k,
+        }
+    }
+
+    pub const fn jump(code: u16, k: u32, jt: u8, jf: u8) -> Self {
+        Self { code, jt, jf, k }
+    }
+}
+
+pub const BPF_LD: u16 = 0x00;
+pub const BPF_LDX: u16 = 0x01;
+pub const BPF_ST: u16 = 0x02;
+pub const BPF_STX: u16 = 0x03;
+pub const BPF_ALU: u16 = 0x04;
+pub const BPF_JMP: u16 = 0x05;
+pub const BPF_RET: u16 = 0x06;
+pub const BPF_MISC: u16 = 0x07;
+
+pub const BPF_W: u16 = 0x00;
+pub const BPF_H: u16 = 0x08;
+pub const BPF_B: u16 = 0x10;
+
+pub const BPF_IMM: u16 = 0x00;
+pub const BPF_ABS: u16 = 0x20;
+pub const BPF_IND: u16 = 0x40;
+pub const BPF_MEM: u16 = 0x60;
+pub const BPF_LEN: u16 = 0x80;
+pub const BPF_MSH: u16 = 0xa0;
+
+pub const BPF_ADD: u16 = 0x00;
+pub const BPF_SUB: u16 = 0x10;
+pub const BPF_MUL: u16 = 0x20;
+pub const BPF_DIV: u16 = 0x30;
+pub const BPF_OR: u16 = 0x40;
+pub const BPF_AND: u16 = 0x50;
+pub const BPF_LSH: u16 = 0x60;
+pub const BPF_RSH: u16 = 0x70;
+pub const BPF_NEG: u16 = 0x80;
+pub const BPF_MOD: u16 = 0x90;
+pub const BPF_XOR: u16 = 0xa0;
+
+pub const BPF_JA: u16 = 0x00;
+pub const BPF_JEQ: u16 = 0x10;
+pub const BPF_JGT: u16 = 0x20;
+pub const BPF_JGE: u16 = 0x30;
+pub const BPF_JSET: u16 = 0x40;
+
+pub const BPF_K: u16 = 0x00;
+pub const BPF_X: u16 = 0x08;
+pub const BPF_A: u16 = 0x10;
+
+pub const BPF_TAX: u16 = 0x00;
+pub const BPF_TXA: u16 = 0x80;
+
+/// Scratch memory words, `BPF_MEMWORDS`.
+pub const MEMWORDS: usize = 16;
+/// Kernel limit on the program length, `BPF_MAXINSNS`.
+pub const MAX_INSNS: usize = 4096;
+/// What a matching libpcap program returns, the default snapshot length.
+pub const ACCEPT: u32 = 262_144;
+
+/// Runs `program` on `packet` and returns the number of bytes to accept,
+/// zero for a drop. Like the kernel, out of bounds loads, division by zero,
+/// unknown instructions and running off the end all drop.
+pub fn run(program: &[Insn], packet: &[u8]) -> u32 {
+    let load = |at: u32, size: u16| -> Option<u32> {
+        let at = at as usize;
+        let bytes = packet.get(
+            at..at.checked_add(match size {
+                BPF_W => 4,
+                BPF_H => 2,
+                _ => 1,
+            })?,
+        )?;
+        Some(bytes.iter().fold(0, |acc, b| (acc << 8) | u32::from(*b)))
+    };
+    let (mut a, mut x) = (0u32, 0u32);
+    let mut mem = [0u32; MEMWORDS];
+    let mut pc = 0;
+    while let Some(insn) = program.get(pc) {
+        pc += 1;
+        let k = insn.k;
+        let src = if insn.code & BPF_X != 0 { x } else { k };
+        match insn.code & 0x07 {
+            BPF_LD => {
+                let size = insn.code & 0x18;
+                a = match insn.code & 0xe0 {
+                    BPF_IMM => k,
+                    BPF_ABS => match load(k, size) {
+                        Some(value) => value,
+                        None => return 0,
+                    },
+                    BPF_IND => match x.checked_add(k).and_then(|at| load(at, size)) {
+                        Some(value) => value,
+                        None => return 0,
+                    },
+                    BPF_MEM => match mem.get(k as usize) {
+                        Some(value) => *value,
+                        None => return 0,
+                    },
+                    BPF_LEN => packet.len() as u32,
+                    _ => return 0,
+                }
+            }
+            BPF_LDX => {
+                x = match insn.code & 0xe0 {
+                    BPF_IMM => k,
+                    BPF_MEM => match mem.get(k as usize) {
+                        Some(value) => *value,
+                        None => return 0,
+                    },
+                    BPF_LEN => packet.len() as u32,
+                    BPF_MSH => match load(k, BPF_B) {
+                        Some(value) => (value & 0xf) << 2,
+                        None => return 0,
+                    },
+                    _ => return 0,
+                }
+            }
+            BPF_ST | BPF_STX => {
+                let value = if insn.code & 0x07 == BPF_ST { a } else { x };
+                match mem.get_mut(k as usize) {
+                    Some(slot) => *slot = value,
+                    None => return 0,
+                }
+            }
+            BPF_ALU => {
+                a = match insn.code & 0xf0 {
+                    BPF_ADD => a.wrapping_add(src),
+                    BPF_SUB => a.wrapping_sub(src),
+                    BPF_MUL => a.wrapping_mul(src),
+                    BPF_DIV => match a.checked_div(src) {
+                        Some(value) => value,
+                        None => return 0,
+                    },
+                    BPF_MOD => match a.checked_rem(src) {
+                        Some(value) => value,
+                        None => return 0,
+                    },
+                    BPF_OR => a | src,
+                    BPF_AND => a & src,
+                    BPF_XOR => a ^ src,
+                    BPF_LSH => a.checked_shl(src).unwrap_or(0),
+                    BPF_RSH => a.checked_shr(src).unwrap_or(0),
+                    BPF_NEG => a.wrapping_neg(),
+                    _ => return 0,
+                }
+            }
+            BPF_JMP => {
+                let taken = match insn.code & 0xf0 {
+                    BPF_JA => {
+                        pc = pc.saturating_add(k as usize);
+                        continue;
+                    }
+                    BPF_JEQ => a == src,
+                    BPF_JGT => a > src,
+                    BPF_JGE => a >= src,
+                    BPF_JSET => a & src != 0,
+                    _ => return 0,
+                };
+                pc += usize::from(if taken { insn.jt } else { insn.jf });
+            }
+            BPF_RET => {
+                return match insn.code & 0x18 {
+                    BPF_K => k,
+                    BPF_A => a,
+                    _ => 0,
+                }
+            }
+            _ => match insn.code & 0xf8 {
+                BPF_TAX => x = a,
+                BPF_TXA => a = x,
+                _ => return 0,
+            },
+        }
+    }
+    0
+}
+
+/// Generates a program for packets captured on `link_type`, which has to
+/// be Ethernet, Linux cooked or raw IP.
+#[cfg(feature = "std")]
+pub fn compile(expr: &Expr, link_type: LinkType) -> Result<Vec<Insn>, Error> {
+    let (ethertype_at, network_at) = match link_type {
+        LinkType::Ethernet => (Some(12), 14),
+        LinkType::LinuxSll => (Some(14), 16),
+        LinkType::Raw => (None, 0),
+        x => return Err(Error::UnsupportedLink(x)),
+    };
+    let mut compiler = Compiler {
+        ethernet: link_type == LinkType::Ethernet,
+        ethertype_at,
+        network_at,
+        insns: Vec::new(),
+        labels: Vec::new(),
+    };
+    let accept = compiler.label();
+    let reject = compiler.label();
+    compiler.expr(expr, accept, reject);
+    compiler.place(accept);
+    compiler.stmt(BPF_RET | BPF_K, ACCEPT);
+    compiler.place(reject);
+    compiler.stmt(BPF_RET | BPF_K, 0);
+    compiler.finish()
+}
+
+#[cfg(feature = "std")]
+#[derive(Clone, Copy)]
+enum Target {
+    Next,
+    Label(usize),
+}
+
+#[cfg(feature = "std")]
+struct Compiler {
+    /// Only Ethernet has VLAN tags in the packet.
+    ethernet: bool,
+    /// `None` for raw IP, where the version nibble stands in.
+    ethertype_at: Option<u32>,
+    network_at: u32,
+    insns: Vec<(u16, u32, Target, Target)>,
+    labels: Vec<usize>,
+}
+
+#[cfg(feature = "std")]
+impl Compiler {
+    fn label(&mut self) -> usize {
+        self.labels.push(0);
+        self.labels.len() - 1
+    }
+
+    fn place(&mut self, label: usize) {
+        self.labels[label] = self.insns.len();
+    }
+
+    fn stmt(&mut self, code: u16, k: u32) {
+        self.insns.push((code, k, Target::Next, Target::Next));
+    }
+
+    fn jump(&mut self, code: u16, k: u32, jt: Target, jf: Target) {
+        self.insns.push((BPF_JMP | code | BPF_K, k, jt, jf));
+    }
+
+    fn ja(&mut self, label: usize) {
+        self.insns
+            .push((BPF_JMP | BPF_JA, 0, Target::Label(label), Target::Next));
+    }
+
+    /// Resolves labels to relative forward jumps. Conditional jumps only
+    /// reach 255 instructions, so like libpcap a `BPF_JA` is inserted right
+    /// behind a jump whose target is further away.
+    fn finish(mut self) -> Result<Vec<Insn>, Error> {
+        let offset = |labels: &[usize], pc: usize, target| match target {
+            Target::Next => 0,
+            Target::Label(label) => labels[label] - pc - 1,
+        };
+        let far = |labels: &[usize], pc, target| offset(labels, pc, target) > usize::from(u8::MAX);
+        while self.insns.len() <= MAX_INSNS {
+            let Some((pc, far_jt)) = self.insns.iter().enumerate().find_map(|(pc, insn)| {
+                let &(code, _, jt, jf) = insn;
+                if code == BPF_JMP | BPF_JA {
+                    None
+                } else if far(&self.labels, pc, jt) {
+                    Some((pc, true))
+                } else if far(&self.labels, pc, jf) {
+                    Some((pc, false))
+                } else {
+                    None
+                }
+            }) else {
+                break;
+            };
+            for at in &mut self.labels {
+                if *at > pc {
+                    *at += 1;
+                }
+            }
+            let trampoline = self.label();
+            self.labels[trampoline] = pc + 1;
+            let after = self.label();
+            self.labels[after] = pc + 2;
+            let (_, _, jt, jf) = &mut self.insns[pc];
+            let (long, short) = if far_jt { (jt, jf) } else { (jf, jt) };
+            let target = core::mem::replace(long, Target::Label(trampoline));
+            if let Target::Next = short {
+                *short = Target::Label(after);
+            }
+            self.insns
+                .insert(pc + 1, (BPF_JMP | BPF_JA, 0, target, Target::Next));
+        }
+
+        let len = self.insns.len();
+        if len > MAX_INSNS {
+            return Err(Error::TooLong(len));
+        }
+        Ok(self
+            .insns
+            .iter()
+            .enumerate()
+            .map(|(pc, &(code, k, jt, jf))| {
+                let jt = offset(&self.labels, pc, jt);
+                if code == BPF_JMP | BPF_JA {
+                    return Insn::stmt(code, jt as u32);
+                }
+                let jf = offset(&self.labels, pc, jf);
+                Insn::jump(code, k, jt as u8, jf as u8)
+            })
+            .collect())
+    }
+
+    fn expr(&mut self, expr: &Expr, on_true: usize, on_false: usize) {
+        match expr {
+            Expr::And(a, b) => {
+                let next = self.label();
+                self.expr(a, next, on_false);
+                self.place(next);
+                self.expr(b, on_true, on_false);
+            }
+            Expr::Or(a, b) => {
+                let next = self.label();
+                self.expr(a, on_true, next);
+                self.place(next);
+                self.expr(b, on_true, on_false);
+            }
+            Expr::Not(a) => self.expr(a, on_false, on_true),
+            Expr::Primitive(primitive) => self.primitive(primitive, on_true, on_false),
+        }
+    }
+
+    /// Loads the ethertype, or the IP version nibble for raw IP, into A.
+    fn load_ethertype(&mut self) {
+        match self.ethertype_at {
+            Some(at) => self.stmt(BPF_LD | BPF_H | BPF_ABS, at),
+            None => {
+                self.stmt(BPF_LD | BPF_B | BPF_ABS, 0);
+                self.stmt(BPF_ALU | BPF_AND | BPF_K, 0xf0);
+            }
+        }
+    }
+
+    /// What `load_ethertype` leaves in A for `ethertype`, `None` if it
+    /// cannot occur.
+    fn ethertype_k(&self, ethertype: EtherType) -> Option<u32> {
+        match (self.ethertype_at, ethertype) {
+            (Some(_), x) => Some(u16::from(x).into()),
+            (None, EtherType::IPv4) => Some(0x40),
+            (None, EtherType::IPv6) => Some(0x60),
+            (None, _) => None,
+        }
+    }
+
+    /// Goes on for `ethertype`, to `on_false` for anything else.
+    fn ethertype(&mut self, ethertype: EtherType, on_false: usize) {
+        match self.ethertype_k(ethertype) {
+            Some(k) => {
+                self.load_ethertype();
+                self.jump(BPF_JEQ, k, Target::Next, Target::Label(on_false));
+            }
+            None => self.ja(on_false),
+        }
+    }
+
+    /// Runs `v4` for IPv4 and `v6` for IPv6 packets, with the other
+    /// ethertypes going to `on_false`.
+    fn ip_either(
+        &mut self,
+        on_false: usize,
+        v4: impl FnOnce(&mut Self),
+        v6: impl FnOnce(&mut Self),
+    ) {
+        let ip6 = self.label();
+        self.load_ethertype();
+        let k4 = self.ethertype_k(EtherType::IPv4).unwrap_or_default();
+        let k6 = self.ethertype_k(EtherType::IPv6).unwrap_or_default();
+        self.jump(BPF_JEQ, k4, Target::Next, Target::Label(ip6));
+        v4(self);
+        self.place(ip6);
+        self.jump(BPF_JEQ, k6, Target::Next, Target::Label(on_false));
+        v6(self);
+    }
+
+    /// Emits `test(dst, on_true, on_false)` for the source, the destination
+    /// or first one then the other.
+    fn dir(
+        &mut self,
+        dir: Dir,
+        on_true: usize,
+        on_false: usize,
+        mut test: impl FnMut(&mut Self, bool, usize, usize),
+    ) {
+        match dir {
+            Dir::Src => test(self, false, on_true, on_false),
+            Dir::Dst => test(self, true, on_true, on_false),
+            Dir::SrcOrDst => {
+                let dst = self.label();
+                test(self, false, on_true, dst);
+                self.place(dst);
+                test(self, true, on_true, on_false);
+            }
+        }
+    }
+
+    /// Compares the words at `at` under their masks.
+    fn words(&mut self, at: u32, words: &[(u32, u32)], on_true: usize, on_false: usize) {
+        let words: Vec<_> = (0..)
+            .zip(words)
+            .filter(|(_, (_, mask))| *mask != 0)
+            .collect();
+        let Some(last) = words.last().map(|(i, _)| *i) else {
+            return self.ja(on_true);
+        };
+        for (i, &(value, mask)) in words {
+            self.stmt(BPF_LD | BPF_W | BPF_ABS, at + 4 * i);
+            if mask != u32::MAX {
+                self.stmt(BPF_ALU | BPF_AND | BPF_K, mask);
+            }
+            let jt = if i == last {
+                Target::Label(on_true)
+            } else {
+                Target::Next
+            };
+            self.jump(BPF_JEQ, value & mask, jt, Target::Label(on_false));
+        }
+    }
+
+    /// Goes on for TCP and UDP in the protocol byte at `at`.
+    fn tcp_or_udp(&mut self, at: u32, on_false: usize) {
+        let ok = self.label();
+        self.stmt(BPF_LD | BPF_B | BPF_ABS, at);
+        self.jump(BPF_JEQ, 6, Target::Label(ok), Target::Next);
+        self.jump(BPF_JEQ, 17, Target::Label(ok), Target::Label(on_false));
+        self.place(ok);
+    }
+
+    fn primitive(&mut self, primitive: &Primitive, on_true: usize, on_false: usize) {
+        let n = self.network_at;
+        match *primitive {
+            Primitive::EtherType(ethertype) => {
+                self.ethertype(ethertype, on_false);
+                self.ja(on_true);
+            }
+            Primitive::Protocol(protocol) => {
+                let k = u32::from(u8::from(protocol));
+                let test = |at| {
+                    move |c: &mut Self| {
+                        c.stmt(BPF_LD | BPF_B | BPF_ABS, at);
+                        c.jump(BPF_JEQ, k, Target::Label(on_true), Target::Label(on_false));
+                    }
+                };
+                self.ip_either(on_false, test(n + 9), test(n + 6));
+            }
+            Primitive::Net(dir, IpAddr::V4(net), prefix) => {
+                self.ethertype(EtherType::IPv4, on_false);
+                let mask = u32::MAX.checked_shl(32 - u32::from(prefix.min(32)));
+                let word = [(net.to_bits(), mask.unwrap_or(0))];
+                self.dir(dir, on_true, on_false, |c, dst, t, f| {
+                    c.words(if dst { n + 16 } else { n + 12 }, &word, t, f)
+                });
+            }
+            Primitive::Net(dir, IpAddr::V6(net), prefix) => {
+                self.ethertype(EtherType::IPv6, on_false);
+                let mask = u128::MAX.checked_shl(128 - u32::from(prefix.min(128)));
+                let (net, mask) = (net.to_bits(), mask.unwrap_or(0));
+                let words: Vec<_> = (0..4)
+                    .map(|i| {
+                        let shift = 96 - 32 * i;
+                        ((net >> shift) as u32, (mask >> shift) as u32)
+                    })
+                    .collect();
+                self.dir(dir, on_true, on_false, |c, dst, t, f| {
+                    c.words(if dst { n + 24 } else { n + 8 }, &words, t, f)
+                });
+            }
+            Primitive::Port(dir, port) => {
+                let port = u32::from(port);
+                let test = move |at: u32, indirect: bool| {
+                    move |c: &mut Self, dst: bool, t: usize, f: usize| {
+                        let mode = if indirect { BPF_IND } else { BPF_ABS };
+                        c.stmt(BPF_LD | BPF_H | mode, at + if dst { 2 } else { 0 });
+                        c.jump(BPF_JEQ, port, Target::Label(t), Target::Label(f));
+                    }
+                };
+                self.ip_either(
+                    on_false,
+                    |c| {
+                        c.tcp_or_udp(n + 9, on_false);
+                        // ports are only in the first fragment
+                        c.stmt(BPF_LD | BPF_H | BPF_ABS, n + 6);
+                        c.jump(BPF_JSET, 0x1fff, Target::Label(on_false), Target::Next);
+                        c.stmt(BPF_LDX | BPF_B | BPF_MSH, n);
+                        c.dir(dir, on_true, on_false, test(n, true));
+                    },
+                    |c| {
+                        c.tcp_or_udp(n + 6, on_false);
+                        c.dir(dir, on_true, on_false, test(n + 40, false));
+                    },
+                );
+            }
+            Primitive::Vlan(id) => {
+                let Some(at) = self.ethertype_at.filter(|_| self.ethernet) else {
+                    return self.ja(on_false);
+                };
+                let tagged = self.label();
+                self.stmt(BPF_LD | BPF_H | BPF_ABS, at);
+                for tpid in [0x8100, 0x88a8] {
+                    self.jump(BPF_JEQ, tpid, Target::Label(tagged), Target::Next);
+                }
+                self.jump(
+                    BPF_JEQ,
+                    0x9100,
+                    Target::Label(tagged),
+                    Target::Label(on_false),
+                );
+                self.place(tagged);
+                match id {
+                    Some(id) => {
+                        self.stmt(BPF_LD | BPF_H | BPF_ABS, at + 2);
+                        self.stmt(BPF_ALU | BPF_AND | BPF_K, 0x0fff);
+                        self.jump(
+                            BPF_JEQ,
+                            id.into(),
+                            Target::Label(on_true),
+                            Target::Label(on_false),
+                        );
+                    }
+                    None => self.ja(on_true),
+                }
+                // like libpcap, everything after `vlan` looks behind the tag
+                self.ethertype_at = Some(at + 4);
+                self.network_at += 4;
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::decode::decode;
+    use crate::filter::parse;
+
+    fn frame(ip: &[u8]) -> Vec<u8> {
+        let ethertype: &[u8] = if ip[0] >> 4 == 6 {
+            &[0x86, 0xdd]
+        } else {
+            &[0x08, 0x00]
+        };
+        [&[2, 0, 0, 0, 0, 2, 2, 0, 0, 0, 0, 1][..], ethertype, ip].concat()
+    }
+
+    #[test]
+    fn same_as_decoded() {
+        let tcp = [
+            0x45, 0, 0, 40, 0, 0, 0x40, 0, 64, 6, 0, 0, 192, 168, 1, 7, 10, 1, 2, 3, //
+            0xc9, 0x3a, 0x01, 0xbb, 0, 0, 0, 1, 0, 0, 0, 0, 0x50, 0x02, 0xff, 0xff, 0, 0, 0, 0,
+        ];
+        let mut fragment = tcp;
+        fragment[7] = 0x10;
+        let mut udp6 = vec![0x60, 0, 0, 0, 0, 8, 17, 64];
+        udp6.extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        udp6.extend_from_slice(&[0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+        udp6.extend_from_slice(&[0x30, 0x39, 0, 53, 0, 8, 0, 0]);
+        let packets = [&tcp[..], &fragment, &udp6];
+
+        for filter in [
+            "tcp and dst port 443 and not net 10.0.0.0/8",
+            "tcp port 443",
+            "src port 51514",
+            "udp dst port 53",
+            "ip6 and src net 2001:db8::/32",
+            "dst host fe80::2 or host 10.1.2.3",
+            "net 0.0.0.0/0",
+            "proto 17 || arp",
+            "not ip",
+            "icmp6",
+        ] {
+            let expr = parse(filter).unwrap();
+            let program = compile(&expr, LinkType::Ethernet).unwrap();
+            let raw = compile(&expr, LinkType::Raw).unwrap();
+            for packet in packets {
+                let frame = frame(packet);
+                let decoded = decode(LinkType::Ethernet, &frame).unwrap();
+                let expected = expr.matches(&decoded);
+                assert_eq!(run(&program, &frame) != 0, expected, "{}", filter);
+                assert_eq!(run(&raw, packet) != 0, expected, "{} raw", filter);
+            }
+        }
+
+        let tag = |tpid: u16, tci: u16, frame: &[u8]| {
+            [
+                &frame[..12],
+                &tpid.to_be_bytes(),
+                &tci.to_be_bytes(),
+                &frame[12..],
+            ]
+            .concat()
+        };
+        let mut frames = Vec::new();
+        for packet in packets {
+            let frame = frame(packet);
+            frames.push(tag(0x8100, 100, &frame));
+            frames.push(tag(0x88a8, 200, &tag(0x8100, 100, &frame)));
+            frames.push(tag(0x9100, 100, &frame));
+            frames.push(frame);
+        }
+        for filter in [
+            "vlan 100 and tcp port 443",
+            "vlan and ip",
+            "vlan 100 and host 10.1.2.3",
+            "vlan 200 and vlan 100 and udp",
+            "vlan and not (tcp or udp)",
+            "not vlan and ip6",
+            "vlan or ip",
+            "ip or vlan 100",
+        ] {
+            let expr = parse(filter).unwrap();
+            let program = compile(&expr, LinkType::Ethernet).unwrap();
+            let bytecode = expr.compile().unwrap();
+            for frame in &frames {
+                let decoded = decode(LinkType::Ethernet, frame).unwrap();
+                let expected = expr.matches(&decoded);
+                assert_eq!(
+                    run(&program, frame) != 0,
+                    expected,
+                    "{} {:x?}",
+                    filter,
+                    frame
+                );
+                assert_eq!(bytecode.matches(&decoded), expected, "{}", filter);
+            }
+        }
+    }
+
+    #[test]
+    fn vlan_shifts_offsets() {
+        let mut frame = vec![2, 0, 0, 0, 0, 2, 2, 0, 0, 0, 0, 1, 0x81, 0x00, 0x00, 100];
+        frame.extend_from_slice(&[0x08, 0x00, 0x45, 0, 0, 20, 0, 0, 0, 0, 64, 1, 0, 0]);
+        frame.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+        for (filter, expected) in [
+            ("vlan 100 and icmp and host 10.0.0.2", true),
+            ("vlan 101", false),
+            ("vlan and ip", true),
+            ("ip", false),
+        ] {
+            let expr = parse(filter).unwrap();
+            let program = compile(&expr, LinkType::Ethernet).unwrap();
+            assert_eq!(
+                run(&program, &frame),
+                expected as u32 * ACCEPT,
+                "{}",
+                filter
+            );
+            let decoded = decode(LinkType::Ethernet, &frame).unwrap();
+            assert_eq!(expr.matches(&decoded), expected, "{}", filter);
+            assert_eq!(expr.compile().unwrap().matches(&decoded), expected);
+        }
+        let expr = parse("vlan").unwrap();
+        assert!(matches!(
+            compile(&expr, LinkType::Null),
+            Err(Error::UnsupportedLink(LinkType::Null))
+        ));
+    }
+
+    #[test]
+    fn long_jumps() {
+        let mut udp6 = vec![0x60, 0, 0, 0, 0, 8, 17, 64];
+        udp6.extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        udp6.extend_from_slice(&[0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+        udp6.extend_from_slice(&[0x30, 0x39, 0, 53, 0, 8, 0, 0]);
+        let frame = frame(&udp6);
+        let decoded = decode(LinkType::Ethernet, &frame).unwrap();
+
+        let hosts = |n: usize| {
+            (2..n + 2)
+                .map(|i| format!("host 2001:db8::{:x}", i))
+                .collect::<Vec<_>>()
+                .join(" or ")
+        };
+        for filter in [
+            hosts(15),
+            format!("{} or host 2001:db8::1", hosts(40)),
+            format!("host 2001:db8::1 and not ({})", hosts(40)),
+            format!("({}) and udp", hosts(100)),
+        ] {
+            let expr = parse(&filter).unwrap();
+            let program = compile(&expr, LinkType::Ethernet).unwrap();
+            assert!(program.len() > 256);
+            assert_eq!(
+                run(&program, &frame) != 0,
+                expr.matches(&decoded),
+                "{}",
+                filter
+            );
+        }
+
+        let expr = parse(&hosts(300)).unwrap();
+        assert!(matches!(
+            compile(&expr, LinkType::Ethernet),
+            Err(Error::TooLong(len)) if len > MAX_INSNS
+        ));
+    }
+
+    #[test]
+    fn interpreter() {
+        let program = [
+            Insn::stmt(BPF_LD | BPF_LEN, 0),
+            Insn::stmt(BPF_ST, 3),
+            Insn::stmt(BPF_LDX | BPF_MEM, 3),
+            Insn::stmt(BPF_ALU | BPF_MUL | BPF_X, 0),
+            Insn::jump(BPF_JMP | BPF_JGT | BPF_K, 10, 1, 0),
+            Insn::stmt(BPF_RET | BPF_K, 1),
+            Insn::stmt(BPF_RET | BPF_A, 0),
+        ];
+        assert_eq!(run(&program, &[0; 4]), 16);
+        assert_eq!(run(&program, &[0; 3]), 1);
+        assert_eq!(run(&[Insn::stmt(BPF_LD | BPF_W | BPF_ABS, 2)], &[0; 4]), 0);
+        assert_eq!(run(&[Insn::stmt(BPF_ALU | BPF_DIV | BPF_K, 0)], &[]), 0);
+    }
+}
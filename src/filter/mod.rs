@@ -0,0 +1,363 @@
+//! A small pcap-filter style language, e.g.
+//! `tcp and dst port 443 and not net 10.0.0.0/8`, evaluated against
+//! [`Decoded`] layers.
+//!
+//! Filters are parsed (with `std`) into an [`Expr`] tree of [`Primitive`]
+//! tests. A tree can be flattened into a [`bytecode::Program`], a fixed size
+//! and forward-jumping program meant to sit in an eBPF map, so that a loaded
+//! program can change its filter without being reloaded, or into classic BPF
+//! with [`cbpf::compile`].
+//!
+//! Supported primitives:
+//! - `ip`, `ip6`, `arp`, `ether proto <number>`
+//! - `tcp`, `udp`, `icmp`, `icmp6`, `proto <name or number>`
+//! - `[src|dst] host <address>`, `[src|dst] net <address>/<prefix>`
+//! - `[src|dst] port <number>`, also as `tcp port 80` or `udp dst port 53`
+//! - `vlan [<id>]`
+//!
+//! combined with `and`/`&&`, `or`/`||`, `not`/`!` and parentheses.
+//!
+//! As in libpcap, every `vlan` moves the primitives written after it behind
+//! one more 802.1Q/802.1ad tag of an Ethernet frame, whether or not the tag
+//! matched, so `vlan 100 and tcp` looks for TCP inside VLAN 100.
+
+pub mod bytecode;
+pub mod cbpf;
+#[cfg(feature = "std")]
+mod parse;
+
+use core::net::IpAddr;
+
+use crate::decode::{self, Decoded, Link, Network, Transport};
+use crate::link::EtherType;
+use crate::network::InetProtocol;
+
+#[cfg(feature = "std")]
+pub use parse::parse;
+
+/// Which address or port of a packet a primitive looks at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Dir {
+    Src,
+    Dst,
+    SrcOrDst,
+}
+
+/// A single test, `host` is a `Net` with a full-length prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Primitive {
+    EtherType(EtherType),
+    Protocol(InetProtocol),
+    Net(Dir, IpAddr, u8),
+    /// TCP or UDP port.
+    Port(Dir, u16),
+    /// 802.1Q/802.1ad tagged, with the outer VLAN identifier if given.
+    Vlan(Option<u16>),
+}
+
+impl Primitive {
+    /// Tests the outermost layers of the packet.
+    pub fn matches(&self, decoded: &Decoded) -> bool {
+        self.matches_behind(decoded, 0)
+    }
+
+    /// Tests an Ethernet frame `vlans` tags further in, where libpcap looks
+    /// for a primitive that follows `vlans` `vlan`s. Other links have no tags.
+    fn matches_behind(&self, decoded: &Decoded, vlans: usize) -> bool {
+        let Link::Ethernet(eth) = &decoded.link else {
+            return self.test(decoded.link.ethertype(), None, decoded);
+        };
+        // The bytes after the header view are only at hand when nothing was
+        // decoded from them.
+        let head = eth.slice();
+        let tail = |offset: usize| match offset.checked_sub(head.len()) {
+            None => head.get(offset..),
+            Some(skip) if decoded.network.is_none() => decoded.payload.get(skip..),
+            Some(_) => None,
+        };
+        let u16_at = |offset| match tail(offset)? {
+            [a, b, ..] => Some(u16::from_be_bytes([*a, *b])),
+            _ => None,
+        };
+
+        let at = 12 + 4 * vlans;
+        let Some(ethertype) = u16_at(at).map(EtherType::from) else {
+            return false;
+        };
+        let tagged = matches!(
+            ethertype,
+            EtherType::VlanTaggedFrame
+                | EtherType::ProviderBridging
+                | EtherType::VlanDoubleTaggedFrame
+        );
+        let tci = u16_at(at + 2).filter(|_| tagged);
+        if at + 2 == head.len() {
+            // the ethertype the view stopped at, `decoded` has what follows
+            return self.test(ethertype, tci, decoded);
+        }
+
+        let mut inner = Decoded {
+            link: Link::Raw(ethertype),
+            network: None,
+            transport: None,
+            payload: &[],
+        };
+        if let Some(data) = tail(at + 2).filter(|_| at + 2 >= head.len()) {
+            // a malformed header leaves the layers that did decode
+            let _ = decode::decode_network(&mut inner, ethertype, data);
+        }
+        self.test(ethertype, tci, &inner)
+    }
+
+    /// `ethertype` and the tag control information `tci` of a VLAN tag are
+    /// what the link layer looks like at this point, `decoded` has the layers
+    /// that follow them.
+    fn test(&self, ethertype: EtherType, tci: Option<u16>, decoded: &Decoded) -> bool {
+        match *self {
+            Primitive::EtherType(x) => ethertype == x,
+            Primitive::Protocol(protocol) => decoded
+                .network
+                .as_ref()
+                .is_some_and(|network| network.protocol() == protocol),
+            Primitive::Net(dir, net, prefix) => match (&decoded.network, net) {
+                (Some(Network::IPv4(ip)), IpAddr::V4(net)) => {
+                    let mask = u32::MAX.checked_shl(32 - u32::from(prefix.min(32)));
+                    let mask = mask.unwrap_or(0);
+                    let net = net.to_bits() & mask;
+                    dir.any(ip.source_u32(), ip.destination_u32(), |x| x & mask == net)
+                }
+                (Some(Network::IPv6(ip)), IpAddr::V6(net)) => {
+                    let mask = u128::MAX.checked_shl(128 - u32::from(prefix.min(128)));
+                    let mask = mask.unwrap_or(0);
+                    let net = net.to_bits() & mask;
+                    dir.any(ip.source_u128(), ip.destination_u128(), |x| x & mask == net)
+                }
+                _ => false,
+            },
+            Primitive::Port(dir, port) => match &decoded.transport {
+                Some(Transport::Tcp(tcp)) => {
+                    dir.any(tcp.source(), tcp.destination(), |x| x == port)
+                }
+                Some(Transport::Udp(udp)) => {
+                    dir.any(udp.source(), udp.destination(), |x| x == port)
+                }
+                _ => false,
+            },
+            Primitive::Vlan(id) => tci.is_some_and(|tci| id.is_none_or(|id| tci & 0x0fff == id)),
+        }
+    }
+}
+
+impl Dir {
+    fn any<T>(self, source: T, destination: T, f: impl Fn(T) -> bool) -> bool {
+        match self {
+            Dir::Src => f(source),
+            Dir::Dst => f(destination),
+            Dir::SrcOrDst => f(source) || f(destination),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+pub use expr::{Error, Expr};
+
+#[cfg(feature = "std")]
+mod expr {
+    use super::bytecode::{Insn, Program};
+    use super::*;
+    use crate::pcap::LinkType;
+
+    #[derive(Debug)]
+    pub enum Error {
+        UnexpectedEnd,
+        UnexpectedToken(String),
+        InvalidNumber(String),
+        InvalidAddress(String),
+        InvalidPrefix(String),
+        /// The compiled program needs more than `Program::MAX_LEN`, or for
+        /// classic BPF `cbpf::MAX_INSNS`, instructions.
+        TooLong(usize),
+        /// Classic BPF is only generated for Ethernet, Linux cooked and raw IP.
+        UnsupportedLink(LinkType),
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub enum Expr {
+        And(Box<Expr>, Box<Expr>),
+        Or(Box<Expr>, Box<Expr>),
+        Not(Box<Expr>),
+        Primitive(Primitive),
+    }
+
+    impl Expr {
+        pub fn matches(&self, decoded: &Decoded) -> bool {
+            self.eval(decoded, 0).0
+        }
+
+        /// The result and the number of `vlan`s in the expression, which
+        /// shift everything after them whether they are evaluated or not.
+        fn eval(&self, decoded: &Decoded, vlans: usize) -> (bool, usize) {
+            match self {
+                Expr::And(a, b) => {
+                    let (a, n) = a.eval(decoded, vlans);
+                    let (b, m) = b.eval(decoded, vlans + n);
+                    (a && b, n + m)
+                }
+                Expr::Or(a, b) => {
+                    let (a, n) = a.eval(decoded, vlans);
+                    let (b, m) = b.eval(decoded, vlans + n);
+                    (a || b, n + m)
+                }
+                Expr::Not(a) => {
+                    let (a, n) = a.eval(decoded, vlans);
+                    (!a, n)
+                }
+                Expr::Primitive(primitive) => (
+                    primitive.matches_behind(decoded, vlans),
+                    matches!(primitive, Primitive::Vlan(_)) as usize,
+                ),
+            }
+        }
+
+        /// Flattens the tree into short-circuiting tests that only jump
+        /// forward, followed by an accept and a reject instruction.
+        pub fn compile(&self) -> Result<Program, Error> {
+            let mut compiler = Compiler::default();
+            compiler.expr(self, Target::Accept, Target::Reject);
+            let accept = compiler.insns.len();
+            let resolve = |target| match target {
+                Target::Accept => accept,
+                Target::Reject => accept + 1,
+                Target::Label(label) => compiler.labels[label],
+            };
+
+            let len = accept + 2;
+            if len > Program::MAX_LEN {
+                return Err(Error::TooLong(len));
+            }
+            let mut program = Program::default();
+            for (i, (primitive, jt, jf)) in compiler.insns.iter().enumerate() {
+                program.insns[i] = Insn::test(primitive, resolve(*jt) as u16, resolve(*jf) as u16);
+            }
+            program.insns[accept] = Insn::accept();
+            program.insns[accept + 1] = Insn::reject();
+            program.len = len as u32;
+            Ok(program)
+        }
+    }
+
+    #[derive(Clone, Copy)]
+    enum Target {
+        Accept,
+        Reject,
+        Label(usize),
+    }
+
+    #[derive(Default)]
+    struct Compiler {
+        insns: Vec<(Primitive, Target, Target)>,
+        /// Instruction index of each label.
+        labels: Vec<usize>,
+    }
+
+    impl Compiler {
+        fn expr(&mut self, expr: &Expr, on_true: Target, on_false: Target) {
+            match expr {
+                Expr::And(a, b) => {
+                    let next = self.label();
+                    self.expr(a, Target::Label(next), on_false);
+                    self.labels[next] = self.insns.len();
+                    self.expr(b, on_true, on_false);
+                }
+                Expr::Or(a, b) => {
+                    let next = self.label();
+                    self.expr(a, on_true, Target::Label(next));
+                    self.labels[next] = self.insns.len();
+                    self.expr(b, on_true, on_false);
+                }
+                Expr::Not(a) => self.expr(a, on_false, on_true),
+                Expr::Primitive(primitive) => self.insns.push((*primitive, on_true, on_false)),
+            }
+        }
+
+        fn label(&mut self) -> usize {
+            self.labels.push(0);
+            self.labels.len() - 1
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::decode::decode;
+    use crate::pcap::LinkType;
+
+    fn tcp_frame(source_port: u16, destination_port: u16) -> Vec<u8> {
+        let mut frame = vec![
+            0x02, 0, 0, 0, 0, 0x02, 0x02, 0, 0, 0, 0, 0x01, 0x08, 0x00, // ethernet
+            0x45, 0, 0, 40, 0, 0, 0x40, 0, 64, 6, 0, 0, 192, 168, 1, 7, 10, 1, 2, 3, // ipv4
+        ];
+        frame.extend_from_slice(&source_port.to_be_bytes());
+        frame.extend_from_slice(&destination_port.to_be_bytes());
+        frame.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 0, 0x50, 0x02, 0xff, 0xff, 0, 0, 0, 0]);
+        frame
+    }
+
+    #[test]
+    fn parse_and_match() {
+        let frame = tcp_frame(51514, 443);
+        let decoded = decode(LinkType::Ethernet, &frame).unwrap();
+        for (filter, expected) in [
+            ("tcp and dst port 443 and not net 10.0.0.0/8", false),
+            ("tcp and dst port 443 and not net 172.16.0.0/12", true),
+            ("tcp dst port 443", true),
+            ("tcp src port 443", false),
+            ("udp or (ip and src host 192.168.1.7)", true),
+            ("ip6 || !tcp", false),
+            ("proto 6 && port 51514", true),
+            ("dst net 10.1.0.0/16 and ether proto 0x800", true),
+            ("vlan", false),
+            ("icmp or arp", false),
+            ("tcp or udp and port 53", false),
+            ("udp and port 53 or tcp", true),
+            ("ip6 and udp or tcp and port 443", true),
+        ] {
+            let expr = parse(filter).unwrap();
+            assert_eq!(expr.matches(&decoded), expected, "{}", filter);
+            let program = expr.compile().unwrap();
+            assert_eq!(program.matches(&decoded), expected, "{}", filter);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(parse("tcp and"), Err(Error::UnexpectedEnd)));
+        assert!(matches!(parse("port 99999"), Err(Error::InvalidNumber(_))));
+        assert!(matches!(
+            parse("net 10.0.0.0/33"),
+            Err(Error::InvalidPrefix(_))
+        ));
+        assert!(matches!(
+            parse("host 10.0.0"),
+            Err(Error::InvalidAddress(_))
+        ));
+        assert!(matches!(parse("(tcp"), Err(Error::UnexpectedEnd)));
+        assert!(matches!(parse("tcp udp"), Err(Error::UnexpectedToken(_))));
+
+        let primitive = |filter| parse(filter).map(Box::new).unwrap();
+        assert_eq!(
+            parse("tcp or udp and port 53").unwrap(),
+            Expr::And(
+                Box::new(Expr::Or(primitive("tcp"), primitive("udp"))),
+                primitive("port 53")
+            )
+        );
+
+        let long = vec!["port 1"; 63].join(" or ");
+        assert!(matches!(
+            parse(&long).unwrap().compile(),
+            Err(Error::TooLong(65))
+        ));
+    }
+}
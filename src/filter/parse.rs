@@ -0,0 +1,213 @@
+use core::net::IpAddr;
+
+use super::{Dir, Error, Expr, Primitive};
+use crate::link::EtherType;
+use crate::network::InetProtocol;
+
+/// Parses a filter expression, `and` and `or` are evaluated left to right
+/// with the same precedence.
+pub fn parse(filter: &str) -> Result<Expr, Error> {
+    let mut parser = Parser {
+        tokens: tokenize(filter),
+        pos: 0,
+    };
+    let expr = parser.expr()?;
+    match parser.next() {
+        None => Ok(expr),
+        Some(token) => Err(Error::UnexpectedToken(token.into())),
+    }
+}
+
+fn tokenize(filter: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut rest = filter.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let len = match c {
+            '(' | ')' | '!' => 1,
+            '&' | '|' if rest[1..].starts_with(c) => 2,
+            _ => rest
+                .find(|c: char| c.is_whitespace() || "()!".contains(c))
+                .unwrap_or(rest.len())
+                .max(1),
+        };
+        tokens.push(&rest[..len]);
+        rest = rest[len..].trim_start();
+    }
+    tokens
+}
+
+struct Parser<'a> {
+    tokens: Vec<&'a str>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        let token = self.peek()?;
+        self.pos += 1;
+        Some(token)
+    }
+
+    fn expect_next(&mut self) -> Result<&'a str, Error> {
+        self.next().ok_or(Error::UnexpectedEnd)
+    }
+
+    /// `and` and `or` chains, which like in pcap-filter have the same
+    /// precedence and group from the left.
+    fn expr(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.not()?;
+        loop {
+            let and = match self.peek() {
+                Some("and" | "&&") => true,
+                Some("or" | "||") => false,
+                _ => return Ok(expr),
+            };
+            self.next();
+            let (left, right) = (Box::new(expr), Box::new(self.not()?));
+            expr = if and {
+                Expr::And(left, right)
+            } else {
+                Expr::Or(left, right)
+            };
+        }
+    }
+
+    fn not(&mut self) -> Result<Expr, Error> {
+        if matches!(self.peek(), Some("not" | "!")) {
+            self.next();
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, Error> {
+        let primitive = match self.peek().ok_or(Error::UnexpectedEnd)? {
+            "(" => {
+                self.next();
+                let expr = self.expr()?;
+                return match self.expect_next()? {
+                    ")" => Ok(expr),
+                    token => Err(Error::UnexpectedToken(token.into())),
+                };
+            }
+            "src" | "dst" | "host" | "net" | "port" => return self.qualified(),
+            "tcp" | "udp" => {
+                let protocol = Expr::Primitive(self.protocol()?);
+                if matches!(self.peek(), Some("src" | "dst" | "port")) {
+                    let port = self.qualified()?;
+                    return Ok(Expr::And(Box::new(protocol), Box::new(port)));
+                }
+                return Ok(protocol);
+            }
+            "icmp" | "icmp6" => return Ok(Expr::Primitive(self.protocol()?)),
+            "proto" => {
+                self.next();
+                return Ok(Expr::Primitive(self.protocol()?));
+            }
+            "ip" => Primitive::EtherType(EtherType::IPv4),
+            "ip6" => Primitive::EtherType(EtherType::IPv6),
+            "arp" => Primitive::EtherType(EtherType::Arp),
+            "ether" => {
+                self.next();
+                match self.expect_next()? {
+                    "proto" => {}
+                    token => return Err(Error::UnexpectedToken(token.into())),
+                }
+                let ethertype = number::<u16>(self.peek().ok_or(Error::UnexpectedEnd)?)?;
+                Primitive::EtherType(EtherType::from(ethertype))
+            }
+            "vlan" => {
+                let id = self
+                    .tokens
+                    .get(self.pos + 1)
+                    .and_then(|t| number::<u16>(t).ok());
+                if id.is_some() {
+                    self.next();
+                }
+                Primitive::Vlan(id)
+            }
+            token => return Err(Error::UnexpectedToken(token.into())),
+        };
+        self.next();
+        Ok(Expr::Primitive(primitive))
+    }
+
+    /// Protocol name or number, consumed.
+    fn protocol(&mut self) -> Result<Primitive, Error> {
+        let protocol = match self.expect_next()? {
+            "tcp" => InetProtocol::TCP,
+            "udp" => InetProtocol::UDP,
+            "icmp" => InetProtocol::ICMP,
+            "icmp6" => InetProtocol::IPV6_ICMP,
+            token => InetProtocol::from(number::<u8>(token)?),
+        };
+        Ok(Primitive::Protocol(protocol))
+    }
+
+    /// `[src|dst] host|net|port <value>`
+    fn qualified(&mut self) -> Result<Expr, Error> {
+        let dir = match self.peek() {
+            Some("src") => Dir::Src,
+            Some("dst") => Dir::Dst,
+            _ => Dir::SrcOrDst,
+        };
+        if dir != Dir::SrcOrDst {
+            self.next();
+        }
+        let primitive = match self.expect_next()? {
+            "host" => {
+                let token = self.expect_next()?;
+                let addr = address(token)?;
+                Primitive::Net(dir, addr, full_prefix(addr))
+            }
+            "net" => {
+                let token = self.expect_next()?;
+                let (addr, prefix) = match token.split_once('/') {
+                    Some((addr, prefix)) => (address(addr)?, Some(prefix)),
+                    None => (address(token)?, None),
+                };
+                let prefix = match prefix {
+                    Some(prefix) => prefix
+                        .parse::<u8>()
+                        .ok()
+                        .filter(|p| *p <= full_prefix(addr))
+                        .ok_or_else(|| Error::InvalidPrefix(token.into()))?,
+                    None => full_prefix(addr),
+                };
+                Primitive::Net(dir, addr, prefix)
+            }
+            "port" => Primitive::Port(dir, number(self.expect_next()?)?),
+            token => return Err(Error::UnexpectedToken(token.into())),
+        };
+        Ok(Expr::Primitive(primitive))
+    }
+}
+
+fn address(token: &str) -> Result<IpAddr, Error> {
+    token
+        .parse()
+        .map_err(|_| Error::InvalidAddress(token.into()))
+}
+
+fn full_prefix(addr: IpAddr) -> u8 {
+    match addr {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+/// Decimal or `0x` prefixed hexadecimal number.
+fn number<T: TryFrom<u32>>(token: &str) -> Result<T, Error> {
+    let value = match token.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => token.parse(),
+    };
+    value
+        .ok()
+        .and_then(|value| T::try_from(value).ok())
+        .ok_or_else(|| Error::InvalidNumber(token.into()))
+}
@@ -0,0 +1,309 @@
+//! Five-tuple flow keys for connection tables and load balancing.
+//!
+//! `FlowKey` is `#[repr(C)]` without padding so it can be used as is as the
+//! key of an eBPF hash map. IPv4 addresses are stored IPv4-mapped
+//! (`::ffff:a.b.c.d`) so both families share one layout.
+
+use crate::hash;
+use crate::link::{eth, EtherType, Ethernet};
+use crate::network::{ipv4, ipv6, IPv4, IPv6, InetProtocol};
+use crate::transport::tcp::Tcp;
+use crate::transport::udp::Udp;
+
+#[derive(Debug)]
+pub enum Error {
+    Ethernet(eth::Error),
+    IPv4(ipv4::Error),
+    IPv6(ipv6::Error),
+    Unsupported(EtherType),
+}
+
+impl From<eth::Error> for Error {
+    fn from(value: eth::Error) -> Self {
+        Error::Ethernet(value)
+    }
+}
+
+impl From<ipv4::Error> for Error {
+    fn from(value: ipv4::Error) -> Self {
+        Error::IPv4(value)
+    }
+}
+
+impl From<ipv6::Error> for Error {
+    fn from(value: ipv6::Error) -> Self {
+        Error::IPv6(value)
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct FlowKey {
+    pub source: [u8; 16],
+    pub destination: [u8; 16],
+    /// TCP/UDP ports, zero for other protocols and fragmented packets.
+    pub source_port: u16,
+    pub destination_port: u16,
+    pub protocol: u8,
+    /// IP version, 4 or 6.
+    pub version: u8,
+    /// 802.1Q VLAN identifier, zero when untagged.
+    pub vlan: u16,
+    /// VXLAN/Geneve network identifier, zero when not tunnelled.
+    pub vni: u32,
+}
+
+impl FlowKey {
+    pub const SIZE: usize = 44;
+
+    pub fn ipv4(
+        source: &[u8; 4],
+        destination: &[u8; 4],
+        protocol: InetProtocol,
+        source_port: u16,
+        destination_port: u16,
+    ) -> Self {
+        Self {
+            source: mapped(source),
+            destination: mapped(destination),
+            source_port,
+            destination_port,
+            protocol: u8::from(protocol),
+            version: 4,
+            ..Default::default()
+        }
+    }
+
+    pub fn ipv6(
+        source: &[u8; 16],
+        destination: &[u8; 16],
+        protocol: InetProtocol,
+        source_port: u16,
+        destination_port: u16,
+    ) -> Self {
+        Self {
+            source: *source,
+            destination: *destination,
+            source_port,
+            destination_port,
+            protocol: u8::from(protocol),
+            version: 6,
+            ..Default::default()
+        }
+    }
+
+    /// Key of an IPv4 header followed by `l4`, ports are read when `l4`
+    /// holds a TCP or UDP header of an unfragmented packet. Fragments,
+    /// including the first one, leave them zero so that every fragment of a
+    /// datagram gets the same key, as RSS does in `crate::rss`.
+    pub fn from_ipv4<P: AsRef<[u8]>>(ip: &IPv4<P>, l4: &[u8]) -> Self {
+        let (source_port, destination_port) =
+            if ip.fragment_offset() == [0, 0] && !ip.more_fragments() {
+                ports(ip.protocol(), l4)
+            } else {
+                (0, 0)
+            };
+        Self::ipv4(
+            ip.source(),
+            ip.destination(),
+            ip.protocol(),
+            source_port,
+            destination_port,
+        )
+    }
+
+    /// Key of an IPv6 header followed by `l4`, extension headers are not followed.
+    pub fn from_ipv6<P: AsRef<[u8]>>(ip: &IPv6<P>, l4: &[u8]) -> Self {
+        let (source_port, destination_port) = ports(ip.next_header(), l4);
+        Self::ipv6(
+            ip.source(),
+            ip.destination(),
+            ip.next_header(),
+            source_port,
+            destination_port,
+        )
+    }
+
+    pub fn from_tcp<P: AsRef<[u8]>, Q: AsRef<[u8]>>(ip: &IPv4<P>, tcp: &Tcp<Q>) -> Self {
+        Self::ipv4(
+            ip.source(),
+            ip.destination(),
+            InetProtocol::TCP,
+            tcp.source(),
+            tcp.destination(),
+        )
+    }
+
+    pub fn from_udp<P: AsRef<[u8]>, Q: AsRef<[u8]>>(ip: &IPv4<P>, udp: &Udp<Q>) -> Self {
+        Self::ipv4(
+            ip.source(),
+            ip.destination(),
+            InetProtocol::UDP,
+            udp.source(),
+            udp.destination(),
+        )
+    }
+
+    /// Key of an Ethernet frame carrying IPv4 or IPv6.
+    pub fn parse(packet: &[u8]) -> Result<Self, Error> {
+        let (eth, rem) = Ethernet::new(packet)?;
+        match eth.ethertype() {
+            EtherType::IPv4 => {
+                let (ip, l4) = IPv4::new(rem)?;
+                Ok(Self::from_ipv4(&ip, l4))
+            }
+            EtherType::IPv6 => {
+                let (ip, l4) = IPv6::new(rem)?;
+                Ok(Self::from_ipv6(&ip, l4))
+            }
+            x => Err(Error::Unsupported(x)),
+        }
+    }
+
+    pub fn with_vlan(self, vlan: u16) -> Self {
+        Self {
+            vlan: vlan & 0x0fff,
+            ..self
+        }
+    }
+
+    pub fn with_vni(self, vni: u32) -> Self {
+        Self {
+            vni: vni & 0x00ff_ffff,
+            ..self
+        }
+    }
+
+    /// IPv4 source address, `None` for IPv6 keys.
+    pub fn source_ipv4(&self) -> Option<[u8; 4]> {
+        unmapped(&self.source, self.version)
+    }
+
+    /// IPv4 destination address, `None` for IPv6 keys.
+    pub fn destination_ipv4(&self) -> Option<[u8; 4]> {
+        unmapped(&self.destination, self.version)
+    }
+
+    /// Key of the opposite direction.
+    pub fn reversed(&self) -> Self {
+        Self {
+            source: self.destination,
+            destination: self.source,
+            source_port: self.destination_port,
+            destination_port: self.source_port,
+            ..*self
+        }
+    }
+
+    /// Direction independent form, with the lower address and port as source.
+    pub fn canonical(&self) -> Self {
+        if self.is_canonical() {
+            *self
+        } else {
+            self.reversed()
+        }
+    }
+
+    pub fn is_canonical(&self) -> bool {
+        (self.source, self.source_port) <= (self.destination, self.destination_port)
+    }
+
+    /// Bytes in the order of the `#[repr(C)]` layout, with multi-byte
+    /// fields in native byte order.
+    pub fn to_bytes(&self) -> [u8; FlowKey::SIZE] {
+        let mut bytes = [0; FlowKey::SIZE];
+        let fields: [&[u8]; 8] = [
+            &self.source,
+            &self.destination,
+            &self.source_port.to_ne_bytes(),
+            &self.destination_port.to_ne_bytes(),
+            &[self.protocol],
+            &[self.version],
+            &self.vlan.to_ne_bytes(),
+            &self.vni.to_ne_bytes(),
+        ];
+        let mut offset = 0;
+        for field in fields {
+            if let Some(dst) = bytes.get_mut(offset..offset + field.len()) {
+                dst.copy_from_slice(field);
+            }
+            offset += field.len();
+        }
+        bytes
+    }
+
+    /// `jhash` of the key, the same value in both directions only for
+    /// canonical keys, see `symmetric_hash`.
+    pub fn hash(&self, seed: u32) -> u32 {
+        hash::jhash(&self.to_bytes(), seed)
+    }
+
+    /// Hash equal for both directions of a flow.
+    pub fn symmetric_hash(&self, seed: u32) -> u32 {
+        self.canonical().hash(seed)
+    }
+}
+
+fn mapped(address: &[u8; 4]) -> [u8; 16] {
+    let mut res = [0; 16];
+    res[10] = 0xff;
+    res[11] = 0xff;
+    res[12..].copy_from_slice(address);
+    res
+}
+
+fn unmapped(address: &[u8; 16], version: u8) -> Option<[u8; 4]> {
+    match (version, address.split_last_chunk::<4>()) {
+        (4, Some((_, v4))) => Some(*v4),
+        _ => None,
+    }
+}
+
+fn ports(protocol: InetProtocol, l4: &[u8]) -> (u16, u16) {
+    match protocol {
+        InetProtocol::TCP => Tcp::new(l4)
+            .map(|(tcp, _)| (tcp.source(), tcp.destination()))
+            .unwrap_or((0, 0)),
+        InetProtocol::UDP => Udp::new(l4)
+            .map(|(udp, _)| (udp.source(), udp.destination()))
+            .unwrap_or((0, 0)),
+        _ => (0, 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layout() {
+        assert_eq!(core::mem::size_of::<FlowKey>(), FlowKey::SIZE);
+        assert_eq!(core::mem::align_of::<FlowKey>(), 4);
+    }
+
+    #[test]
+    fn parse_tcp() {
+        let packet = [
+            0x02, 0x00, 0x00, 0x00, 0x00, 0x02, 0x02, 0x00, 0x00, 0x00, 0x00, 0x01, 0x08, 0x00,
+            0x45, 0x00, 0x00, 0x28, 0x00, 0x01, 0x40, 0x00, 0x40, 0x06, 0x00, 0x00, 0x0a, 0x00,
+            0x00, 0x02, 0x0a, 0x00, 0x00, 0x01, 0xc3, 0x50, 0x00, 0x16, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x50, 0x02, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00,
+        ];
+        let key = FlowKey::parse(&packet).unwrap();
+        assert_eq!(key.source_ipv4(), Some([10, 0, 0, 2]));
+        assert_eq!(key.destination_ipv4(), Some([10, 0, 0, 1]));
+        assert_eq!((key.source_port, key.destination_port), (50000, 22));
+        assert_eq!(key.protocol, 6);
+
+        let (ip, l4) = IPv4::new(&packet[14..]).unwrap();
+        let (tcp, _) = Tcp::new(l4).unwrap();
+        assert_eq!(FlowKey::from_tcp(&ip, &tcp), key);
+
+        let reply = key.reversed();
+        assert!(!key.is_canonical());
+        assert_eq!(key.canonical(), reply.canonical());
+        assert_eq!(key.symmetric_hash(1), reply.symmetric_hash(1));
+        assert_ne!(key.hash(1), reply.hash(1));
+        assert_ne!(key.with_vni(42).hash(1), key.hash(1));
+    }
+}
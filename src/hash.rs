@@ -0,0 +1,141 @@
+//! Bob Jenkins' lookup3 hash as used by the Linux kernel (`jhash`), so flow
+//! hashes computed here match the ones computed by kernel helpers.
+
+const JHASH_INITVAL: u32 = 0xdeadbeef;
+
+#[inline(always)]
+fn mix(a: &mut u32, b: &mut u32, c: &mut u32) {
+    *a = a.wrapping_sub(*c);
+    *a ^= c.rotate_left(4);
+    *c = c.wrapping_add(*b);
+    *b = b.wrapping_sub(*a);
+    *b ^= a.rotate_left(6);
+    *a = a.wrapping_add(*c);
+    *c = c.wrapping_sub(*b);
+    *c ^= b.rotate_left(8);
+    *b = b.wrapping_add(*a);
+    *a = a.wrapping_sub(*c);
+    *a ^= c.rotate_left(16);
+    *c = c.wrapping_add(*b);
+    *b = b.wrapping_sub(*a);
+    *b ^= a.rotate_left(19);
+    *a = a.wrapping_add(*c);
+    *c = c.wrapping_sub(*b);
+    *c ^= b.rotate_left(4);
+    *b = b.wrapping_add(*a);
+}
+
+#[inline(always)]
+fn final_mix(a: u32, b: u32, mut c: u32) -> u32 {
+    c ^= b;
+    c = c.wrapping_sub(b.rotate_left(14));
+    let a = (a ^ c).wrapping_sub(c.rotate_left(11));
+    let b = (b ^ a).wrapping_sub(a.rotate_left(25));
+    c ^= b;
+    c = c.wrapping_sub(b.rotate_left(16));
+    let a = (a ^ c).wrapping_sub(c.rotate_left(4));
+    let b = (b ^ a).wrapping_sub(a.rotate_left(14));
+    c ^= b;
+    c.wrapping_sub(b.rotate_left(24))
+}
+
+#[inline(always)]
+fn le_u32(bytes: &[u8]) -> u32 {
+    let mut word = [0; 4];
+    for (w, b) in word.iter_mut().zip(bytes) {
+        *w = *b;
+    }
+    u32::from_le_bytes(word)
+}
+
+/// Hash of arbitrary bytes, equal to the kernel's `jhash(key, length, initval)`
+/// on little endian machines.
+pub fn jhash(key: &[u8], initval: u32) -> u32 {
+    let init = JHASH_INITVAL
+        .wrapping_add(key.len() as u32)
+        .wrapping_add(initval);
+    let (mut a, mut b, mut c) = (init, init, init);
+
+    let mut rest = key;
+    while rest.len() > 12 {
+        let Some((block, tail)) = rest.split_first_chunk::<12>() else {
+            break;
+        };
+        a = a.wrapping_add(le_u32(&block[0..4]));
+        b = b.wrapping_add(le_u32(&block[4..8]));
+        c = c.wrapping_add(le_u32(&block[8..12]));
+        mix(&mut a, &mut b, &mut c);
+        rest = tail;
+    }
+
+    if rest.is_empty() {
+        return c;
+    }
+    a = a.wrapping_add(le_u32(rest));
+    b = b.wrapping_add(le_u32(rest.get(4..).unwrap_or(&[])));
+    c = c.wrapping_add(le_u32(rest.get(8..).unwrap_or(&[])));
+    final_mix(a, b, c)
+}
+
+/// Hash of 32-bit words, equal to the kernel's `jhash2(key, length, initval)`.
+pub fn jhash2(key: &[u32], initval: u32) -> u32 {
+    let init = JHASH_INITVAL
+        .wrapping_add((key.len() as u32) << 2)
+        .wrapping_add(initval);
+    let (mut a, mut b, mut c) = (init, init, init);
+
+    let mut rest = key;
+    while let [x, y, z, tail @ ..] = rest {
+        if tail.is_empty() {
+            break;
+        }
+        a = a.wrapping_add(*x);
+        b = b.wrapping_add(*y);
+        c = c.wrapping_add(*z);
+        mix(&mut a, &mut b, &mut c);
+        rest = tail;
+    }
+
+    match rest {
+        [x, y, z] => final_mix(a.wrapping_add(*x), b.wrapping_add(*y), c.wrapping_add(*z)),
+        [x, y] => final_mix(a.wrapping_add(*x), b.wrapping_add(*y), c),
+        [x] => final_mix(a.wrapping_add(*x), b, c),
+        _ => c,
+    }
+}
+
+/// Hash of three words, the kernel's `jhash_3words(a, b, c, initval)`.
+pub fn jhash_3words(a: u32, b: u32, c: u32, initval: u32) -> u32 {
+    let init = JHASH_INITVAL.wrapping_add(initval).wrapping_add(3 << 2);
+    final_mix(
+        a.wrapping_add(init),
+        b.wrapping_add(init),
+        c.wrapping_add(init),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup3_vectors() {
+        assert_eq!(jhash(b"", 0), 0xdeadbeef);
+        assert_eq!(jhash(b"", 0xdeadbeef), 0xbd5b7dde);
+        assert_eq!(jhash(b"Four score and seven years ago", 0), 0x17770551);
+        assert_eq!(jhash(b"Four score and seven years ago", 1), 0xcd628161);
+    }
+
+    #[test]
+    fn words_match_bytes() {
+        let words = [0x01020304, 0x05060708, 0x090a0b0c, 0x0d0e0f10, 0x11121314];
+        let mut bytes = [0u8; 20];
+        for (chunk, word) in bytes.chunks_exact_mut(4).zip(words) {
+            chunk.copy_from_slice(&u32::to_le_bytes(word));
+        }
+        for len in 0..=words.len() {
+            assert_eq!(jhash2(&words[..len], 7), jhash(&bytes[..len * 4], 7));
+        }
+        assert_eq!(jhash_3words(1, 2, 3, 9), jhash2(&[1, 2, 3], 9));
+    }
+}
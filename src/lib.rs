@@ -1,7 +1,26 @@
-#![cfg_attr(not(feature = "schema"), no_std)]
+#![cfg_attr(not(any(feature = "std", feature = "schema")), no_std)]
 
+pub mod buf;
+pub mod checksum;
+pub mod conntrack;
+pub mod decode;
+pub mod dissect;
+mod field;
+pub mod filter;
+pub mod flow;
+pub mod hash;
 pub mod link;
+pub mod maglev;
+pub mod nat;
+pub mod nat64;
 pub mod network;
+pub mod pcap;
+pub mod pcapng;
+#[cfg(feature = "std")]
+pub mod reassembly;
+pub mod reflect;
+pub mod rss;
+pub mod summary;
 pub mod transport;
 
 #[cfg(feature = "aya")]
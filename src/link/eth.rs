@@ -1,9 +1,13 @@
+use crate::field;
+
 pub struct Ethernet<P = ()> {
     slice: P,
     size: EtherSize,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[repr(u16)]
 pub enum EtherType {
     IPv4 = 0x0800,
@@ -13,6 +17,8 @@ pub enum EtherType {
     VlanTaggedFrame = 0x8100,
     ProviderBridging = 0x88A8,
     VlanDoubleTaggedFrame = 0x9100,
+    PPPoEDiscovery = 0x8863,
+    PPPoESession = 0x8864,
     Other(u16),
 }
 
@@ -34,6 +40,8 @@ impl From<EtherType> for u16 {
             EtherType::VlanTaggedFrame => 0x8100,
             EtherType::ProviderBridging => 0x88A8,
             EtherType::VlanDoubleTaggedFrame => 0x9100,
+            EtherType::PPPoEDiscovery => 0x8863,
+            EtherType::PPPoESession => 0x8864,
             EtherType::Other(v) => v,
         }
     }
@@ -49,11 +57,7 @@ impl From<[u8; 2]> for EtherType {
 impl TryFrom<&[u8]> for EtherType {
     type Error = ();
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        if value.len() < 2 {
-            return Err(());
-        }
-
-        let array = *value.first_chunk::<2>().unwrap();
+        let array = *value.first_chunk::<2>().ok_or(())?;
         Ok(EtherType::from(array))
     }
 }
@@ -68,6 +72,8 @@ impl From<u16> for EtherType {
             0x8100 => Self::VlanTaggedFrame,
             0x88A8 => Self::ProviderBridging,
             0x9100 => Self::VlanDoubleTaggedFrame,
+            0x8863 => Self::PPPoEDiscovery,
+            0x8864 => Self::PPPoESession,
             x => Self::Other(x),
         }
     }
@@ -81,16 +87,28 @@ pub enum Error {
 
 impl<P: AsMut<[u8]> + AsRef<[u8]>> Ethernet<P> {
     pub fn set_destination(&mut self, new_dest: &[u8; 6]) {
-        self.slice.as_mut()[0..6].copy_from_slice(new_dest);
+        field::write(self.slice.as_mut(), 0, new_dest);
     }
 
     pub fn set_source(&mut self, new_dest: &[u8; 6]) {
-        self.slice.as_mut()[6..12].copy_from_slice(new_dest);
+        field::write(self.slice.as_mut(), 6, new_dest);
     }
 
     pub fn set_ethertype(&mut self, ethertype: EtherType) {
-        self.slice.as_mut()[self.size as usize - 2..self.size as usize]
-            .copy_from_slice(&u16::from(ethertype).to_be_bytes());
+        let offset = self.size as usize - 2;
+        field::write(
+            self.slice.as_mut(),
+            offset,
+            &u16::from(ethertype).to_be_bytes(),
+        );
+    }
+
+    /// Swaps source and destination addresses to send the frame back.
+    pub fn reflect(&mut self) {
+        let source = *self.source();
+        let destination = *self.destination();
+        self.set_source(&destination);
+        self.set_destination(&source);
     }
 
     pub fn slice_mut(&mut self) -> &mut [u8] {
@@ -109,7 +127,7 @@ impl<'pkt> Ethernet<&'pkt [u8]> {
             return Err(Error::WrongSize(slice.len()));
         }
 
-        let size = match EtherType::from(*slice[12..14].first_chunk::<2>().unwrap()) {
+        let size = match EtherType::from(field::read_u16(slice, 12)) {
             EtherType::VlanDoubleTaggedFrame if slice.len() >= Ethernet::MAX_LEN => EtherSize::S18,
             EtherType::VlanTaggedFrame if slice.len() >= Ethernet::MIN_LEN + 2 => EtherSize::S16,
             EtherType::Other(_) if slice.len() >= Ethernet::MIN_LEN => EtherSize::S14,
@@ -117,7 +135,9 @@ impl<'pkt> Ethernet<&'pkt [u8]> {
             x => return Err(Error::WrongSizeForType(x, slice.len())),
         };
 
-        let (parsed, rem) = slice.split_at(size as usize);
+        let (parsed, rem) = slice
+            .split_at_checked(size as usize)
+            .ok_or(Error::WrongSize(slice.len()))?;
         Ok((
             Ethernet {
                 slice: parsed,
@@ -136,7 +156,7 @@ impl<'pkt> Ethernet<&'pkt mut [u8]> {
             return Err(Error::WrongSize(slice.len()));
         }
 
-        let size = match EtherType::from(*slice[12..14].first_chunk::<2>().unwrap()) {
+        let size = match EtherType::from(field::read_u16(slice, 12)) {
             EtherType::VlanDoubleTaggedFrame if slice.len() >= Ethernet::MAX_LEN => EtherSize::S18,
             EtherType::VlanTaggedFrame if slice.len() >= Ethernet::MIN_LEN + 2 => EtherSize::S16,
             EtherType::Other(_) if slice.len() >= Ethernet::MIN_LEN => EtherSize::S14,
@@ -144,7 +164,10 @@ impl<'pkt> Ethernet<&'pkt mut [u8]> {
             x => return Err(Error::WrongSizeForType(x, slice.len())),
         };
 
-        let (parsed, rem) = slice.split_at_mut(size as usize);
+        let len = slice.len();
+        let (parsed, rem) = slice
+            .split_at_mut_checked(size as usize)
+            .ok_or(Error::WrongSize(len))?;
         Ok((
             Ethernet {
                 slice: parsed,
@@ -157,21 +180,11 @@ impl<'pkt> Ethernet<&'pkt mut [u8]> {
 
 impl<P: AsRef<[u8]>> Ethernet<P> {
     pub fn ethertype(&self) -> EtherType {
-        match self.size {
-            EtherSize::S18 => {
-                EtherType::from(*self.slice.as_ref()[16..18].first_chunk::<2>().unwrap())
-            }
-            EtherSize::S16 => {
-                EtherType::from(*self.slice.as_ref()[14..16].first_chunk::<2>().unwrap())
-            }
-            EtherSize::S14 => {
-                EtherType::from(*self.slice.as_ref()[12..14].first_chunk::<2>().unwrap())
-            }
-        }
+        EtherType::from(field::read_u16(self.slice.as_ref(), self.size as usize - 2))
     }
 
     pub fn destination(&self) -> &[u8; 6] {
-        self.slice.as_ref()[0..6].try_into().unwrap()
+        field::array(self.slice.as_ref(), 0).unwrap_or(&[0; 6])
     }
 
     pub fn slice(&self) -> &[u8] {
@@ -191,14 +204,52 @@ impl<P: AsRef<[u8]>> Ethernet<P> {
     }
 
     pub fn source(&self) -> &[u8; 6] {
-        self.slice.as_ref()[6..12].try_into().unwrap()
+        field::array(self.slice.as_ref(), 6).unwrap_or(&[0; 6])
+    }
+}
+
+/// Owned Ethernet header without VLAN tags, e.g. for logging or as a
+/// template to write frames from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct EthernetRepr {
+    pub destination: [u8; 6],
+    pub source: [u8; 6],
+    pub ethertype: EtherType,
+}
+
+impl<P: AsRef<[u8]>> From<&Ethernet<P>> for EthernetRepr {
+    fn from(eth: &Ethernet<P>) -> Self {
+        Self {
+            destination: *eth.destination(),
+            source: *eth.source(),
+            ethertype: eth.ethertype(),
+        }
+    }
+}
+
+impl EthernetRepr {
+    /// Writes the header to the start of `slice`.
+    pub fn emit<'pkt>(
+        &self,
+        slice: &'pkt mut [u8],
+    ) -> Result<(Ethernet<&'pkt mut [u8]>, &'pkt mut [u8]), Error> {
+        if slice.len() < Ethernet::MIN_LEN {
+            return Err(Error::WrongSize(slice.len()));
+        }
+        field::write(slice, 12, &u16::from(self.ethertype).to_be_bytes());
+        let (mut eth, rem) = Ethernet::new_mut(slice)?;
+        eth.set_destination(&self.destination);
+        eth.set_source(&self.source);
+        Ok((eth, rem))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::link::eth::EtherSize;
-    use crate::link::{EtherType, Ethernet};
+    use crate::link::{EtherType, Ethernet, EthernetRepr};
 
     #[test]
     fn create_mut() {
@@ -233,6 +284,30 @@ mod tests {
         assert_eq!(eth.ethertype(), EtherType::IPv4);
     }
 
+    #[test]
+    fn repr_round_trip() {
+        let packet = [
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x86, 0xdd,
+        ];
+        let (eth, _) = Ethernet::new(&packet).unwrap();
+        let repr = EthernetRepr::from(&eth);
+        assert_eq!(repr.ethertype, EtherType::IPv6);
+
+        let mut buf = [0xff; 14];
+        let (emitted, _) = repr.emit(&mut buf).unwrap();
+        assert_eq!(emitted.slice(), packet);
+
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&repr).unwrap();
+            assert_eq!(
+                json,
+                r#"{"destination":[0,0,0,0,0,0],"source":[1,1,1,1,1,1],"ethertype":"IPv6"}"#
+            );
+            assert_eq!(serde_json::from_str::<EthernetRepr>(&json).unwrap(), repr);
+        }
+    }
+
     #[test]
     fn vlan_tagged() {
         let packet = [
@@ -1,2 +1,8 @@
 pub mod eth;
-pub use eth::{Ethernet, EtherType};
\ No newline at end of file
+pub mod null;
+pub mod pppoe;
+pub mod sll;
+pub use eth::{EtherType, Ethernet, EthernetRepr};
+pub use null::{Null, NullRepr};
+pub use pppoe::{PPPoE, PPPoERepr, Ppp, PppProtocol};
+pub use sll::{Sll, Sll2, Sll2Repr, SllRepr};
@@ -0,0 +1,163 @@
+use super::EtherType;
+use crate::field;
+
+/// BSD loopback header \[[LINKTYPE_NULL](https://www.tcpdump.org/linktypes/LINKTYPE_NULL.html)\],
+/// a 4-byte address family of the payload.
+///
+/// The family is in the byte order of the capturing host for `LINKTYPE_NULL`
+/// and in network byte order for `LINKTYPE_LOOP`; since families are small
+/// numbers `family` finds out the byte order itself and the same view serves
+/// both.
+pub struct Null<P = ()> {
+    slice: P,
+}
+
+/// Address family, `AF_INET6` differs between systems.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum AddressFamily {
+    Inet,
+    Inet6,
+    Other(u32),
+}
+
+impl From<u32> for AddressFamily {
+    fn from(value: u32) -> Self {
+        match value {
+            2 => AddressFamily::Inet,
+            // Linux, NetBSD/OpenBSD, FreeBSD, Darwin
+            10 | 24 | 28 | 30 => AddressFamily::Inet6,
+            x => AddressFamily::Other(x),
+        }
+    }
+}
+
+impl From<AddressFamily> for EtherType {
+    fn from(value: AddressFamily) -> Self {
+        match value {
+            AddressFamily::Inet => EtherType::IPv4,
+            AddressFamily::Inet6 => EtherType::IPv6,
+            AddressFamily::Other(x) => EtherType::Other(x as u16),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidSize(usize),
+}
+
+impl Null<()> {
+    pub const SIZE: usize = 4;
+}
+
+impl<'pkt> Null<&'pkt [u8]> {
+    pub fn new(slice: &'pkt [u8]) -> Result<(Self, &'pkt [u8]), Error> {
+        let (slice, rem) = slice
+            .split_at_checked(Null::SIZE)
+            .ok_or(Error::InvalidSize(slice.len()))?;
+        Ok((Self { slice }, rem))
+    }
+}
+
+impl<'pkt> Null<&'pkt mut [u8]> {
+    pub fn new_mut(slice: &'pkt mut [u8]) -> Result<(Self, &'pkt mut [u8]), Error> {
+        let len = slice.len();
+        let (slice, rem) = slice
+            .split_at_mut_checked(Null::SIZE)
+            .ok_or(Error::InvalidSize(len))?;
+        Ok((Self { slice }, rem))
+    }
+}
+
+impl<P: AsMut<[u8]> + AsRef<[u8]>> Null<P> {
+    /// Writes the family in network byte order, as `LINKTYPE_LOOP` does.
+    pub fn set_family_u32(&mut self, family: u32) {
+        field::write(self.slice.as_mut(), 0, &family.to_be_bytes());
+    }
+
+    pub fn slice_mut(&mut self) -> &mut [u8] {
+        self.slice.as_mut()
+    }
+}
+
+impl<P: AsRef<[u8]>> Null<P> {
+    /// Family number in whichever byte order gives a value below 2^16.
+    pub fn family_u32(&self) -> u32 {
+        let family = field::read_u32(self.slice.as_ref(), 0);
+        if family & 0xffff_0000 != 0 {
+            family.swap_bytes()
+        } else {
+            family
+        }
+    }
+
+    pub fn family(&self) -> AddressFamily {
+        AddressFamily::from(self.family_u32())
+    }
+
+    pub fn slice(&self) -> &[u8] {
+        self.slice.as_ref()
+    }
+}
+
+/// Owned loopback header, the family number is kept as is since the
+/// `AF_INET6` value tells which system captured the packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct NullRepr {
+    pub family: u32,
+}
+
+impl<P: AsRef<[u8]>> From<&Null<P>> for NullRepr {
+    fn from(null: &Null<P>) -> Self {
+        Self {
+            family: null.family_u32(),
+        }
+    }
+}
+
+impl NullRepr {
+    /// Writes the header to the start of `slice`, in network byte order.
+    pub fn emit<'pkt>(
+        &self,
+        slice: &'pkt mut [u8],
+    ) -> Result<(Null<&'pkt mut [u8]>, &'pkt mut [u8]), Error> {
+        let (mut null, rem) = Null::new_mut(slice)?;
+        null.set_family_u32(self.family);
+        Ok((null, rem))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_orders() {
+        let (null, rem) = Null::new(&[2, 0, 0, 0, 0x45]).unwrap();
+        assert_eq!(null.family(), AddressFamily::Inet);
+        assert_eq!(EtherType::from(null.family()), EtherType::IPv4);
+        assert_eq!(rem, [0x45]);
+
+        let (null, _) = Null::new(&[0, 0, 0, 30]).unwrap();
+        assert_eq!(null.family(), AddressFamily::Inet6);
+        assert_eq!(null.family_u32(), 30);
+        assert!(matches!(Null::new(&[0, 0]), Err(Error::InvalidSize(2))));
+    }
+
+    #[test]
+    fn repr_round_trip() {
+        let (null, _) = Null::new(&[30, 0, 0, 0]).unwrap();
+        let repr = NullRepr::from(&null);
+        assert_eq!(repr.family, 30);
+
+        let mut buf = [0xff; 5];
+        let (emitted, rem) = repr.emit(&mut buf).unwrap();
+        assert_eq!(emitted.slice(), [0, 0, 0, 30]);
+        assert_eq!(emitted.family(), AddressFamily::Inet6);
+        assert_eq!(rem.len(), 1);
+    }
+}
@@ -0,0 +1,520 @@
+use super::EtherType;
+
+/// PPPoE header as described in [RFC2516](https://datatracker.ietf.org/doc/html/rfc2516).
+///
+/// The same header is used by discovery (`EtherType::PPPoEDiscovery`) and
+/// session (`EtherType::PPPoESession`) frames; the payload returned by `new`
+/// is bounded by the `length` field so trailing Ethernet padding is dropped.
+pub struct PPPoE<P = ()> {
+    slice: P,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[repr(u8)]
+pub enum Code {
+    Session = 0x00,
+    /// PPPoE Active Discovery Offer
+    Pado = 0x07,
+    /// PPPoE Active Discovery Initiation
+    Padi = 0x09,
+    /// PPPoE Active Discovery Request
+    Padr = 0x19,
+    /// PPPoE Active Discovery Session-confirmation
+    Pads = 0x65,
+    /// PPPoE Active Discovery Terminate
+    Padt = 0xa7,
+    Other(u8),
+}
+
+impl From<u8> for Code {
+    fn from(value: u8) -> Self {
+        match value {
+            0x00 => Code::Session,
+            0x07 => Code::Pado,
+            0x09 => Code::Padi,
+            0x19 => Code::Padr,
+            0x65 => Code::Pads,
+            0xa7 => Code::Padt,
+            x => Code::Other(x),
+        }
+    }
+}
+
+impl From<Code> for u8 {
+    fn from(value: Code) -> Self {
+        match value {
+            Code::Session => 0x00,
+            Code::Pado => 0x07,
+            Code::Padi => 0x09,
+            Code::Padr => 0x19,
+            Code::Pads => 0x65,
+            Code::Padt => 0xa7,
+            Code::Other(x) => x,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidSize(usize),
+    InvalidVersion(u8),
+    InvalidType(u8),
+    InvalidSizeForLength(usize, u16),
+}
+
+impl PPPoE<()> {
+    pub const SIZE: usize = 6;
+}
+
+fn validate(slice: &[u8]) -> Result<usize, Error> {
+    if slice.len() < PPPoE::SIZE {
+        return Err(Error::InvalidSize(slice.len()));
+    }
+
+    if slice[0] >> 4 != 1 {
+        return Err(Error::InvalidVersion(slice[0] >> 4));
+    }
+
+    if slice[0] & 0xF != 1 {
+        return Err(Error::InvalidType(slice[0] & 0xF));
+    }
+
+    let length = u16::from_be_bytes(*slice[4..6].first_chunk::<2>().unwrap());
+    if slice.len() - PPPoE::SIZE < length as usize {
+        return Err(Error::InvalidSizeForLength(slice.len(), length));
+    }
+
+    Ok(length as usize)
+}
+
+impl<'pkt> PPPoE<&'pkt [u8]> {
+    pub fn new(slice: &'pkt [u8]) -> Result<(Self, &'pkt [u8]), Error> {
+        let length = validate(slice)?;
+
+        let (slice, rem) = slice.split_at(PPPoE::SIZE);
+        Ok((Self { slice }, &rem[..length]))
+    }
+}
+
+impl<'pkt> PPPoE<&'pkt mut [u8]> {
+    pub fn new_mut(slice: &'pkt mut [u8]) -> Result<(Self, &'pkt mut [u8]), Error> {
+        let length = validate(slice)?;
+
+        let (slice, rem) = slice.split_at_mut(PPPoE::SIZE);
+        Ok((Self { slice }, &mut rem[..length]))
+    }
+}
+
+impl<P: AsMut<[u8]> + AsRef<[u8]>> PPPoE<P> {
+    pub fn set_code(&mut self, code: Code) {
+        self.slice.as_mut()[1] = u8::from(code);
+    }
+
+    pub fn set_session_id(&mut self, session_id: u16) {
+        self.slice.as_mut()[2..4].copy_from_slice(&session_id.to_be_bytes())
+    }
+
+    pub fn set_length(&mut self, length: u16) {
+        self.slice.as_mut()[4..6].copy_from_slice(&length.to_be_bytes())
+    }
+
+    pub fn slice_mut(&mut self) -> &mut [u8] {
+        self.slice.as_mut()
+    }
+}
+
+impl<P: AsRef<[u8]>> PPPoE<P> {
+    pub fn version(&self) -> u8 {
+        self.slice.as_ref()[0] >> 4
+    }
+
+    pub fn type_u8(&self) -> u8 {
+        self.slice.as_ref()[0] & 0xF
+    }
+
+    pub fn code(&self) -> Code {
+        Code::from(self.slice.as_ref()[1])
+    }
+
+    pub fn code_u8(&self) -> u8 {
+        self.slice.as_ref()[1]
+    }
+
+    pub fn session_id(&self) -> u16 {
+        u16::from_be_bytes(*self.slice.as_ref()[2..4].first_chunk::<2>().unwrap())
+    }
+
+    pub fn length(&self) -> u16 {
+        u16::from_be_bytes(*self.slice.as_ref()[4..6].first_chunk::<2>().unwrap())
+    }
+
+    pub fn is_discovery(&self) -> bool {
+        !matches!(self.code(), Code::Session)
+    }
+
+    pub fn slice(&self) -> &[u8] {
+        self.slice.as_ref()
+    }
+}
+
+/// Owned PPPoE header, version and type are always 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct PPPoERepr {
+    pub code: Code,
+    pub session_id: u16,
+    pub length: u16,
+}
+
+impl<P: AsRef<[u8]>> From<&PPPoE<P>> for PPPoERepr {
+    fn from(pppoe: &PPPoE<P>) -> Self {
+        Self {
+            code: pppoe.code(),
+            session_id: pppoe.session_id(),
+            length: pppoe.length(),
+        }
+    }
+}
+
+impl PPPoERepr {
+    /// Writes the header to the start of `slice`, the payload returned is
+    /// `length` bytes long.
+    pub fn emit<'pkt>(
+        &self,
+        slice: &'pkt mut [u8],
+    ) -> Result<(PPPoE<&'pkt mut [u8]>, &'pkt mut [u8]), Error> {
+        if slice.len() < PPPoE::SIZE {
+            return Err(Error::InvalidSize(slice.len()));
+        }
+        if slice.len() - PPPoE::SIZE < self.length as usize {
+            return Err(Error::InvalidSizeForLength(slice.len(), self.length));
+        }
+        slice[0] = 0x11;
+        slice[4..6].copy_from_slice(&self.length.to_be_bytes());
+        let (mut pppoe, rem) = PPPoE::new_mut(slice)?;
+        pppoe.set_code(self.code);
+        pppoe.set_session_id(self.session_id);
+        Ok((pppoe, rem))
+    }
+}
+
+/// PPP protocol field carried at the start of every PPPoE session payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u16)]
+pub enum PppProtocol {
+    IPv4 = 0x0021,
+    IPv6 = 0x0057,
+    /// IP Control Protocol
+    Ipcp = 0x8021,
+    /// IPv6 Control Protocol
+    Ipv6cp = 0x8057,
+    /// Link Control Protocol
+    Lcp = 0xc021,
+    /// Password Authentication Protocol
+    Pap = 0xc023,
+    /// Link Quality Report
+    Lqr = 0xc025,
+    /// Challenge Handshake Authentication Protocol
+    Chap = 0xc223,
+    Other(u16),
+}
+
+impl From<u16> for PppProtocol {
+    fn from(value: u16) -> Self {
+        match value {
+            0x0021 => PppProtocol::IPv4,
+            0x0057 => PppProtocol::IPv6,
+            0x8021 => PppProtocol::Ipcp,
+            0x8057 => PppProtocol::Ipv6cp,
+            0xc021 => PppProtocol::Lcp,
+            0xc023 => PppProtocol::Pap,
+            0xc025 => PppProtocol::Lqr,
+            0xc223 => PppProtocol::Chap,
+            x => PppProtocol::Other(x),
+        }
+    }
+}
+
+impl From<PppProtocol> for u16 {
+    fn from(value: PppProtocol) -> Self {
+        match value {
+            PppProtocol::IPv4 => 0x0021,
+            PppProtocol::IPv6 => 0x0057,
+            PppProtocol::Ipcp => 0x8021,
+            PppProtocol::Ipv6cp => 0x8057,
+            PppProtocol::Lcp => 0xc021,
+            PppProtocol::Pap => 0xc023,
+            PppProtocol::Lqr => 0xc025,
+            PppProtocol::Chap => 0xc223,
+            PppProtocol::Other(x) => x,
+        }
+    }
+}
+
+impl PppProtocol {
+    /// The `EtherType` the payload would have if carried directly over
+    /// Ethernet, used to hand session traffic to the network layer parsers.
+    pub fn ethertype(&self) -> Option<EtherType> {
+        match self {
+            PppProtocol::IPv4 => Some(EtherType::IPv4),
+            PppProtocol::IPv6 => Some(EtherType::IPv6),
+            _ => None,
+        }
+    }
+}
+
+/// The 2 byte PPP header found in PPPoE session payloads.
+pub struct Ppp<P = ()> {
+    slice: P,
+}
+
+impl Ppp<()> {
+    pub const SIZE: usize = 2;
+}
+
+impl<'pkt> Ppp<&'pkt [u8]> {
+    pub fn new(slice: &'pkt [u8]) -> Result<(Self, &'pkt [u8]), Error> {
+        if slice.len() < Ppp::SIZE {
+            return Err(Error::InvalidSize(slice.len()));
+        }
+
+        let (slice, rem) = slice.split_at(Ppp::SIZE);
+        Ok((Self { slice }, rem))
+    }
+}
+
+impl<'pkt> Ppp<&'pkt mut [u8]> {
+    pub fn new_mut(slice: &'pkt mut [u8]) -> Result<(Self, &'pkt mut [u8]), Error> {
+        if slice.len() < Ppp::SIZE {
+            return Err(Error::InvalidSize(slice.len()));
+        }
+
+        let (slice, rem) = slice.split_at_mut(Ppp::SIZE);
+        Ok((Self { slice }, rem))
+    }
+}
+
+impl<P: AsMut<[u8]> + AsRef<[u8]>> Ppp<P> {
+    pub fn set_protocol(&mut self, protocol: PppProtocol) {
+        self.slice.as_mut()[0..2].copy_from_slice(&u16::from(protocol).to_be_bytes())
+    }
+}
+
+impl<P: AsRef<[u8]>> Ppp<P> {
+    pub fn protocol(&self) -> PppProtocol {
+        PppProtocol::from(self.protocol_u16())
+    }
+
+    pub fn protocol_u16(&self) -> u16 {
+        u16::from_be_bytes(*self.slice.as_ref()[0..2].first_chunk::<2>().unwrap())
+    }
+
+    /// Shorthand for `self.protocol().ethertype()`.
+    pub fn ethertype(&self) -> Option<EtherType> {
+        self.protocol().ethertype()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u16)]
+pub enum TagType {
+    EndOfList = 0x0000,
+    ServiceName = 0x0101,
+    AcName = 0x0102,
+    HostUniq = 0x0103,
+    AcCookie = 0x0104,
+    VendorSpecific = 0x0105,
+    RelaySessionId = 0x0110,
+    ServiceNameError = 0x0201,
+    AcSystemError = 0x0202,
+    GenericError = 0x0203,
+    Other(u16),
+}
+
+impl From<u16> for TagType {
+    fn from(value: u16) -> Self {
+        match value {
+            0x0000 => TagType::EndOfList,
+            0x0101 => TagType::ServiceName,
+            0x0102 => TagType::AcName,
+            0x0103 => TagType::HostUniq,
+            0x0104 => TagType::AcCookie,
+            0x0105 => TagType::VendorSpecific,
+            0x0110 => TagType::RelaySessionId,
+            0x0201 => TagType::ServiceNameError,
+            0x0202 => TagType::AcSystemError,
+            0x0203 => TagType::GenericError,
+            x => TagType::Other(x),
+        }
+    }
+}
+
+impl From<TagType> for u16 {
+    fn from(value: TagType) -> Self {
+        match value {
+            TagType::EndOfList => 0x0000,
+            TagType::ServiceName => 0x0101,
+            TagType::AcName => 0x0102,
+            TagType::HostUniq => 0x0103,
+            TagType::AcCookie => 0x0104,
+            TagType::VendorSpecific => 0x0105,
+            TagType::RelaySessionId => 0x0110,
+            TagType::ServiceNameError => 0x0201,
+            TagType::AcSystemError => 0x0202,
+            TagType::GenericError => 0x0203,
+            TagType::Other(x) => x,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tag<'pkt> {
+    pub tag_type: TagType,
+    pub value: &'pkt [u8],
+}
+
+/// Iterator over the TLV tags of a discovery (PADI/PADO/PADR/PADS/PADT) payload.
+///
+/// Iteration stops at an `EndOfList` tag, at the end of the payload, or after
+/// yielding an error for a tag whose length overruns the payload.
+pub struct Tags<'pkt> {
+    rem: &'pkt [u8],
+}
+
+impl<'pkt> Tags<'pkt> {
+    pub fn new(payload: &'pkt [u8]) -> Self {
+        Self { rem: payload }
+    }
+}
+
+impl<'pkt> Iterator for Tags<'pkt> {
+    type Item = Result<Tag<'pkt>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rem.is_empty() {
+            return None;
+        }
+
+        if self.rem.len() < 4 {
+            let len = self.rem.len();
+            self.rem = &[];
+            return Some(Err(Error::InvalidSize(len)));
+        }
+
+        let tag_type = TagType::from(u16::from_be_bytes(
+            *self.rem[0..2].first_chunk::<2>().unwrap(),
+        ));
+        let length = u16::from_be_bytes(*self.rem[2..4].first_chunk::<2>().unwrap());
+
+        if self.rem.len() - 4 < length as usize {
+            let len = self.rem.len();
+            self.rem = &[];
+            return Some(Err(Error::InvalidSizeForLength(len, length)));
+        }
+
+        let (value, rem) = self.rem[4..].split_at(length as usize);
+        self.rem = if tag_type == TagType::EndOfList {
+            &[]
+        } else {
+            rem
+        };
+
+        Some(Ok(Tag { tag_type, value }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Code, Error, PPPoE, PPPoERepr, Ppp, PppProtocol, TagType, Tags};
+    use crate::link::{EtherType, Ethernet};
+    use crate::network::IPv4;
+
+    #[test]
+    fn padi_tags() {
+        let packet = [
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x88, 0x63,
+            0x11, 0x09, 0x00, 0x00, 0x00, 0x10, 0x01, 0x01, 0x00, 0x00, 0x01, 0x03, 0x00, 0x04,
+            0xde, 0xad, 0xbe, 0xef, 0x00, 0x00, 0x00, 0x00,
+        ];
+        let (eth, rem) = Ethernet::new(&packet).unwrap();
+        assert_eq!(eth.ethertype(), EtherType::PPPoEDiscovery);
+
+        let (pppoe, payload) = PPPoE::new(rem).unwrap();
+        assert_eq!(pppoe.version(), 1);
+        assert_eq!(pppoe.code(), Code::Padi);
+        assert_eq!(pppoe.session_id(), 0);
+        assert!(pppoe.is_discovery());
+        assert_eq!(payload.len(), 16);
+
+        let mut tags = Tags::new(payload);
+        let tag = tags.next().unwrap().unwrap();
+        assert_eq!(tag.tag_type, TagType::ServiceName);
+        assert!(tag.value.is_empty());
+
+        let tag = tags.next().unwrap().unwrap();
+        assert_eq!(tag.tag_type, TagType::HostUniq);
+        assert_eq!(tag.value, &[0xde, 0xad, 0xbe, 0xef]);
+
+        let tag = tags.next().unwrap().unwrap();
+        assert_eq!(tag.tag_type, TagType::EndOfList);
+        assert!(tags.next().is_none());
+    }
+
+    #[test]
+    fn truncated_tag() {
+        let payload = [0x01, 0x02, 0x00, 0x08, 0x41, 0x43];
+        let mut tags = Tags::new(&payload);
+        assert!(tags.next().unwrap().is_err());
+        assert!(tags.next().is_none());
+    }
+
+    #[test]
+    fn session_ipv4() {
+        let packet = [
+            0x11, 0x00, 0x12, 0x34, 0x00, 0x16, 0x00, 0x21, 0x45, 0x00, 0x00, 0x14, 0x00, 0x00,
+            0x40, 0x00, 0x40, 0x11, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x01, 0x0a, 0x00, 0x00, 0x02,
+            0x00, 0x00,
+        ];
+        let (pppoe, payload) = PPPoE::new(&packet).unwrap();
+        assert_eq!(pppoe.code(), Code::Session);
+        assert_eq!(pppoe.session_id(), 0x1234);
+
+        let (ppp, rem) = Ppp::new(payload).unwrap();
+        assert_eq!(ppp.protocol(), PppProtocol::IPv4);
+        assert_eq!(ppp.ethertype(), Some(EtherType::IPv4));
+
+        let (ip, rem) = IPv4::new(rem).unwrap();
+        assert_eq!(rem.len(), 0);
+        assert_eq!(ip.source(), &[10, 0, 0, 1]);
+    }
+
+    #[test]
+    fn repr_round_trip() {
+        let packet = [0x11, 0x00, 0x12, 0x34, 0x00, 0x02, 0x00, 0x21];
+        let (pppoe, _) = PPPoE::new(&packet).unwrap();
+        let repr = PPPoERepr::from(&pppoe);
+        assert_eq!(repr.code, Code::Session);
+        assert_eq!(repr.session_id, 0x1234);
+
+        let mut buf = [0xff; 9];
+        buf[6..8].copy_from_slice(&[0x00, 0x21]);
+        let (emitted, rem) = repr.emit(&mut buf).unwrap();
+        assert_eq!(emitted.slice(), &packet[..6]);
+        assert_eq!(rem, [0x00, 0x21]);
+        let mut short = [0xff; 7];
+        assert!(matches!(
+            repr.emit(&mut short),
+            Err(Error::InvalidSizeForLength(7, _))
+        ));
+        assert_eq!(short, [0xff; 7]);
+    }
+
+    #[test]
+    fn length_overrun() {
+        let packet = [0x11, 0x00, 0x12, 0x34, 0x00, 0x10, 0x00, 0x21];
+        assert!(PPPoE::new(&packet).is_err());
+    }
+}
@@ -0,0 +1,465 @@
+use super::EtherType;
+use crate::field;
+
+/// Linux "cooked" capture header \[[LINKTYPE_LINUX_SLL](https://www.tcpdump.org/linktypes/LINKTYPE_LINUX_SLL.html)\],
+/// used instead of the link-layer header by `tcpdump -i any`.
+pub struct Sll<P = ()> {
+    slice: P,
+}
+
+/// Linux "cooked" capture header, version 2 \[[LINKTYPE_LINUX_SLL2](https://www.tcpdump.org/linktypes/LINKTYPE_LINUX_SLL2.html)\],
+/// which adds the interface index.
+pub struct Sll2<P = ()> {
+    slice: P,
+}
+
+/// Direction of the packet relative to the capturing host, `PACKET_*` of `<linux/if_packet.h>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[repr(u16)]
+pub enum PacketType {
+    /// Sent to us.
+    Host = 0,
+    Broadcast = 1,
+    Multicast = 2,
+    /// Sent by somebody else to somebody else.
+    OtherHost = 3,
+    /// Sent by us.
+    Outgoing = 4,
+    Other(u16),
+}
+
+impl From<u16> for PacketType {
+    fn from(value: u16) -> Self {
+        match value {
+            0 => PacketType::Host,
+            1 => PacketType::Broadcast,
+            2 => PacketType::Multicast,
+            3 => PacketType::OtherHost,
+            4 => PacketType::Outgoing,
+            x => PacketType::Other(x),
+        }
+    }
+}
+
+impl From<PacketType> for u16 {
+    fn from(value: PacketType) -> Self {
+        match value {
+            PacketType::Host => 0,
+            PacketType::Broadcast => 1,
+            PacketType::Multicast => 2,
+            PacketType::OtherHost => 3,
+            PacketType::Outgoing => 4,
+            PacketType::Other(x) => x,
+        }
+    }
+}
+
+/// Link-layer device type, `ARPHRD_*` of `<linux/if_arp.h>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[repr(u16)]
+pub enum ArphrdType {
+    Ether = 1,
+    /// IPIP tunnel
+    Tunnel = 768,
+    /// IPv6 in IPv6 tunnel
+    Tunnel6 = 769,
+    Loopback = 772,
+    /// GRE over IP
+    Ipgre = 778,
+    Ieee80211 = 801,
+    Netlink = 824,
+    /// No link-layer header, e.g. TUN devices and WireGuard.
+    None = 0xfffe,
+    Other(u16),
+}
+
+impl From<u16> for ArphrdType {
+    fn from(value: u16) -> Self {
+        match value {
+            1 => ArphrdType::Ether,
+            768 => ArphrdType::Tunnel,
+            769 => ArphrdType::Tunnel6,
+            772 => ArphrdType::Loopback,
+            778 => ArphrdType::Ipgre,
+            801 => ArphrdType::Ieee80211,
+            824 => ArphrdType::Netlink,
+            0xfffe => ArphrdType::None,
+            x => ArphrdType::Other(x),
+        }
+    }
+}
+
+impl From<ArphrdType> for u16 {
+    fn from(value: ArphrdType) -> Self {
+        match value {
+            ArphrdType::Ether => 1,
+            ArphrdType::Tunnel => 768,
+            ArphrdType::Tunnel6 => 769,
+            ArphrdType::Loopback => 772,
+            ArphrdType::Ipgre => 778,
+            ArphrdType::Ieee80211 => 801,
+            ArphrdType::Netlink => 824,
+            ArphrdType::None => 0xfffe,
+            ArphrdType::Other(x) => x,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidSize(usize),
+}
+
+impl Sll<()> {
+    pub const SIZE: usize = 16;
+}
+
+impl<'pkt> Sll<&'pkt [u8]> {
+    pub fn new(slice: &'pkt [u8]) -> Result<(Self, &'pkt [u8]), Error> {
+        let (slice, rem) = slice
+            .split_at_checked(Sll::SIZE)
+            .ok_or(Error::InvalidSize(slice.len()))?;
+        Ok((Self { slice }, rem))
+    }
+}
+
+impl<'pkt> Sll<&'pkt mut [u8]> {
+    pub fn new_mut(slice: &'pkt mut [u8]) -> Result<(Self, &'pkt mut [u8]), Error> {
+        let len = slice.len();
+        let (slice, rem) = slice
+            .split_at_mut_checked(Sll::SIZE)
+            .ok_or(Error::InvalidSize(len))?;
+        Ok((Self { slice }, rem))
+    }
+}
+
+impl<P: AsMut<[u8]> + AsRef<[u8]>> Sll<P> {
+    pub fn set_packet_type(&mut self, packet_type: PacketType) {
+        field::write(
+            self.slice.as_mut(),
+            0,
+            &u16::from(packet_type).to_be_bytes(),
+        );
+    }
+
+    pub fn set_arphrd_type(&mut self, arphrd_type: ArphrdType) {
+        field::write(
+            self.slice.as_mut(),
+            2,
+            &u16::from(arphrd_type).to_be_bytes(),
+        );
+    }
+
+    /// Sets the address and its length, truncated to 8 bytes.
+    pub fn set_address(&mut self, address: &[u8]) {
+        let slice = self.slice.as_mut();
+        field::write(slice, 4, &(address.len().min(8) as u16).to_be_bytes());
+        write_address(slice, 6, address);
+    }
+
+    pub fn set_protocol(&mut self, protocol: EtherType) {
+        field::write(self.slice.as_mut(), 14, &u16::from(protocol).to_be_bytes());
+    }
+
+    pub fn slice_mut(&mut self) -> &mut [u8] {
+        self.slice.as_mut()
+    }
+}
+
+impl<P: AsRef<[u8]>> Sll<P> {
+    pub fn packet_type(&self) -> PacketType {
+        PacketType::from(field::read_u16(self.slice.as_ref(), 0))
+    }
+
+    pub fn arphrd_type(&self) -> ArphrdType {
+        ArphrdType::from(field::read_u16(self.slice.as_ref(), 2))
+    }
+
+    /// Link-layer source address, at most 8 bytes.
+    pub fn address(&self) -> &[u8] {
+        let len = field::read_u16(self.slice.as_ref(), 4).min(8) as usize;
+        field::range(self.slice.as_ref(), 6, 6 + len)
+    }
+
+    /// Protocol of the payload, an `EtherType` for most device types.
+    pub fn protocol(&self) -> EtherType {
+        EtherType::from(field::read_u16(self.slice.as_ref(), 14))
+    }
+
+    pub fn slice(&self) -> &[u8] {
+        self.slice.as_ref()
+    }
+}
+
+impl Sll2<()> {
+    pub const SIZE: usize = 20;
+}
+
+impl<'pkt> Sll2<&'pkt [u8]> {
+    pub fn new(slice: &'pkt [u8]) -> Result<(Self, &'pkt [u8]), Error> {
+        let (slice, rem) = slice
+            .split_at_checked(Sll2::SIZE)
+            .ok_or(Error::InvalidSize(slice.len()))?;
+        Ok((Self { slice }, rem))
+    }
+}
+
+impl<'pkt> Sll2<&'pkt mut [u8]> {
+    pub fn new_mut(slice: &'pkt mut [u8]) -> Result<(Self, &'pkt mut [u8]), Error> {
+        let len = slice.len();
+        let (slice, rem) = slice
+            .split_at_mut_checked(Sll2::SIZE)
+            .ok_or(Error::InvalidSize(len))?;
+        Ok((Self { slice }, rem))
+    }
+}
+
+impl<P: AsMut<[u8]> + AsRef<[u8]>> Sll2<P> {
+    pub fn set_protocol(&mut self, protocol: EtherType) {
+        field::write(self.slice.as_mut(), 0, &u16::from(protocol).to_be_bytes());
+    }
+
+    pub fn set_interface_index(&mut self, interface_index: u32) {
+        field::write(self.slice.as_mut(), 4, &interface_index.to_be_bytes());
+    }
+
+    pub fn set_arphrd_type(&mut self, arphrd_type: ArphrdType) {
+        field::write(
+            self.slice.as_mut(),
+            8,
+            &u16::from(arphrd_type).to_be_bytes(),
+        );
+    }
+
+    /// Only the low byte is stored, all defined packet types fit.
+    pub fn set_packet_type(&mut self, packet_type: PacketType) {
+        field::write_u8(self.slice.as_mut(), 10, u16::from(packet_type) as u8);
+    }
+
+    /// Sets the address and its length, truncated to 8 bytes.
+    pub fn set_address(&mut self, address: &[u8]) {
+        let slice = self.slice.as_mut();
+        field::write_u8(slice, 11, address.len().min(8) as u8);
+        write_address(slice, 12, address);
+    }
+
+    pub fn slice_mut(&mut self) -> &mut [u8] {
+        self.slice.as_mut()
+    }
+}
+
+impl<P: AsRef<[u8]>> Sll2<P> {
+    pub fn protocol(&self) -> EtherType {
+        EtherType::from(field::read_u16(self.slice.as_ref(), 0))
+    }
+
+    /// Index of the capturing interface, as in `if_nametoindex`.
+    pub fn interface_index(&self) -> u32 {
+        field::read_u32(self.slice.as_ref(), 4)
+    }
+
+    pub fn arphrd_type(&self) -> ArphrdType {
+        ArphrdType::from(field::read_u16(self.slice.as_ref(), 8))
+    }
+
+    pub fn packet_type(&self) -> PacketType {
+        PacketType::from(field::read_u8(self.slice.as_ref(), 10) as u16)
+    }
+
+    /// Link-layer source address, at most 8 bytes.
+    pub fn address(&self) -> &[u8] {
+        let len = field::read_u8(self.slice.as_ref(), 11).min(8) as usize;
+        field::range(self.slice.as_ref(), 12, 12 + len)
+    }
+
+    pub fn slice(&self) -> &[u8] {
+        self.slice.as_ref()
+    }
+}
+
+/// Owned Linux cooked capture header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct SllRepr {
+    pub packet_type: PacketType,
+    pub arphrd_type: ArphrdType,
+    /// Number of used bytes of `address`, at most 8.
+    pub address_len: u8,
+    pub address: [u8; 8],
+    pub protocol: EtherType,
+}
+
+impl<P: AsRef<[u8]>> From<&Sll<P>> for SllRepr {
+    fn from(sll: &Sll<P>) -> Self {
+        let (address_len, address) = owned_address(sll.address());
+        Self {
+            packet_type: sll.packet_type(),
+            arphrd_type: sll.arphrd_type(),
+            address_len,
+            address,
+            protocol: sll.protocol(),
+        }
+    }
+}
+
+impl SllRepr {
+    /// Writes the header to the start of `slice`.
+    pub fn emit<'pkt>(
+        &self,
+        slice: &'pkt mut [u8],
+    ) -> Result<(Sll<&'pkt mut [u8]>, &'pkt mut [u8]), Error> {
+        let (mut sll, rem) = Sll::new_mut(slice)?;
+        sll.set_packet_type(self.packet_type);
+        sll.set_arphrd_type(self.arphrd_type);
+        sll.set_address(&self.address[..usize::from(self.address_len).min(8)]);
+        sll.set_protocol(self.protocol);
+        Ok((sll, rem))
+    }
+}
+
+/// Owned Linux cooked capture header, version 2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Sll2Repr {
+    pub protocol: EtherType,
+    pub interface_index: u32,
+    pub arphrd_type: ArphrdType,
+    pub packet_type: PacketType,
+    /// Number of used bytes of `address`, at most 8.
+    pub address_len: u8,
+    pub address: [u8; 8],
+}
+
+impl<P: AsRef<[u8]>> From<&Sll2<P>> for Sll2Repr {
+    fn from(sll: &Sll2<P>) -> Self {
+        let (address_len, address) = owned_address(sll.address());
+        Self {
+            protocol: sll.protocol(),
+            interface_index: sll.interface_index(),
+            arphrd_type: sll.arphrd_type(),
+            packet_type: sll.packet_type(),
+            address_len,
+            address,
+        }
+    }
+}
+
+impl Sll2Repr {
+    /// Writes the header to the start of `slice`, the reserved field is zeroed.
+    pub fn emit<'pkt>(
+        &self,
+        slice: &'pkt mut [u8],
+    ) -> Result<(Sll2<&'pkt mut [u8]>, &'pkt mut [u8]), Error> {
+        let (mut sll, rem) = Sll2::new_mut(slice)?;
+        field::write(sll.slice_mut(), 2, &[0; 2]);
+        sll.set_protocol(self.protocol);
+        sll.set_interface_index(self.interface_index);
+        sll.set_arphrd_type(self.arphrd_type);
+        sll.set_packet_type(self.packet_type);
+        sll.set_address(&self.address[..usize::from(self.address_len).min(8)]);
+        Ok((sll, rem))
+    }
+}
+
+fn owned_address(address: &[u8]) -> (u8, [u8; 8]) {
+    let mut owned = [0; 8];
+    for (dst, src) in owned.iter_mut().zip(address) {
+        *dst = *src;
+    }
+    (address.len().min(8) as u8, owned)
+}
+
+/// Writes the address field, zero padded to 8 bytes.
+fn write_address(slice: &mut [u8], offset: usize, address: &[u8]) {
+    let mut padded = [0; 8];
+    for (dst, src) in padded.iter_mut().zip(address) {
+        *dst = *src;
+    }
+    field::write(slice, offset, &padded);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::IPv4;
+
+    #[test]
+    fn sll() {
+        let mut packet = [
+            0x00, 0x04, 0x00, 0x01, 0x00, 0x06, 0x02, 0x42, 0xac, 0x11, 0x00, 0x02, 0x00, 0x00,
+            0x08, 0x00, 0x45, 0x00, 0x00, 0x14, 0x00, 0x00, 0x00, 0x00, 0x40, 0x06, 0x00, 0x00,
+            0x0a, 0x00, 0x00, 0x01, 0x0a, 0x00, 0x00, 0x02,
+        ];
+        let (sll, rem) = Sll::new(&packet).unwrap();
+        assert_eq!(sll.packet_type(), PacketType::Outgoing);
+        assert_eq!(sll.arphrd_type(), ArphrdType::Ether);
+        assert_eq!(sll.address(), [0x02, 0x42, 0xac, 0x11, 0x00, 0x02]);
+        assert_eq!(sll.protocol(), EtherType::IPv4);
+        let (ip, _) = IPv4::new(rem).unwrap();
+        assert_eq!(ip.destination(), &[10, 0, 0, 2]);
+
+        let (mut sll, _) = Sll::new_mut(&mut packet).unwrap();
+        sll.set_packet_type(PacketType::Host);
+        sll.set_address(&[1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(sll.packet_type(), PacketType::Host);
+        assert_eq!(sll.address(), [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert!(matches!(
+            Sll::new(&packet[..15]),
+            Err(Error::InvalidSize(15))
+        ));
+    }
+
+    #[test]
+    fn sll2() {
+        let mut packet = [
+            0x86, 0xdd, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0xff, 0xfe, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x60,
+        ];
+        let (sll, rem) = Sll2::new(&packet).unwrap();
+        assert_eq!(sll.protocol(), EtherType::IPv6);
+        assert_eq!(sll.interface_index(), 5);
+        assert_eq!(sll.arphrd_type(), ArphrdType::None);
+        assert_eq!(sll.packet_type(), PacketType::Host);
+        assert!(sll.address().is_empty());
+        assert_eq!(rem, [0x60]);
+
+        let (mut sll, _) = Sll2::new_mut(&mut packet).unwrap();
+        sll.set_address(&[0xaa, 0xbb]);
+        sll.set_packet_type(PacketType::Outgoing);
+        assert_eq!(sll.address(), [0xaa, 0xbb]);
+        assert_eq!(sll.packet_type(), PacketType::Outgoing);
+    }
+
+    #[test]
+    fn repr_round_trip() {
+        let packet = [
+            0x00, 0x04, 0x00, 0x01, 0x00, 0x06, 0x02, 0x42, 0xac, 0x11, 0x00, 0x02, 0x00, 0x00,
+            0x08, 0x00,
+        ];
+        let (sll, _) = Sll::new(&packet).unwrap();
+        let repr = SllRepr::from(&sll);
+        assert_eq!(repr.address_len, 6);
+        let mut buf = [0xff; 16];
+        assert_eq!(repr.emit(&mut buf).unwrap().0.slice(), packet);
+
+        let packet = [
+            0x86, 0xdd, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x00, 0x01, 0x04, 0x02, 0xaa, 0xbb,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        let (sll, _) = Sll2::new(&packet).unwrap();
+        let repr = Sll2Repr::from(&sll);
+        assert_eq!(repr.packet_type, PacketType::Outgoing);
+        let mut buf = [0xff; 20];
+        assert_eq!(repr.emit(&mut buf).unwrap().0.slice(), packet);
+        assert!(matches!(
+            repr.emit(&mut [0; 19]),
+            Err(Error::InvalidSize(19))
+        ));
+    }
+}
@@ -0,0 +1,235 @@
+//! Maglev consistent hashing
+//! \[[Eisenbud et al., NSDI 2016](https://www.usenix.org/conference/nsdi16/technical-sessions/presentation/eisenbud)\].
+//!
+//! `populate` builds the lookup table in userspace into caller provided
+//! buffers, the table is a plain `[u32]` of backend indices meant to be
+//! copied as is into an aya `Array<u32>` map of the same size. `lookup` is the
+//! data path side, a single bounds checked index that is safe to call from
+//! eBPF.
+//!
+//! Backends are identified by a key (e.g. their address) rather than their
+//! position, so adding or removing one only moves about `1/n` of the slots.
+
+use crate::hash;
+
+/// Table size used by the paper and by most deployments.
+pub const DEFAULT_SIZE: usize = 65537;
+
+/// Marks slots not yet assigned during `populate`.
+const EMPTY: u32 = u32::MAX;
+
+const OFFSET_SEED: u32 = 0x5f3d_e2a1;
+const SKIP_SEED: u32 = 0x9e37_79b9;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The table size must be prime for every permutation to cover all slots.
+    NotPrime(usize),
+    /// The table needs more slots than backends.
+    TooSmall(usize),
+    /// No backend has a non-zero weight.
+    NoBackends,
+    /// The scratch buffer must hold one entry per backend.
+    InvalidScratch(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backend<'a> {
+    pub key: &'a [u8],
+    /// Relative share of the slots, zero drains the backend.
+    pub weight: u32,
+}
+
+/// Per backend state of `populate`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Scratch {
+    offset: u64,
+    skip: u64,
+    next: u64,
+    credit: u64,
+}
+
+pub const fn is_prime(n: usize) -> bool {
+    if n < 2 {
+        return false;
+    }
+    let mut i = 2;
+    while i * i <= n {
+        if n.is_multiple_of(i) {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// Fills `table` with indices into `backends`, `scratch` needs one entry per
+/// backend. Each backend gets a share of slots proportional to its weight.
+pub fn populate(
+    table: &mut [u32],
+    backends: &[Backend],
+    scratch: &mut [Scratch],
+) -> Result<(), Error> {
+    let size = table.len();
+    if !is_prime(size) {
+        return Err(Error::NotPrime(size));
+    }
+    if size <= backends.len() {
+        return Err(Error::TooSmall(size));
+    }
+    if scratch.len() != backends.len() {
+        return Err(Error::InvalidScratch(scratch.len()));
+    }
+    let max_weight = backends.iter().map(|b| b.weight).max().unwrap_or(0) as u64;
+    if max_weight == 0 {
+        return Err(Error::NoBackends);
+    }
+
+    let m = size as u64;
+    for (s, backend) in scratch.iter_mut().zip(backends) {
+        *s = Scratch {
+            offset: hash::jhash(backend.key, OFFSET_SEED) as u64 % m,
+            skip: hash::jhash(backend.key, SKIP_SEED) as u64 % (m - 1) + 1,
+            next: 0,
+            credit: 0,
+        };
+    }
+    table.fill(EMPTY);
+
+    let mut filled = 0;
+    loop {
+        for (i, (s, backend)) in scratch.iter_mut().zip(backends).enumerate() {
+            s.credit += backend.weight as u64;
+            while s.credit >= max_weight {
+                s.credit -= max_weight;
+                // Walk the permutation of this backend up to its next free slot.
+                loop {
+                    let slot = ((s.offset + s.next * s.skip) % m) as usize;
+                    s.next += 1;
+                    if let Some(entry) = table.get_mut(slot) {
+                        if *entry == EMPTY {
+                            *entry = i as u32;
+                            break;
+                        }
+                    }
+                }
+                filled += 1;
+                if filled == size {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Slot of `hash` in a table of `size` entries, the index to look up in the map.
+#[inline(always)]
+pub fn slot(hash: u32, size: u32) -> u32 {
+    hash.checked_rem(size).unwrap_or(0)
+}
+
+/// Backend index for `hash`, `None` for an empty table.
+#[inline(always)]
+pub fn lookup(table: &[u32], hash: u32) -> Option<u32> {
+    let index = (hash as usize).checked_rem(table.len())?;
+    table.get(index).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: usize = 4099;
+
+    fn counts(table: &[u32]) -> [usize; 8] {
+        let mut counts = [0; 8];
+        for &i in table {
+            counts[i as usize] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn balanced() {
+        let keys: [[u8; 4]; 5] = [
+            [10, 0, 0, 1],
+            [10, 0, 0, 2],
+            [10, 0, 0, 3],
+            [10, 0, 0, 4],
+            [10, 0, 0, 5],
+        ];
+        let backends: [Backend; 5] = core::array::from_fn(|i| Backend {
+            key: &keys[i],
+            weight: 1,
+        });
+        let mut table = [0; SIZE];
+        let mut scratch = [Scratch::default(); 5];
+        populate(&mut table, &backends, &mut scratch).unwrap();
+
+        for count in &counts(&table)[..5] {
+            assert!(count.abs_diff(SIZE / 5) <= 1, "{count}");
+        }
+        assert!(lookup(&table, 12345).unwrap() < 5);
+        assert_eq!(
+            lookup(&table, 12345),
+            Some(table[slot(12345, SIZE as u32) as usize])
+        );
+    }
+
+    #[test]
+    fn weighted_and_disruption() {
+        let keys: [[u8; 4]; 4] = [[10, 0, 0, 1], [10, 0, 0, 2], [10, 0, 0, 3], [10, 0, 0, 4]];
+        let weights = [1, 2, 1, 0];
+        let backends: [Backend; 4] = core::array::from_fn(|i| Backend {
+            key: &keys[i],
+            weight: weights[i],
+        });
+        let mut table = [0; SIZE];
+        let mut scratch = [Scratch::default(); 4];
+        populate(&mut table, &backends, &mut scratch).unwrap();
+        let c = counts(&table);
+        assert_eq!(c[3], 0);
+        assert!(c[1].abs_diff(2 * c[0]) <= 2);
+
+        // Bring a drained backend up, mostly slots moving to it should change.
+        let weights = [1, 1, 1, 0];
+        let backends: [Backend; 4] = core::array::from_fn(|i| Backend {
+            key: &keys[i],
+            weight: weights[i],
+        });
+        populate(&mut table, &backends, &mut scratch).unwrap();
+        let backends: [Backend; 4] = core::array::from_fn(|i| Backend {
+            key: &keys[i],
+            weight: 1,
+        });
+        let mut after = [0; SIZE];
+        populate(&mut after, &backends, &mut scratch).unwrap();
+        let moved = table.iter().zip(&after).filter(|(a, b)| a != b).count();
+        let to_new = after.iter().filter(|&&b| b == 3).count();
+        assert!(moved < to_new + SIZE / 10, "{moved} {to_new}");
+    }
+
+    #[test]
+    fn errors() {
+        let key = [1];
+        let backends = [Backend {
+            key: &key,
+            weight: 0,
+        }];
+        let mut scratch = [Scratch::default()];
+        assert!(matches!(
+            populate(&mut [0; 7], &backends, &mut scratch),
+            Err(Error::NoBackends)
+        ));
+        assert!(matches!(
+            populate(&mut [0; 8], &backends, &mut scratch),
+            Err(Error::NotPrime(8))
+        ));
+        assert!(matches!(
+            populate(&mut [0; 7], &backends, &mut []),
+            Err(Error::InvalidScratch(0))
+        ));
+        assert_eq!(lookup(&[], 1), None);
+        assert!(is_prime(DEFAULT_SIZE));
+    }
+}
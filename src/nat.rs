@@ -0,0 +1,392 @@
+//! Stateless NAT44 rewrites with incremental checksum fixup.
+//!
+//! Only the address and port of one side are rewritten, keeping track of which
+//! binding applies to which packet is up to the caller.
+
+use crate::checksum;
+use crate::link::{eth, EtherType, Ethernet};
+use crate::network::{ipv4, IPv4, InetProtocol};
+use crate::transport::icmp::{self, Icmp};
+use crate::transport::tcp::{self, Tcp};
+use crate::transport::udp::{self, Udp};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Nat {
+    /// Rewrite the source address and port.
+    Source,
+    /// Rewrite the destination address and port.
+    Destination,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Ethernet(eth::Error),
+    IPv4(ipv4::Error),
+    Tcp(tcp::Error),
+    Udp(udp::Error),
+    Icmp(icmp::Error),
+    NotIPv4(EtherType),
+}
+
+impl From<eth::Error> for Error {
+    fn from(value: eth::Error) -> Self {
+        Error::Ethernet(value)
+    }
+}
+
+impl From<ipv4::Error> for Error {
+    fn from(value: ipv4::Error) -> Self {
+        Error::IPv4(value)
+    }
+}
+
+impl From<tcp::Error> for Error {
+    fn from(value: tcp::Error) -> Self {
+        Error::Tcp(value)
+    }
+}
+
+impl From<udp::Error> for Error {
+    fn from(value: udp::Error) -> Self {
+        Error::Udp(value)
+    }
+}
+
+impl From<icmp::Error> for Error {
+    fn from(value: icmp::Error) -> Self {
+        Error::Icmp(value)
+    }
+}
+
+pub fn snat(packet: &mut [u8], address: &[u8; 4], port: u16) -> Result<(), Error> {
+    translate(packet, Nat::Source, address, port)
+}
+
+pub fn dnat(packet: &mut [u8], address: &[u8; 4], port: u16) -> Result<(), Error> {
+    translate(packet, Nat::Destination, address, port)
+}
+
+/// Rewrites an Ethernet frame carrying IPv4, see `translate_ipv4`.
+pub fn translate(packet: &mut [u8], nat: Nat, address: &[u8; 4], port: u16) -> Result<(), Error> {
+    let (eth, rem) = Ethernet::new_mut(packet)?;
+
+    match eth.ethertype() {
+        EtherType::IPv4 => translate_ipv4(rem, nat, address, port),
+        x => Err(Error::NotIPv4(x)),
+    }
+}
+
+/// Rewrites the address and the TCP/UDP port (or ICMP query identifier) of an
+/// IPv4 packet, fixing every checksum that covers them.
+///
+/// ICMP errors also get the embedded header rewritten: the quoted packet
+/// travelled in the opposite direction, so a source rewrite of the error
+/// changes the destination of the quoted packet and vice versa. Non-first
+/// fragments only get their address rewritten as they carry no L4 header.
+///
+/// The packet is left untouched if any of the headers fails to parse.
+pub fn translate_ipv4(
+    packet: &mut [u8],
+    nat: Nat,
+    address: &[u8; 4],
+    port: u16,
+) -> Result<(), Error> {
+    let (mut ip, l4) = IPv4::new_mut(packet)?;
+
+    let old = match nat {
+        Nat::Source => *ip.source(),
+        Nat::Destination => *ip.destination(),
+    };
+
+    if ip.fragment_offset() == [0, 0] {
+        match ip.protocol() {
+            InetProtocol::TCP => {
+                let (mut tcp, _) = Tcp::new_mut(l4)?;
+                let old_port = match nat {
+                    Nat::Source => tcp.source(),
+                    Nat::Destination => tcp.destination(),
+                };
+                match nat {
+                    Nat::Source => tcp.set_source(port),
+                    Nat::Destination => tcp.set_destination(port),
+                }
+                let csum = checksum::update(tcp.csum(), &old, address);
+                tcp.set_csum(checksum::update_u16(csum, old_port, port));
+            }
+            InetProtocol::UDP => {
+                let (mut udp, _) = Udp::new_mut(l4)?;
+                let old_port = match nat {
+                    Nat::Source => udp.source(),
+                    Nat::Destination => udp.destination(),
+                };
+                match nat {
+                    Nat::Source => udp.set_source(port),
+                    Nat::Destination => udp.set_destination(port),
+                }
+                // A zero checksum means none was computed and must stay that way.
+                if udp.checksum_u16() != 0 {
+                    let csum = checksum::update(udp.checksum_u16(), &old, address);
+                    let csum = checksum::update_u16(csum, old_port, port);
+                    udp.set_checksum(if csum == 0 { 0xffff } else { csum });
+                }
+            }
+            InetProtocol::ICMP => translate_icmp(l4, nat, address, port)?,
+            _ => {}
+        }
+    }
+
+    match nat {
+        Nat::Source => ip.set_source(address),
+        Nat::Destination => ip.set_destination(address),
+    }
+    ip.set_csum(checksum::update(ip.csum(), &old, address));
+
+    Ok(())
+}
+
+fn translate_icmp(l4: &mut [u8], nat: Nat, address: &[u8; 4], port: u16) -> Result<(), Error> {
+    let (mut icmp, payload) = Icmp::new_mut(l4)?;
+    let icmp_type = icmp.icmp_type();
+
+    if icmp_type.is_query() {
+        let old_id = icmp.identifier();
+        icmp.set_identifier(port);
+        icmp.set_csum(checksum::update_u16(icmp.csum(), old_id, port));
+    } else if icmp_type.is_error() {
+        let csum = translate_embedded(payload, nat, address, port, icmp.csum())?;
+        icmp.set_csum(csum);
+    }
+
+    Ok(())
+}
+
+/// Rewrites the packet quoted in an ICMP error and returns the updated ICMP checksum.
+fn translate_embedded(
+    payload: &mut [u8],
+    nat: Nat,
+    address: &[u8; 4],
+    port: u16,
+    mut icmp_csum: u16,
+) -> Result<u16, Error> {
+    let (mut ip, l4) = IPv4::new_mut(payload)?;
+
+    let old = match nat {
+        Nat::Source => *ip.destination(),
+        Nat::Destination => *ip.source(),
+    };
+
+    match ip.protocol() {
+        protocol @ (InetProtocol::TCP | InetProtocol::UDP) if l4.len() >= 4 => {
+            let offset = match nat {
+                Nat::Source => 2,
+                Nat::Destination => 0,
+            };
+            let old_port = u16::from_be_bytes([l4[offset], l4[offset + 1]]);
+            l4[offset..offset + 2].copy_from_slice(&port.to_be_bytes());
+            icmp_csum = checksum::update_u16(icmp_csum, old_port, port);
+
+            // The quoted L4 checksum is only fixed when it was not truncated.
+            let (csum_offset, is_udp) = match protocol {
+                InetProtocol::TCP => (16, false),
+                _ => (6, true),
+            };
+            if l4.len() >= csum_offset + 2 {
+                let old_csum = u16::from_be_bytes([l4[csum_offset], l4[csum_offset + 1]]);
+                if !(is_udp && old_csum == 0) {
+                    let csum = checksum::update(old_csum, &old, address);
+                    let mut csum = checksum::update_u16(csum, old_port, port);
+                    if is_udp && csum == 0 {
+                        csum = 0xffff;
+                    }
+                    l4[csum_offset..csum_offset + 2].copy_from_slice(&csum.to_be_bytes());
+                    icmp_csum = checksum::update_u16(icmp_csum, old_csum, csum);
+                }
+            }
+        }
+        InetProtocol::ICMP => {
+            if let Ok((mut inner, _)) = Icmp::new_mut(l4) {
+                if inner.icmp_type().is_query() {
+                    let old_id = inner.identifier();
+                    let old_csum = inner.csum();
+                    let csum = checksum::update_u16(old_csum, old_id, port);
+                    inner.set_identifier(port);
+                    inner.set_csum(csum);
+                    icmp_csum = checksum::update_u16(icmp_csum, old_id, port);
+                    icmp_csum = checksum::update_u16(icmp_csum, old_csum, csum);
+                }
+            }
+        }
+        _ => {}
+    }
+
+    let old_ip_csum = ip.csum();
+    let ip_csum = checksum::update(old_ip_csum, &old, address);
+    match nat {
+        Nat::Source => ip.set_destination(address),
+        Nat::Destination => ip.set_source(address),
+    }
+    ip.set_csum(ip_csum);
+
+    icmp_csum = checksum::update(icmp_csum, &old, address);
+    Ok(checksum::update_u16(icmp_csum, old_ip_csum, ip_csum))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn l4_csum(ip: &IPv4<&[u8]>, l4: &[u8], csum_offset: usize) -> u16 {
+        let mut copy = [0; 64];
+        let copy = &mut copy[..l4.len()];
+        copy.copy_from_slice(l4);
+        copy[csum_offset..csum_offset + 2].fill(0);
+        let sum = checksum::pseudo_ipv4(
+            ip.source(),
+            ip.destination(),
+            ip.protocol_u8(),
+            l4.len() as u16,
+        );
+        checksum::finish(checksum::sum(sum, copy))
+    }
+
+    fn icmp_csum(l4: &[u8]) -> u16 {
+        let (icmp, payload) = Icmp::new(l4).unwrap();
+        icmp.calc_csum(payload)
+    }
+
+    fn ipv4(protocol: InetProtocol, l4: &[u8], buf: &mut [u8]) -> usize {
+        let len = IPv4::MIN_LEN + l4.len();
+        buf[..IPv4::MIN_LEN].copy_from_slice(&[
+            0x45, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x00, 0x40, 0x00, 0x00, 0x00, 10, 0, 0, 1,
+            198, 51, 100, 7,
+        ]);
+        buf[IPv4::MIN_LEN..len].copy_from_slice(l4);
+        let (mut ip, l4) = IPv4::new_mut(&mut buf[..len]).unwrap();
+        ip.set_total_length_u16(len as u16);
+        ip.set_protocol(protocol);
+        ip.update_csum();
+
+        let csum = match protocol {
+            InetProtocol::TCP => Some(16),
+            InetProtocol::UDP => Some(6),
+            _ => None,
+        };
+        if let Some(offset) = csum {
+            let (ip, _) = IPv4::new(ip.slice()).unwrap();
+            let csum = l4_csum(&ip, l4, offset);
+            l4[offset..offset + 2].copy_from_slice(&csum.to_be_bytes());
+        } else {
+            let csum = icmp_csum(l4);
+            l4[2..4].copy_from_slice(&csum.to_be_bytes());
+        }
+        len
+    }
+
+    fn assert_valid(packet: &[u8], csum_offset: usize) {
+        let (ip, l4) = IPv4::new(packet).unwrap();
+        assert_eq!(ip.csum(), ip.calc_csum());
+        let stored = u16::from_be_bytes([l4[csum_offset], l4[csum_offset + 1]]);
+        assert_eq!(stored, l4_csum(&ip, l4, csum_offset));
+    }
+
+    #[test]
+    fn snat_tcp() {
+        let tcp = [
+            0xc9, 0x3a, 0x01, 0xbb, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x50, 0x02,
+            0xff, 0xff, 0x00, 0x00, 0x00, 0x00, 0xde, 0xad,
+        ];
+        let mut packet = [0; 14 + 42];
+        packet[12..14].copy_from_slice(&[0x08, 0x00]);
+        ipv4(InetProtocol::TCP, &tcp, &mut packet[14..]);
+
+        snat(&mut packet, &[203, 0, 113, 9], 40000).unwrap();
+
+        let (ip, l4) = IPv4::new(&packet[14..]).unwrap();
+        assert_eq!(ip.source(), &[203, 0, 113, 9]);
+        let (tcp, _) = Tcp::new(l4).unwrap();
+        assert_eq!(tcp.source(), 40000);
+        assert_eq!(tcp.destination(), 443);
+        assert_valid(&packet[14..], 16);
+    }
+
+    #[test]
+    fn dnat_udp() {
+        let udp = [
+            0x13, 0x88, 0x00, 0x35, 0x00, 0x0b, 0x00, 0x00, 0x01, 0x02, 0x03,
+        ];
+        let mut buf = [0; 31];
+        ipv4(InetProtocol::UDP, &udp, &mut buf);
+
+        translate_ipv4(&mut buf, Nat::Destination, &[10, 9, 9, 9], 5353).unwrap();
+
+        let (ip, l4) = IPv4::new(&buf).unwrap();
+        assert_eq!(ip.destination(), &[10, 9, 9, 9]);
+        assert_eq!(Udp::new(l4).unwrap().0.destination(), 5353);
+        assert_valid(&buf, 6);
+    }
+
+    #[test]
+    fn udp_zero_checksum_is_kept() {
+        let udp = [0x13, 0x88, 0x00, 0x35, 0x00, 0x08, 0x00, 0x00];
+        let mut buf = [0; 28];
+        ipv4(InetProtocol::UDP, &udp, &mut buf);
+        buf[26..28].fill(0);
+
+        translate_ipv4(&mut buf, Nat::Source, &[203, 0, 113, 9], 1).unwrap();
+
+        let (ip, l4) = IPv4::new(&buf).unwrap();
+        assert_eq!(ip.csum(), ip.calc_csum());
+        assert_eq!(Udp::new(l4).unwrap().0.checksum_u16(), 0);
+    }
+
+    #[test]
+    fn icmp_error_embedded() {
+        // Port unreachable generated by 10.0.0.1 about 198.51.100.7:53 -> 10.0.0.1:5000.
+        let mut quoted = [0; 28];
+        let udp = [0x00, 0x35, 0x13, 0x88, 0x00, 0x08, 0x00, 0x00];
+        ipv4(InetProtocol::UDP, &udp, &mut quoted);
+        {
+            let (mut ip, _) = IPv4::new_mut(&mut quoted).unwrap();
+            ip.set_source(&[198, 51, 100, 7]);
+            ip.set_destination(&[10, 0, 0, 1]);
+            ip.update_csum();
+        }
+        let (ip, l4) = IPv4::new(&quoted).unwrap();
+        let csum = l4_csum(&ip, l4, 6);
+        quoted[26..28].copy_from_slice(&csum.to_be_bytes());
+
+        let mut icmp = [0; 36];
+        icmp[0] = 3;
+        icmp[1] = 3;
+        icmp[8..].copy_from_slice(&quoted);
+
+        let mut buf = [0; 56];
+        ipv4(InetProtocol::ICMP, &icmp, &mut buf);
+
+        translate_ipv4(&mut buf, Nat::Source, &[203, 0, 113, 9], 40000).unwrap();
+
+        let (ip, l4) = IPv4::new(&buf).unwrap();
+        assert_eq!(ip.csum(), ip.calc_csum());
+        assert_eq!(ip.source(), &[203, 0, 113, 9]);
+        assert_eq!(u16::from_be_bytes([l4[2], l4[3]]), icmp_csum(l4));
+
+        let (inner, inner_l4) = IPv4::new(&l4[8..]).unwrap();
+        assert_eq!(inner.destination(), &[203, 0, 113, 9]);
+        assert_eq!(Udp::new(inner_l4).unwrap().0.destination(), 40000);
+        assert_valid(&l4[8..], 6);
+    }
+
+    #[test]
+    fn icmp_echo_identifier() {
+        let echo = [8, 0, 0, 0, 0x12, 0x34, 0x00, 0x01, 0x61, 0x62];
+        let mut buf = [0; 30];
+        ipv4(InetProtocol::ICMP, &echo, &mut buf);
+
+        translate_ipv4(&mut buf, Nat::Source, &[203, 0, 113, 9], 0x4321).unwrap();
+
+        let (_, l4) = IPv4::new(&buf).unwrap();
+        let (icmp, payload) = Icmp::new(l4).unwrap();
+        assert_eq!(icmp.identifier(), 0x4321);
+        assert_eq!(icmp.csum(), icmp.calc_csum(payload));
+    }
+}
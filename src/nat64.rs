@@ -0,0 +1,860 @@
+//! Stateless IP/ICMP translation between IPv4 and IPv6 \[[RFC7915](https://datatracker.ietf.org/doc/html/rfc7915)\].
+//!
+//! Addresses are mapped with an RFC 6052 `Prefix`. Translation happens in
+//! place: `ipv4_to_ipv6` needs `HEADROOM` free bytes in front of the IPv4
+//! packet, since both the outer header and the header quoted by ICMP errors
+//! grow by 20 bytes, while `ipv6_to_ipv4` shrinks the packet towards its end.
+//! Both return the range of the buffer holding the translated packet.
+
+use core::ops::Range;
+
+use crate::checksum;
+use crate::network::{ipv4, ipv6, IPv4, IPv6, InetProtocol};
+use crate::transport::icmp::IcmpType;
+use crate::transport::icmpv6::Icmpv6Type;
+
+/// Bytes needed in front of an IPv4 packet to translate it to IPv6.
+pub const HEADROOM: usize = 2 * (IPv6::SIZE - IPv4::MIN_LEN);
+
+/// IPv4-embedded IPv6 address prefix \[[RFC6052](https://datatracker.ietf.org/doc/html/rfc6052)\].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Prefix {
+    prefix: [u8; 16],
+    len: u8,
+}
+
+/// The `64:ff9b::/96` well-known prefix.
+pub const WELL_KNOWN_PREFIX: Prefix = Prefix {
+    prefix: [0x00, 0x64, 0xff, 0x9b, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+    len: 96,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    IPv4(ipv4::Error),
+    IPv6(ipv6::Error),
+    InvalidHeadroom(usize),
+    InvalidLength(usize),
+    InvalidPrefixLength(u8),
+    /// Fragmented IPv4 packets would need an IPv6 fragment header.
+    Fragmented,
+    TtlExceeded,
+    /// IPv6 address outside of the translation prefix.
+    Unmapped([u8; 16]),
+    /// IPv6 extension header in front of the upper layer protocol.
+    Unsupported(InetProtocol),
+    /// ICMP type and code with no counterpart, RFC 7915 says to drop those.
+    Untranslatable(u8, u8),
+}
+
+impl From<ipv4::Error> for Error {
+    fn from(value: ipv4::Error) -> Self {
+        Error::IPv4(value)
+    }
+}
+
+impl From<ipv6::Error> for Error {
+    fn from(value: ipv6::Error) -> Self {
+        Error::IPv6(value)
+    }
+}
+
+impl Prefix {
+    pub fn new(prefix: [u8; 16], len: u8) -> Result<Self, Error> {
+        match len {
+            32 | 40 | 48 | 56 | 64 | 96 => Ok(Self { prefix, len }),
+            x => Err(Error::InvalidPrefixLength(x)),
+        }
+    }
+
+    pub fn prefix(&self) -> &[u8; 16] {
+        &self.prefix
+    }
+
+    pub fn prefix_len(&self) -> u8 {
+        self.len
+    }
+
+    /// Builds the IPv6 address for `address`, skipping the reserved bits 64 to 71.
+    pub fn embed(&self, address: &[u8; 4]) -> [u8; 16] {
+        let n = self.len as usize / 8;
+        let mut res = [0; 16];
+        res[..n].copy_from_slice(&self.prefix[..n]);
+
+        let mut i = n;
+        for byte in address {
+            if i == 8 {
+                i += 1;
+            }
+            res[i] = *byte;
+            i += 1;
+        }
+        res
+    }
+
+    /// Recovers the IPv4 address from `address`, `None` if it does not start with this prefix.
+    pub fn extract(&self, address: &[u8; 16]) -> Option<[u8; 4]> {
+        let n = self.len as usize / 8;
+        if address[..n] != self.prefix[..n] {
+            return None;
+        }
+
+        let mut res = [0; 4];
+        let mut i = n;
+        for byte in &mut res {
+            if i == 8 {
+                i += 1;
+            }
+            *byte = address[i];
+            i += 1;
+        }
+        Some(res)
+    }
+}
+
+fn concat<const N: usize, const M: usize>(a: &[u8; N], b: &[u8; N]) -> [u8; M] {
+    let mut res = [0; M];
+    res[..N].copy_from_slice(a);
+    res[N..].copy_from_slice(b);
+    res
+}
+
+fn write_ipv4(
+    slice: &mut [u8],
+    tos: u8,
+    total_length: u16,
+    protocol: InetProtocol,
+    ttl: u8,
+    source: &[u8; 4],
+    destination: &[u8; 4],
+) -> Result<(), Error> {
+    slice.fill(0);
+    slice[0] = (4 << 4) | 5;
+
+    let (mut ip, _) = IPv4::new_mut(slice)?;
+    ip.set_dscp(tos >> 2);
+    ip.set_ecn(tos);
+    ip.set_total_length_u16(total_length);
+    ip.set_dont_fragment(total_length > 1260);
+    ip.set_ttl(ttl);
+    ip.set_protocol(protocol);
+    ip.set_source(source);
+    ip.set_destination(destination);
+    ip.update_csum();
+    Ok(())
+}
+
+fn write_ipv6(
+    slice: &mut [u8],
+    traffic_class: u8,
+    payload_length: u16,
+    next_header: InetProtocol,
+    hop_limit: u8,
+    source: &[u8; 16],
+    destination: &[u8; 16],
+) -> Result<(), Error> {
+    slice.fill(0);
+    slice[0] = 6 << 4;
+
+    let (mut ip, _) = IPv6::new_mut(slice)?;
+    ip.set_traffic_class(traffic_class);
+    ip.set_payload_length(payload_length);
+    ip.set_next_header(next_header);
+    ip.set_hop_limit(hop_limit);
+    ip.set_source(source);
+    ip.set_destination(destination);
+    Ok(())
+}
+
+/// Moves a TCP/UDP checksum from the `old` to the `new` pseudo header addresses.
+///
+/// A zero UDP checksum is computed from scratch with `pseudo` when given, as
+/// IPv6 does not allow skipping it; quoted segments pass `None` and keep it.
+fn translate_l4(
+    l4: &mut [u8],
+    protocol: InetProtocol,
+    old: &[u8],
+    new: &[u8],
+    pseudo: Option<u32>,
+) {
+    let is_udp = protocol == InetProtocol::UDP;
+    let offset = if is_udp { 6 } else { 16 };
+    if l4.len() < offset + 2 {
+        return;
+    }
+
+    let csum = u16::from_be_bytes([l4[offset], l4[offset + 1]]);
+    let csum = match pseudo {
+        Some(pseudo) if is_udp && csum == 0 => checksum::finish(checksum::sum(pseudo, l4)),
+        None if is_udp && csum == 0 => return,
+        _ => checksum::update(csum, old, new),
+    };
+    let csum = if is_udp && csum == 0 { 0xffff } else { csum };
+    l4[offset..offset + 2].copy_from_slice(&csum.to_be_bytes());
+}
+
+fn pointer_to_ipv6(pointer: u8) -> Option<u8> {
+    match pointer {
+        0 | 1 => Some(pointer),
+        2 | 3 => Some(4),
+        8 => Some(7),
+        9 => Some(6),
+        12..=15 => Some(8),
+        16..=19 => Some(24),
+        _ => None,
+    }
+}
+
+/// Largest RFC 1191 plateau below `total_length`, the likely path MTU when
+/// a router reports Fragmentation Needed with an MTU of zero.
+fn plateau_mtu(total_length: u16) -> u16 {
+    const PLATEAUS: [u16; 11] = [
+        65535, 32000, 17914, 8166, 4352, 2002, 1492, 1006, 508, 296, 68,
+    ];
+    PLATEAUS
+        .into_iter()
+        .find(|&plateau| plateau < total_length)
+        .unwrap_or(68)
+}
+
+fn pointer_to_ipv4(pointer: u32) -> Option<u8> {
+    match pointer {
+        0 | 1 => Some(pointer as u8),
+        4 | 5 => Some(2),
+        6 => Some(9),
+        7 => Some(8),
+        8..=23 => Some(12),
+        24..=39 => Some(16),
+        _ => None,
+    }
+}
+
+/// ICMPv6 type, code and rest of header for an ICMP message \[RFC7915 section 4.2\].
+///
+/// `quoted_length` is the Total Length of the IPv4 header quoted by an
+/// error, from which the MTU is guessed when a Fragmentation Needed message
+/// carries none.
+pub fn icmp_to_icmpv6(
+    icmp_type: IcmpType,
+    code: u8,
+    rest: &[u8; 4],
+    quoted_length: u16,
+) -> Result<(Icmpv6Type, u8, [u8; 4]), Error> {
+    let untranslatable = Error::Untranslatable(u8::from(icmp_type), code);
+    Ok(match (icmp_type, code) {
+        (IcmpType::EchoRequest, _) => (Icmpv6Type::EchoRequest, 0, *rest),
+        (IcmpType::EchoReply, _) => (Icmpv6Type::EchoReply, 0, *rest),
+        (IcmpType::DestinationUnreachable, 0 | 1 | 5 | 6 | 7 | 8 | 11 | 12) => {
+            (Icmpv6Type::DestinationUnreachable, 0, [0; 4])
+        }
+        (IcmpType::DestinationUnreachable, 2) => (Icmpv6Type::ParameterProblem, 1, [0, 0, 0, 6]),
+        (IcmpType::DestinationUnreachable, 3) => (Icmpv6Type::DestinationUnreachable, 4, [0; 4]),
+        (IcmpType::DestinationUnreachable, 4) => {
+            let mtu = match u16::from_be_bytes([rest[2], rest[3]]) {
+                0 => plateau_mtu(quoted_length),
+                mtu => mtu,
+            };
+            let mtu = u32::from(mtu) + 20;
+            (Icmpv6Type::PacketTooBig, 0, mtu.max(1280).to_be_bytes())
+        }
+        (IcmpType::DestinationUnreachable, 9 | 10 | 13 | 15) => {
+            (Icmpv6Type::DestinationUnreachable, 1, [0; 4])
+        }
+        (IcmpType::TimeExceeded, _) => (Icmpv6Type::TimeExceeded, code, [0; 4]),
+        (IcmpType::ParameterProblem, 0 | 2) => {
+            let pointer = pointer_to_ipv6(rest[0]).ok_or(untranslatable)?;
+            (Icmpv6Type::ParameterProblem, 0, [0, 0, 0, pointer])
+        }
+        _ => return Err(untranslatable),
+    })
+}
+
+/// ICMP type, code and rest of header for an ICMPv6 message \[RFC7915 section 5.2\].
+pub fn icmpv6_to_icmp(
+    icmp_type: Icmpv6Type,
+    code: u8,
+    rest: &[u8; 4],
+) -> Result<(IcmpType, u8, [u8; 4]), Error> {
+    let untranslatable = Error::Untranslatable(u8::from(icmp_type), code);
+    Ok(match (icmp_type, code) {
+        (Icmpv6Type::EchoRequest, _) => (IcmpType::EchoRequest, 0, *rest),
+        (Icmpv6Type::EchoReply, _) => (IcmpType::EchoReply, 0, *rest),
+        (Icmpv6Type::DestinationUnreachable, 0 | 2 | 3) => {
+            (IcmpType::DestinationUnreachable, 1, [0; 4])
+        }
+        (Icmpv6Type::DestinationUnreachable, 1) => (IcmpType::DestinationUnreachable, 10, [0; 4]),
+        (Icmpv6Type::DestinationUnreachable, 4) => (IcmpType::DestinationUnreachable, 3, [0; 4]),
+        (Icmpv6Type::PacketTooBig, _) => {
+            let mtu = u32::from_be_bytes(*rest).saturating_sub(20).min(0xffff) as u16;
+            let mtu = mtu.to_be_bytes();
+            (IcmpType::DestinationUnreachable, 4, [0, 0, mtu[0], mtu[1]])
+        }
+        (Icmpv6Type::TimeExceeded, _) => (IcmpType::TimeExceeded, code, [0; 4]),
+        (Icmpv6Type::ParameterProblem, 0) => {
+            let pointer = pointer_to_ipv4(u32::from_be_bytes(*rest)).ok_or(untranslatable)?;
+            (IcmpType::ParameterProblem, 0, [pointer, 0, 0, 0])
+        }
+        (Icmpv6Type::ParameterProblem, 1) => (IcmpType::DestinationUnreachable, 2, [0; 4]),
+        _ => return Err(untranslatable),
+    })
+}
+
+/// Translates the IPv4 packet found at `buf[HEADROOM..]` to IPv6.
+pub fn ipv4_to_ipv6(buf: &mut [u8], prefix: &Prefix) -> Result<Range<usize>, Error> {
+    if buf.len() < HEADROOM {
+        return Err(Error::InvalidHeadroom(buf.len()));
+    }
+
+    let (ip, _) = IPv4::new(&buf[HEADROOM..])?;
+    if ip.more_fragments() || ip.fragment_offset() != [0, 0] {
+        return Err(Error::Fragmented);
+    }
+    if ip.ttl() <= 1 {
+        return Err(Error::TtlExceeded);
+    }
+
+    let ihl = ip.size() as usize;
+    let total_length = ip.total_length() as usize;
+    if total_length < ihl || buf.len() - HEADROOM < total_length {
+        return Err(Error::InvalidLength(total_length));
+    }
+
+    let source4 = *ip.source();
+    let destination4 = *ip.destination();
+    let source = prefix.embed(&source4);
+    let destination = prefix.embed(&destination4);
+    let traffic_class = (ip.dscp() << 2) | ip.ecn();
+    let hop_limit = ip.ttl() - 1;
+    let protocol = ip.protocol();
+
+    let payload = HEADROOM + ihl;
+    let end = HEADROOM + total_length;
+
+    let (next_header, start) = match protocol {
+        InetProtocol::ICMP => (
+            InetProtocol::IPV6_ICMP,
+            icmp_payload_to_ipv6(buf, payload, end, prefix, &source, &destination)?,
+        ),
+        InetProtocol::TCP | InetProtocol::UDP => {
+            let pseudo = checksum::pseudo_ipv6(
+                &source,
+                &destination,
+                u8::from(protocol),
+                (end - payload) as u32,
+            );
+            translate_l4(
+                &mut buf[payload..end],
+                protocol,
+                &concat::<4, 8>(&source4, &destination4),
+                &concat::<16, 32>(&source, &destination),
+                Some(pseudo),
+            );
+            (protocol, payload)
+        }
+        x => (x, payload),
+    };
+
+    let header = start - IPv6::SIZE;
+    let payload_length =
+        u16::try_from(end - start).map_err(|_| Error::InvalidLength(end - start))?;
+    write_ipv6(
+        &mut buf[header..start],
+        traffic_class,
+        payload_length,
+        next_header,
+        hop_limit,
+        &source,
+        &destination,
+    )?;
+
+    Ok(header..end)
+}
+
+/// Rewrites the ICMP message at `buf[payload..end]` as ICMPv6, returning where it now starts.
+fn icmp_payload_to_ipv6(
+    buf: &mut [u8],
+    payload: usize,
+    end: usize,
+    prefix: &Prefix,
+    source: &[u8; 16],
+    destination: &[u8; 16],
+) -> Result<usize, Error> {
+    if end - payload < 8 {
+        return Err(Error::InvalidLength(end - payload));
+    }
+
+    let mut header = *buf[payload..].first_chunk::<8>().unwrap();
+    let quoted_length = buf
+        .get(payload + 10..payload + 12)
+        .map_or(0, |length| u16::from_be_bytes([length[0], length[1]]));
+    let (icmp_type, code, rest) = icmp_to_icmpv6(
+        IcmpType::from(header[0]),
+        header[1],
+        header[4..8].first_chunk::<4>().unwrap(),
+        quoted_length,
+    )?;
+    header[0] = u8::from(icmp_type);
+    header[1] = code;
+    header[2..4].fill(0);
+    header[4..8].copy_from_slice(&rest);
+
+    let start = if icmp_type.is_error() {
+        let quoted = payload + 8;
+        let (ip, _) = IPv4::new(&buf[quoted..end])?;
+        let ihl = ip.size() as usize;
+        let source4 = *ip.source();
+        let destination4 = *ip.destination();
+        let inner_source = prefix.embed(&source4);
+        let inner_destination = prefix.embed(&destination4);
+        let traffic_class = (ip.dscp() << 2) | ip.ecn();
+        let ttl = ip.ttl();
+        let protocol = ip.protocol();
+        let payload_length = ip.total_length().saturating_sub(ihl as u16);
+
+        let inner_payload = quoted + ihl;
+        let next_header = match protocol {
+            InetProtocol::ICMP => {
+                match buf.get(inner_payload).map(|t| IcmpType::from(*t)) {
+                    Some(IcmpType::EchoRequest) => buf[inner_payload] = 128,
+                    Some(IcmpType::EchoReply) => buf[inner_payload] = 129,
+                    _ => {}
+                }
+                InetProtocol::IPV6_ICMP
+            }
+            InetProtocol::TCP | InetProtocol::UDP => {
+                translate_l4(
+                    &mut buf[inner_payload..end],
+                    protocol,
+                    &concat::<4, 8>(&source4, &destination4),
+                    &concat::<16, 32>(&inner_source, &inner_destination),
+                    None,
+                );
+                protocol
+            }
+            x => x,
+        };
+
+        write_ipv6(
+            &mut buf[inner_payload - IPv6::SIZE..inner_payload],
+            traffic_class,
+            payload_length,
+            next_header,
+            ttl,
+            &inner_source,
+            &inner_destination,
+        )?;
+        inner_payload - IPv6::SIZE - header.len()
+    } else {
+        payload
+    };
+
+    buf[start..start + header.len()].copy_from_slice(&header);
+    let sum = checksum::pseudo_ipv6(
+        source,
+        destination,
+        u8::from(InetProtocol::IPV6_ICMP),
+        (end - start) as u32,
+    );
+    let csum = checksum::finish(checksum::sum(sum, &buf[start..end]));
+    buf[start + 2..start + 4].copy_from_slice(&csum.to_be_bytes());
+
+    Ok(start)
+}
+
+/// Translates the IPv6 packet at the start of `buf` to IPv4.
+pub fn ipv6_to_ipv4(buf: &mut [u8], prefix: &Prefix) -> Result<Range<usize>, Error> {
+    let (ip, _) = IPv6::new(buf)?;
+    if ip.hop_limit() <= 1 {
+        return Err(Error::TtlExceeded);
+    }
+
+    let end = IPv6::SIZE + ip.payload_length() as usize;
+    if buf.len() < end {
+        return Err(Error::InvalidLength(end));
+    }
+
+    let source = *ip.source();
+    let destination = *ip.destination();
+    let source4 = prefix.extract(&source).ok_or(Error::Unmapped(source))?;
+    let destination4 = prefix
+        .extract(&destination)
+        .ok_or(Error::Unmapped(destination))?;
+    let tos = ip.traffic_class();
+    let ttl = ip.hop_limit() - 1;
+    let next_header = ip.next_header();
+
+    let payload = IPv6::SIZE;
+    let (protocol, start) = match next_header {
+        InetProtocol::IPV6_HEADER_HOP_BY_HOP
+        | InetProtocol::IPV6_ROUTE_HEADER
+        | InetProtocol::IPV6_FRAGMENTATION_HEADER
+        | InetProtocol::IPV6_DESTINATION_OPTIONS => {
+            return Err(Error::Unsupported(next_header));
+        }
+        InetProtocol::IPV6_ICMP => (
+            InetProtocol::ICMP,
+            icmpv6_payload_to_ipv4(buf, payload, end, prefix)?,
+        ),
+        InetProtocol::TCP | InetProtocol::UDP => {
+            let length =
+                u16::try_from(end - payload).map_err(|_| Error::InvalidLength(end - payload))?;
+            let pseudo =
+                checksum::pseudo_ipv4(&source4, &destination4, u8::from(next_header), length);
+            translate_l4(
+                &mut buf[payload..end],
+                next_header,
+                &concat::<16, 32>(&source, &destination),
+                &concat::<4, 8>(&source4, &destination4),
+                Some(pseudo),
+            );
+            (next_header, payload)
+        }
+        x => (x, payload),
+    };
+
+    let header = start - IPv4::MIN_LEN;
+    let total_length =
+        u16::try_from(end - header).map_err(|_| Error::InvalidLength(end - header))?;
+    write_ipv4(
+        &mut buf[header..start],
+        tos,
+        total_length,
+        protocol,
+        ttl,
+        &source4,
+        &destination4,
+    )?;
+
+    Ok(header..end)
+}
+
+/// Rewrites the ICMPv6 message at `buf[payload..end]` as ICMP, returning where it now starts.
+fn icmpv6_payload_to_ipv4(
+    buf: &mut [u8],
+    payload: usize,
+    end: usize,
+    prefix: &Prefix,
+) -> Result<usize, Error> {
+    if end - payload < 8 {
+        return Err(Error::InvalidLength(end - payload));
+    }
+
+    let mut header = *buf[payload..].first_chunk::<8>().unwrap();
+    let (icmp_type, code, rest) = icmpv6_to_icmp(
+        Icmpv6Type::from(header[0]),
+        header[1],
+        header[4..8].first_chunk::<4>().unwrap(),
+    )?;
+    header[0] = u8::from(icmp_type);
+    header[1] = code;
+    header[2..4].fill(0);
+    header[4..8].copy_from_slice(&rest);
+
+    let start = if icmp_type.is_error() {
+        let quoted = payload + 8;
+        let (ip, _) = IPv6::new(&buf[quoted..end])?;
+        let inner_source = *ip.source();
+        let inner_destination = *ip.destination();
+        let source4 = prefix
+            .extract(&inner_source)
+            .ok_or(Error::Unmapped(inner_source))?;
+        let destination4 = prefix
+            .extract(&inner_destination)
+            .ok_or(Error::Unmapped(inner_destination))?;
+        let tos = ip.traffic_class();
+        let ttl = ip.hop_limit();
+        let next_header = ip.next_header();
+        let total_length = ip.payload_length().saturating_add(IPv4::MIN_LEN as u16);
+
+        let inner_payload = quoted + IPv6::SIZE;
+        let protocol = match next_header {
+            InetProtocol::IPV6_ICMP => {
+                match buf.get(inner_payload).map(|t| Icmpv6Type::from(*t)) {
+                    Some(Icmpv6Type::EchoRequest) => buf[inner_payload] = 8,
+                    Some(Icmpv6Type::EchoReply) => buf[inner_payload] = 0,
+                    _ => {}
+                }
+                InetProtocol::ICMP
+            }
+            InetProtocol::TCP | InetProtocol::UDP => {
+                translate_l4(
+                    &mut buf[inner_payload..end],
+                    next_header,
+                    &concat::<16, 32>(&inner_source, &inner_destination),
+                    &concat::<4, 8>(&source4, &destination4),
+                    None,
+                );
+                next_header
+            }
+            x => x,
+        };
+
+        write_ipv4(
+            &mut buf[inner_payload - IPv4::MIN_LEN..inner_payload],
+            tos,
+            total_length,
+            protocol,
+            ttl,
+            &source4,
+            &destination4,
+        )?;
+        inner_payload - IPv4::MIN_LEN - header.len()
+    } else {
+        payload
+    };
+
+    buf[start..start + header.len()].copy_from_slice(&header);
+    let csum = checksum::finish(checksum::sum(0, &buf[start..end]));
+    buf[start + 2..start + 4].copy_from_slice(&csum.to_be_bytes());
+
+    Ok(start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::icmp::Icmp;
+    use crate::transport::icmpv6::Icmpv6;
+    use crate::transport::udp::Udp;
+
+    const V4_SOURCE: [u8; 4] = [192, 0, 2, 33];
+    const V4_DESTINATION: [u8; 4] = [198, 51, 100, 7];
+
+    fn ipv4(buf: &mut [u8], protocol: InetProtocol, l4: &[u8]) -> usize {
+        let len = IPv4::MIN_LEN + l4.len();
+        write_ipv4(
+            &mut buf[..IPv4::MIN_LEN],
+            0x02,
+            u16::try_from(len).unwrap(),
+            protocol,
+            64,
+            &V4_SOURCE,
+            &V4_DESTINATION,
+        )
+        .unwrap();
+        buf[IPv4::MIN_LEN..len].copy_from_slice(l4);
+        len
+    }
+
+    fn udp_csum_v4(packet: &[u8]) -> u16 {
+        let (ip, l4) = IPv4::new(packet).unwrap();
+        let mut copy = [0; 64];
+        copy[..l4.len()].copy_from_slice(l4);
+        copy[6..8].fill(0);
+        let sum = checksum::pseudo_ipv4(ip.source(), ip.destination(), 17, l4.len() as u16);
+        checksum::finish(checksum::sum(sum, &copy[..l4.len()]))
+    }
+
+    fn udp_csum_v6(packet: &[u8]) -> u16 {
+        let (ip, l4) = IPv6::new(packet).unwrap();
+        let mut copy = [0; 64];
+        copy[..l4.len()].copy_from_slice(l4);
+        copy[6..8].fill(0);
+        let sum = checksum::pseudo_ipv6(ip.source(), ip.destination(), 17, l4.len() as u32);
+        checksum::finish(checksum::sum(sum, &copy[..l4.len()]))
+    }
+
+    #[test]
+    fn prefix() {
+        let prefix = Prefix::new(
+            [
+                0x20, 0x01, 0x0d, 0xb8, 0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            ],
+            40,
+        )
+        .unwrap();
+        let embedded = prefix.embed(&V4_SOURCE);
+        assert_eq!(
+            embedded,
+            [0x20, 0x01, 0x0d, 0xb8, 0x01, 0xc0, 0x00, 0x02, 0x00, 0x21, 0, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(prefix.extract(&embedded), Some(V4_SOURCE));
+
+        let embedded = WELL_KNOWN_PREFIX.embed(&V4_SOURCE);
+        assert_eq!(
+            embedded,
+            [0x00, 0x64, 0xff, 0x9b, 0, 0, 0, 0, 0, 0, 0, 0, 0xc0, 0x00, 0x02, 0x21]
+        );
+        assert_eq!(prefix.extract(&embedded), None);
+        assert!(Prefix::new([0; 16], 80).is_err());
+    }
+
+    #[test]
+    fn udp_roundtrip() {
+        let mut buf = [0; HEADROOM + 32];
+        let len = ipv4(
+            &mut buf[HEADROOM..],
+            InetProtocol::UDP,
+            &[0x13, 0x88, 0x00, 0x35, 0x00, 0x0c, 0x00, 0x00, 1, 2, 3, 4],
+        );
+        let csum = udp_csum_v4(&buf[HEADROOM..HEADROOM + len]);
+        buf[HEADROOM + 26..HEADROOM + 28].copy_from_slice(&csum.to_be_bytes());
+
+        let range = ipv4_to_ipv6(&mut buf, &WELL_KNOWN_PREFIX).unwrap();
+        assert_eq!(range, HEADROOM - 20..HEADROOM + len);
+
+        let packet = &mut buf[range];
+        let (ip, l4) = IPv6::new(packet).unwrap();
+        assert_eq!(ip.source(), &WELL_KNOWN_PREFIX.embed(&V4_SOURCE));
+        assert_eq!(ip.next_header(), InetProtocol::UDP);
+        assert_eq!(ip.payload_length(), 12);
+        assert_eq!(ip.hop_limit(), 63);
+        assert_eq!(ip.ecn(), 0b10);
+        assert_eq!(Udp::new(l4).unwrap().0.checksum_u16(), udp_csum_v6(packet));
+
+        let range = ipv6_to_ipv4(packet, &WELL_KNOWN_PREFIX).unwrap();
+        let packet = &packet[range];
+        let (ip, l4) = IPv4::new(packet).unwrap();
+        assert_eq!(ip.source(), &V4_SOURCE);
+        assert_eq!(ip.destination(), &V4_DESTINATION);
+        assert_eq!(ip.total_length(), 32);
+        assert_eq!(ip.ttl(), 62);
+        assert_eq!(ip.csum(), ip.calc_csum());
+        assert_eq!(Udp::new(l4).unwrap().0.checksum_u16(), csum);
+    }
+
+    #[test]
+    fn udp_zero_checksum_is_computed() {
+        let mut buf = [0; HEADROOM + 28];
+        ipv4(
+            &mut buf[HEADROOM..],
+            InetProtocol::UDP,
+            &[0x13, 0x88, 0x00, 0x35, 0x00, 0x08, 0x00, 0x00],
+        );
+
+        let range = ipv4_to_ipv6(&mut buf, &WELL_KNOWN_PREFIX).unwrap();
+        let (_, l4) = IPv6::new(&buf[range.clone()]).unwrap();
+        assert_eq!(
+            Udp::new(l4).unwrap().0.checksum_u16(),
+            udp_csum_v6(&buf[range])
+        );
+    }
+
+    #[test]
+    fn oversized_translation_is_rejected() {
+        let mut buf = [0; IPv6::SIZE + 0xffff];
+        write_ipv6(
+            &mut buf[..IPv6::SIZE],
+            0,
+            0xffff,
+            InetProtocol::UDP,
+            64,
+            &WELL_KNOWN_PREFIX.embed(&V4_SOURCE),
+            &WELL_KNOWN_PREFIX.embed(&V4_DESTINATION),
+        )
+        .unwrap();
+        assert!(matches!(
+            ipv6_to_ipv4(&mut buf, &WELL_KNOWN_PREFIX),
+            Err(Error::InvalidLength(0x10013))
+        ));
+    }
+
+    #[test]
+    fn echo_request() {
+        let mut buf = [0; HEADROOM + 32];
+        let mut echo = [8, 0, 0, 0, 0x12, 0x34, 0x00, 0x01, 0x61, 0x62, 0x63, 0x64];
+        let csum = Icmp::new(&echo).unwrap().0.calc_csum(&echo[8..]);
+        echo[2..4].copy_from_slice(&csum.to_be_bytes());
+        ipv4(&mut buf[HEADROOM..], InetProtocol::ICMP, &echo);
+
+        let range = ipv4_to_ipv6(&mut buf, &WELL_KNOWN_PREFIX).unwrap();
+        let (ip, l4) = IPv6::new(&buf[range]).unwrap();
+        assert_eq!(ip.next_header(), InetProtocol::IPV6_ICMP);
+
+        let (icmp, payload) = Icmpv6::new(l4).unwrap();
+        assert_eq!(icmp.icmp_type(), Icmpv6Type::EchoRequest);
+        assert_eq!(icmp.identifier(), 0x1234);
+        assert_eq!(
+            icmp.csum(),
+            icmp.calc_csum(ip.source(), ip.destination(), payload)
+        );
+    }
+
+    #[test]
+    fn fragmentation_needed_mtu() {
+        let mtu = |mtu: u16, quoted_length| {
+            let [high, low] = mtu.to_be_bytes();
+            let (icmp_type, code, rest) = icmp_to_icmpv6(
+                IcmpType::DestinationUnreachable,
+                4,
+                &[0, 0, high, low],
+                quoted_length,
+            )
+            .unwrap();
+            assert_eq!((icmp_type, code), (Icmpv6Type::PacketTooBig, 0));
+            u32::from_be_bytes(rest)
+        };
+        assert_eq!(mtu(1400, 1500), 1420);
+        assert_eq!(mtu(1000, 1500), 1280);
+        // Pre RFC 1191 routers send no MTU, the next plateau down is used.
+        assert_eq!(mtu(0, 1500), 1512);
+        assert_eq!(mtu(0, 9000), 8186);
+        assert_eq!(mtu(0, 1492), 1280);
+        assert_eq!(mtu(0, 0), 1280);
+    }
+
+    #[test]
+    fn port_unreachable_roundtrip() {
+        // 198.51.100.7 reports that 192.0.2.33:5000 -> 198.51.100.7:53 hit a closed port.
+        let mut quoted = [0; 28];
+        let quoted_len = ipv4(
+            &mut quoted,
+            InetProtocol::UDP,
+            &[0x13, 0x88, 0x00, 0x35, 0x00, 0x08, 0x00, 0x00],
+        );
+        let csum = udp_csum_v4(&quoted);
+        quoted[26..28].copy_from_slice(&csum.to_be_bytes());
+
+        let mut icmp = [0; 36];
+        icmp[0] = 3;
+        icmp[1] = 3;
+        icmp[8..8 + quoted_len].copy_from_slice(&quoted);
+        let csum = Icmp::new(&icmp).unwrap().0.calc_csum(&icmp[8..]);
+        icmp[2..4].copy_from_slice(&csum.to_be_bytes());
+
+        let mut buf = [0; HEADROOM + 56];
+        let len = ipv4(&mut buf[HEADROOM..], InetProtocol::ICMP, &icmp);
+        {
+            let (mut ip, _) = IPv4::new_mut(&mut buf[HEADROOM..]).unwrap();
+            ip.set_source(&V4_DESTINATION);
+            ip.set_destination(&V4_SOURCE);
+            ip.update_csum();
+        }
+        let original = buf;
+
+        let range = ipv4_to_ipv6(&mut buf, &WELL_KNOWN_PREFIX).unwrap();
+        assert_eq!(range.len(), len + 2 * 20);
+
+        let (ip, l4) = IPv6::new(&buf[range.clone()]).unwrap();
+        assert_eq!(ip.payload_length(), 56);
+        let (icmp, payload) = Icmpv6::new(l4).unwrap();
+        assert_eq!(icmp.icmp_type(), Icmpv6Type::DestinationUnreachable);
+        assert_eq!(icmp.code(), 4);
+        assert_eq!(
+            icmp.csum(),
+            icmp.calc_csum(ip.source(), ip.destination(), payload)
+        );
+
+        let (inner, _) = IPv6::new(payload).unwrap();
+        assert_eq!(inner.source(), &WELL_KNOWN_PREFIX.embed(&V4_SOURCE));
+        assert_eq!(inner.next_header(), InetProtocol::UDP);
+        assert_eq!(inner.payload_length(), 8);
+        assert_eq!(
+            udp_csum_v6(payload),
+            u16::from_be_bytes([payload[46], payload[47]])
+        );
+
+        let start = range.start;
+        let back = ipv6_to_ipv4(&mut buf[range], &WELL_KNOWN_PREFIX).unwrap();
+        let packet = &buf[start + back.start..start + back.end];
+        assert_eq!(packet.len(), len);
+
+        let (ip, l4) = IPv4::new(packet).unwrap();
+        assert_eq!(ip.csum(), ip.calc_csum());
+        let (icmp, payload) = Icmp::new(l4).unwrap();
+        assert_eq!(icmp.icmp_type(), IcmpType::DestinationUnreachable);
+        assert_eq!(icmp.code(), 3);
+        assert_eq!(icmp.csum(), icmp.calc_csum(payload));
+        assert_eq!(payload, &original[HEADROOM + 28..HEADROOM + len]);
+    }
+}
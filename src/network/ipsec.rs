@@ -0,0 +1,344 @@
+use super::ipnum::InetProtocol;
+
+/// Encapsulating Security Payload header \[[RFC4303](https://datatracker.ietf.org/doc/html/rfc4303)\].
+///
+/// Only the SPI and sequence number are in the clear, everything after them
+/// is returned as an opaque payload.
+pub struct Esp<P = ()> {
+    slice: P,
+}
+
+/// Authentication Header \[[RFC4302](https://datatracker.ietf.org/doc/html/rfc4302)\].
+pub struct Ah<P = ()> {
+    slice: P,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidSize(usize),
+    InvalidSizeForLength(usize, usize),
+}
+
+impl Esp<()> {
+    pub const MIN_LEN: usize = 8;
+}
+
+impl<'pkt> Esp<&'pkt [u8]> {
+    pub fn new(slice: &'pkt [u8]) -> Result<(Self, &'pkt [u8]), Error> {
+        if slice.len() < Esp::MIN_LEN {
+            return Err(Error::InvalidSize(slice.len()));
+        }
+
+        let (slice, rem) = slice.split_at(Esp::MIN_LEN);
+        Ok((Self { slice }, rem))
+    }
+}
+
+impl<'pkt> Esp<&'pkt mut [u8]> {
+    pub fn new_mut(slice: &'pkt mut [u8]) -> Result<(Self, &'pkt mut [u8]), Error> {
+        if slice.len() < Esp::MIN_LEN {
+            return Err(Error::InvalidSize(slice.len()));
+        }
+
+        let (slice, rem) = slice.split_at_mut(Esp::MIN_LEN);
+        Ok((Self { slice }, rem))
+    }
+}
+
+impl<P: AsMut<[u8]> + AsRef<[u8]>> Esp<P> {
+    pub fn set_spi(&mut self, spi: u32) {
+        self.slice.as_mut()[0..4].copy_from_slice(&spi.to_be_bytes())
+    }
+
+    pub fn set_sequence_num(&mut self, sequence_num: u32) {
+        self.slice.as_mut()[4..8].copy_from_slice(&sequence_num.to_be_bytes())
+    }
+}
+
+impl<P: AsRef<[u8]>> Esp<P> {
+    pub fn spi(&self) -> u32 {
+        u32::from_be_bytes(*self.slice.as_ref()[0..4].first_chunk::<4>().unwrap())
+    }
+
+    pub fn sequence_num(&self) -> u32 {
+        u32::from_be_bytes(*self.slice.as_ref()[4..8].first_chunk::<4>().unwrap())
+    }
+
+    pub fn slice(&self) -> &[u8] {
+        self.slice.as_ref()
+    }
+}
+
+impl Ah<()> {
+    pub const MIN_LEN: usize = 12;
+}
+
+fn ah_len(slice: &[u8]) -> Result<usize, Error> {
+    if slice.len() < Ah::MIN_LEN {
+        return Err(Error::InvalidSize(slice.len()));
+    }
+
+    let len = (slice[1] as usize + 2) * 4;
+    if len < Ah::MIN_LEN || slice.len() < len {
+        return Err(Error::InvalidSizeForLength(slice.len(), len));
+    }
+
+    Ok(len)
+}
+
+impl<'pkt> Ah<&'pkt [u8]> {
+    pub fn new(slice: &'pkt [u8]) -> Result<(Self, &'pkt [u8]), Error> {
+        let len = ah_len(slice)?;
+
+        let (slice, rem) = slice.split_at(len);
+        Ok((Self { slice }, rem))
+    }
+}
+
+impl<'pkt> Ah<&'pkt mut [u8]> {
+    pub fn new_mut(slice: &'pkt mut [u8]) -> Result<(Self, &'pkt mut [u8]), Error> {
+        let len = ah_len(slice)?;
+
+        let (slice, rem) = slice.split_at_mut(len);
+        Ok((Self { slice }, rem))
+    }
+}
+
+impl<P: AsMut<[u8]> + AsRef<[u8]>> Ah<P> {
+    pub fn set_next_header(&mut self, protocol: InetProtocol) {
+        self.slice.as_mut()[0] = u8::from(protocol);
+    }
+
+    pub fn set_spi(&mut self, spi: u32) {
+        self.slice.as_mut()[4..8].copy_from_slice(&spi.to_be_bytes())
+    }
+
+    pub fn set_sequence_num(&mut self, sequence_num: u32) {
+        self.slice.as_mut()[8..12].copy_from_slice(&sequence_num.to_be_bytes())
+    }
+
+    pub fn icv_mut(&mut self) -> &mut [u8] {
+        &mut self.slice.as_mut()[Ah::MIN_LEN..]
+    }
+}
+
+impl<P: AsRef<[u8]>> Ah<P> {
+    pub fn next_header(&self) -> InetProtocol {
+        InetProtocol::from(self.slice.as_ref()[0])
+    }
+
+    /// Length of the header in 32-bit words, minus 2.
+    pub fn payload_len(&self) -> u8 {
+        self.slice.as_ref()[1]
+    }
+
+    pub fn spi(&self) -> u32 {
+        u32::from_be_bytes(*self.slice.as_ref()[4..8].first_chunk::<4>().unwrap())
+    }
+
+    pub fn sequence_num(&self) -> u32 {
+        u32::from_be_bytes(*self.slice.as_ref()[8..12].first_chunk::<4>().unwrap())
+    }
+
+    /// Integrity Check Value, including any padding up to the header length.
+    pub fn icv(&self) -> &[u8] {
+        &self.slice.as_ref()[Ah::MIN_LEN..]
+    }
+
+    pub fn size_usize(&self) -> usize {
+        self.slice.as_ref().len()
+    }
+
+    pub fn slice(&self) -> &[u8] {
+        self.slice.as_ref()
+    }
+}
+
+/// Owned ESP header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct EspRepr {
+    pub spi: u32,
+    pub sequence_num: u32,
+}
+
+impl<P: AsRef<[u8]>> From<&Esp<P>> for EspRepr {
+    fn from(esp: &Esp<P>) -> Self {
+        Self {
+            spi: esp.spi(),
+            sequence_num: esp.sequence_num(),
+        }
+    }
+}
+
+impl EspRepr {
+    /// Writes the header to the start of `slice`.
+    pub fn emit<'pkt>(
+        &self,
+        slice: &'pkt mut [u8],
+    ) -> Result<(Esp<&'pkt mut [u8]>, &'pkt mut [u8]), Error> {
+        let (mut esp, rem) = Esp::new_mut(slice)?;
+        esp.set_spi(self.spi);
+        esp.set_sequence_num(self.sequence_num);
+        Ok((esp, rem))
+    }
+}
+
+/// Owned Authentication Header, the ICV is not kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct AhRepr {
+    pub next_header: InetProtocol,
+    /// Length of the header in 32-bit words, minus 2.
+    pub payload_len: u8,
+    pub spi: u32,
+    pub sequence_num: u32,
+}
+
+impl<P: AsRef<[u8]>> From<&Ah<P>> for AhRepr {
+    fn from(ah: &Ah<P>) -> Self {
+        Self {
+            next_header: ah.next_header(),
+            payload_len: ah.payload_len(),
+            spi: ah.spi(),
+            sequence_num: ah.sequence_num(),
+        }
+    }
+}
+
+impl AhRepr {
+    /// Writes the header to the start of `slice`, sized by `payload_len`.
+    /// The ICV bytes are left as they are, see `Ah::icv_mut`.
+    pub fn emit<'pkt>(
+        &self,
+        slice: &'pkt mut [u8],
+    ) -> Result<(Ah<&'pkt mut [u8]>, &'pkt mut [u8]), Error> {
+        if slice.len() < Ah::MIN_LEN {
+            return Err(Error::InvalidSize(slice.len()));
+        }
+        let len = (usize::from(self.payload_len) + 2) * 4;
+        if len < Ah::MIN_LEN || slice.len() < len {
+            return Err(Error::InvalidSizeForLength(slice.len(), len));
+        }
+        slice[1] = self.payload_len;
+        slice[2..4].fill(0);
+        let (mut ah, rem) = Ah::new_mut(slice)?;
+        ah.set_next_header(self.next_header);
+        ah.set_spi(self.spi);
+        ah.set_sequence_num(self.sequence_num);
+        Ok((ah, rem))
+    }
+}
+
+/// UDP port used for NAT traversal \[[RFC3948](https://datatracker.ietf.org/doc/html/rfc3948)\].
+pub const NAT_T_PORT: u16 = 4500;
+
+/// Contents of a UDP datagram on the NAT-T port.
+pub enum NatT<'pkt> {
+    /// Single `0xFF` byte sent to keep NAT bindings alive.
+    Keepalive,
+    /// IKE message, with the 4 byte non-ESP marker stripped.
+    Ike(&'pkt [u8]),
+    /// ESP packet and its opaque payload.
+    Esp(Esp<&'pkt [u8]>, &'pkt [u8]),
+}
+
+impl<'pkt> NatT<'pkt> {
+    /// Classifies the payload of a UDP datagram, `None` if neither port is
+    /// `NAT_T_PORT` or the payload can't be either IKE or ESP.
+    pub fn new(source: u16, destination: u16, payload: &'pkt [u8]) -> Option<Self> {
+        if source != NAT_T_PORT && destination != NAT_T_PORT {
+            return None;
+        }
+
+        match payload {
+            [0xFF] => Some(NatT::Keepalive),
+            [0, 0, 0, 0, ike @ ..] => Some(NatT::Ike(ike)),
+            _ => Esp::new(payload).ok().map(|(esp, rem)| NatT::Esp(esp, rem)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn esp() {
+        let packet = [
+            0x00, 0x00, 0x10, 0x01, 0x00, 0x00, 0x00, 0x2a, 0xaa, 0xbb, 0xcc,
+        ];
+        let (esp, rem) = Esp::new(&packet).unwrap();
+        assert_eq!(esp.spi(), 0x1001);
+        assert_eq!(esp.sequence_num(), 42);
+        assert_eq!(rem, &[0xaa, 0xbb, 0xcc]);
+    }
+
+    #[test]
+    fn ah() {
+        let packet = [
+            0x06, 0x04, 0x00, 0x00, 0x00, 0x00, 0x10, 0x01, 0x00, 0x00, 0x00, 0x01, 0x01, 0x02,
+            0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0xff,
+        ];
+        let (ah, rem) = Ah::new(&packet).unwrap();
+        assert_eq!(ah.next_header(), InetProtocol::TCP);
+        assert_eq!(ah.size_usize(), 24);
+        assert_eq!(ah.spi(), 0x1001);
+        assert_eq!(ah.sequence_num(), 1);
+        assert_eq!(ah.icv().len(), 12);
+        assert_eq!(rem, &[0xff]);
+
+        assert!(Ah::new(&packet[..20]).is_err());
+    }
+
+    #[test]
+    fn repr_round_trip() {
+        let packet = [0x00, 0x00, 0x10, 0x01, 0x00, 0x00, 0x00, 0x2a];
+        let (esp, _) = Esp::new(&packet).unwrap();
+        let repr = EspRepr::from(&esp);
+        let mut buf = [0xff; 8];
+        assert_eq!(repr.emit(&mut buf).unwrap().0.slice(), packet);
+
+        let packet = [
+            0x06, 0x02, 0x00, 0x00, 0x00, 0x00, 0x10, 0x01, 0x00, 0x00, 0x00, 0x01, 0xaa, 0xbb,
+            0xcc, 0xdd,
+        ];
+        let (ah, _) = Ah::new(&packet).unwrap();
+        let repr = AhRepr::from(&ah);
+        assert_eq!(repr.next_header, InetProtocol::TCP);
+        let mut buf = [0xff; 17];
+        let (mut emitted, rem) = repr.emit(&mut buf).unwrap();
+        emitted.icv_mut().copy_from_slice(&[0xaa, 0xbb, 0xcc, 0xdd]);
+        assert_eq!(emitted.slice(), packet);
+        assert_eq!(rem.len(), 1);
+        let mut short = [0xff; 12];
+        assert!(matches!(
+            repr.emit(&mut short),
+            Err(Error::InvalidSizeForLength(12, 16))
+        ));
+        assert_eq!(short, [0xff; 12]);
+    }
+
+    #[test]
+    fn nat_t() {
+        assert!(matches!(
+            NatT::new(4500, 4500, &[0xFF]),
+            Some(NatT::Keepalive)
+        ));
+        assert!(matches!(
+            NatT::new(4500, 4500, &[0, 0, 0, 0, 1, 2]),
+            Some(NatT::Ike(&[1, 2]))
+        ));
+        match NatT::new(
+            51514,
+            4500,
+            &[0x00, 0x00, 0x10, 0x01, 0x00, 0x00, 0x00, 0x01],
+        ) {
+            Some(NatT::Esp(esp, _)) => assert_eq!(esp.spi(), 0x1001),
+            _ => panic!("expected ESP"),
+        }
+        assert!(NatT::new(53, 53, &[0xFF]).is_none());
+    }
+}
@@ -1,4 +1,6 @@
 use super::ipnum::InetProtocol;
+use crate::{checksum, field};
+use core::net::Ipv4Addr;
 
 pub struct IPv4<P = ()> {
     slice: P,
@@ -11,17 +13,17 @@ impl<'pkt> IPv4<&'pkt [u8]> {
             return Err(Error::InvalidSize(slice.len()));
         }
 
-        let size = IPv4Size::try_from_ihl_u8(slice[0] & 0xF).map_err(Error::InvalidIhl)?;
+        let first = field::read_u8(slice, 0);
+        let size = IPv4Size::try_from_ihl_u8(first & 0xF).map_err(Error::InvalidIhl)?;
 
-        if slice[0] >> 4 != 4 {
-            return Err(Error::InvalidVersion(slice[0] >> 4));
+        if first >> 4 != 4 {
+            return Err(Error::InvalidVersion(first >> 4));
         }
 
-        if slice.len() < size as usize {
-            return Err(Error::InvalidSizeForIhl(slice.len(), size));
-        }
-
-        let (slice, rem) = slice.split_at(size as usize);
+        let len = slice.len();
+        let (slice, rem) = slice
+            .split_at_checked(size as usize)
+            .ok_or(Error::InvalidSizeForIhl(len, size))?;
         Ok((Self { slice, size }, rem))
     }
 }
@@ -32,17 +34,17 @@ impl<'pkt> IPv4<&'pkt mut [u8]> {
             return Err(Error::InvalidSize(slice.len()));
         }
 
-        let size = IPv4Size::try_from_ihl_u8(slice[0] & 0xF).map_err(Error::InvalidIhl)?;
+        let first = field::read_u8(slice, 0);
+        let size = IPv4Size::try_from_ihl_u8(first & 0xF).map_err(Error::InvalidIhl)?;
 
-        if slice[0] >> 4 != 4 {
-            return Err(Error::InvalidVersion(slice[0] >> 4));
+        if first >> 4 != 4 {
+            return Err(Error::InvalidVersion(first >> 4));
         }
 
-        if slice.len() < size as usize {
-            return Err(Error::InvalidSizeForIhl(slice.len(), size));
-        }
-
-        let (slice, rem) = slice.split_at_mut(size as usize);
+        let len = slice.len();
+        let (slice, rem) = slice
+            .split_at_mut_checked(size as usize)
+            .ok_or(Error::InvalidSizeForIhl(len, size))?;
         Ok((Self { slice, size }, rem))
     }
 }
@@ -52,6 +54,7 @@ pub enum IhlError {
     InvalidIhl(u8),
 }
 
+#[derive(Debug)]
 pub enum Error {
     InvalidIhl(IhlError),
     InvalidSize(usize),
@@ -61,43 +64,83 @@ pub enum Error {
 
 impl<P: AsMut<[u8]> + AsRef<[u8]>> IPv4<P> {
     pub fn set_csum(&mut self, csum: u16) {
-        self.slice.as_mut()[10..12].copy_from_slice(&csum.to_be_bytes());
+        field::write(self.slice.as_mut(), 10, &csum.to_be_bytes());
     }
 
     pub fn update_csum(&mut self) {
         self.set_csum(self.calc_csum())
     }
 
+    /// Swaps source and destination addresses, the header checksum stays
+    /// valid as the ones' complement sum does not depend on word order.
+    pub fn reflect(&mut self) {
+        let source = *self.source();
+        let destination = *self.destination();
+        self.set_source(&destination);
+        self.set_destination(&source);
+    }
+
     pub fn slice_mut(&mut self) -> &mut [u8] {
         self.slice.as_mut()
     }
 
     pub fn set_source(&mut self, source: &[u8; 4]) {
-        self.slice.as_mut()[12..16].copy_from_slice(source)
+        field::write(self.slice.as_mut(), 12, source)
     }
 
     pub fn set_source_u32(&mut self, source: u32) {
-        self.slice.as_mut()[12..16].copy_from_slice(&source.to_be_bytes())
+        field::write(self.slice.as_mut(), 12, &source.to_be_bytes())
     }
 
     pub fn set_destination_u32(&mut self, destination: u32) {
-        self.slice.as_mut()[16..20].copy_from_slice(&destination.to_be_bytes())
+        field::write(self.slice.as_mut(), 16, &destination.to_be_bytes())
     }
 
     pub fn set_destination(&mut self, destination: &[u8; 4]) {
-        self.slice.as_mut()[16..20].copy_from_slice(destination)
+        field::write(self.slice.as_mut(), 16, destination)
     }
 
     pub fn set_total_length(&mut self, value: &[u8; 2]) {
-        self.slice.as_mut()[2..4].copy_from_slice(value)
+        field::write(self.slice.as_mut(), 2, value)
     }
 
     pub fn set_total_length_u16(&mut self, value: u16) {
-        self.slice.as_mut()[2..4].copy_from_slice(&value.to_be_bytes())
+        field::write(self.slice.as_mut(), 2, &value.to_be_bytes())
     }
 
     pub fn set_protocol(&mut self, protocol: InetProtocol) {
-        self.slice.as_mut()[9] = u8::from(protocol);
+        field::write_u8(self.slice.as_mut(), 9, u8::from(protocol));
+    }
+
+    pub fn set_ttl(&mut self, ttl: u8) {
+        field::write_u8(self.slice.as_mut(), 8, ttl);
+    }
+
+    pub fn set_dscp(&mut self, dscp: u8) {
+        let slice = self.slice.as_mut();
+        let ecn = field::read_u8(slice, 1) & 0b11;
+        field::write_u8(slice, 1, (dscp << 2) | ecn);
+    }
+
+    pub fn set_ecn(&mut self, ecn: u8) {
+        let slice = self.slice.as_mut();
+        let dscp = field::read_u8(slice, 1) & !0b11;
+        field::write_u8(slice, 1, dscp | (ecn & 0b11));
+    }
+
+    pub fn set_identification(&mut self, identification: u16) {
+        field::write(self.slice.as_mut(), 4, &identification.to_be_bytes())
+    }
+
+    pub fn set_dont_fragment(&mut self, dont_fragment: bool) {
+        let slice = self.slice.as_mut();
+        let flags = field::read_u8(slice, 6);
+        let flags = if dont_fragment {
+            flags | (1 << 6)
+        } else {
+            flags & !(1 << 6)
+        };
+        field::write_u8(slice, 6, flags);
     }
 }
 
@@ -108,113 +151,93 @@ impl IPv4<()> {
 
 impl<P: AsRef<[u8]>> IPv4<P> {
     pub fn csum(&self) -> u16 {
-        u16::from_be_bytes(*self.slice.as_ref()[10..12].first_chunk::<2>().unwrap())
+        field::read_u16(self.slice.as_ref(), 10)
     }
 
     pub fn calc_csum(&self) -> u16 {
-        etherparse::checksum::Sum16BitWords::new()
-            .add_2bytes([(4 << 4) | self.ihl_u8(), (self.dscp() << 2) | self.ecn()])
-            .add_2bytes(self.total_length().to_be_bytes())
-            .add_2bytes(self.identification().to_be_bytes())
-            .add_2bytes({
-                let frag_off_be = self.fragment_offset();
-                let flags = {
-                    let mut result = 0;
-                    if self.dont_fragment() {
-                        result |= 64;
-                    }
-                    if self.more_fragments() {
-                        result |= 32;
-                    }
-                    result
-                };
-                [flags | (frag_off_be[0] & 0x1f), frag_off_be[1]]
-            })
-            .add_2bytes([self.ttl(), self.protocol_u8()])
-            .add_4bytes(*self.source())
-            .add_4bytes(*self.destination())
-            .add_slice(self.options())
-            .ones_complement()
-            .to_be()
+        let slice = self.slice.as_ref();
+        let sum = checksum::sum(0, field::range(slice, 0, 10));
+        checksum::finish(checksum::sum(
+            sum,
+            field::range(slice, 12, self.size as usize),
+        ))
     }
 
     pub fn slice(&self) -> &[u8] {
-        &self.slice.as_ref()
+        self.slice.as_ref()
     }
 
     pub fn version(&self) -> u8 {
-        self.slice.as_ref()[0] >> 4
+        field::read_u8(self.slice.as_ref(), 0) >> 4
     }
 
     pub fn source(&self) -> &[u8; 4] {
-        self.slice.as_ref()[12..16].first_chunk::<4>().unwrap()
+        field::array(self.slice.as_ref(), 12).unwrap_or(&[0; 4])
     }
 
     pub fn source_u32(&self) -> u32 {
-        u32::from_be_bytes(*self.slice.as_ref()[12..16].first_chunk::<4>().unwrap())
+        field::read_u32(self.slice.as_ref(), 12)
     }
 
     pub fn destination_u32(&self) -> u32 {
-        u32::from_be_bytes(*self.slice.as_ref()[16..20].first_chunk::<4>().unwrap())
+        field::read_u32(self.slice.as_ref(), 16)
     }
 
     pub fn destination(&self) -> &[u8; 4] {
-        self.slice.as_ref()[16..20].first_chunk::<4>().unwrap()
+        field::array(self.slice.as_ref(), 16).unwrap_or(&[0; 4])
     }
 
     pub fn ttl(&self) -> u8 {
-        self.slice.as_ref()[8]
+        field::read_u8(self.slice.as_ref(), 8)
     }
 
     pub fn options(&self) -> &[u8] {
-        &self.slice.as_ref()[IPv4::MIN_LEN..self.size as usize]
+        field::range(self.slice.as_ref(), IPv4::MIN_LEN, self.size as usize)
     }
 
     pub fn dscp(&self) -> u8 {
-        self.slice.as_ref()[1] >> 2
+        field::read_u8(self.slice.as_ref(), 1) >> 2
     }
 
     pub fn ecn(&self) -> u8 {
-        self.slice.as_ref()[1] & 0b11
+        field::read_u8(self.slice.as_ref(), 1) & 0b11
     }
 
     pub fn total_length(&self) -> u16 {
-        u16::from_be_bytes(*self.slice.as_ref()[2..4].first_chunk::<2>().unwrap())
+        field::read_u16(self.slice.as_ref(), 2)
     }
 
     pub fn identification(&self) -> u16 {
-        u16::from_be_bytes(*self.slice.as_ref()[4..6].first_chunk::<2>().unwrap())
+        field::read_u16(self.slice.as_ref(), 4)
     }
 
     pub fn total_length_u16(&self) -> u16 {
-        u16::from_be_bytes(*self.slice.as_ref()[2..4].first_chunk::<2>().unwrap())
+        field::read_u16(self.slice.as_ref(), 2)
     }
 
     pub fn fragment_offset(&self) -> [u8; 2] {
-        let mut res = [0, 0];
-        res[0] = self.slice.as_ref()[6] & 0b11111;
-        res[1] = self.slice.as_ref()[7];
-        res
+        let slice = self.slice.as_ref();
+        [field::read_u8(slice, 6) & 0b11111, field::read_u8(slice, 7)]
     }
 
     pub fn dont_fragment(&self) -> bool {
-        (self.slice.as_ref()[6] >> 6) & 0b01 == 1
+        (field::read_u8(self.slice.as_ref(), 6) >> 6) & 0b01 == 1
     }
 
     pub fn more_fragments(&self) -> bool {
-        (self.slice.as_ref()[6] >> 5) & 0b001 == 1
+        (field::read_u8(self.slice.as_ref(), 6) >> 5) & 0b001 == 1
     }
 
     pub fn protocol(&self) -> InetProtocol {
-        InetProtocol::from(self.slice.as_ref()[9])
+        InetProtocol::from(field::read_u8(self.slice.as_ref(), 9))
     }
 
     pub fn protocol_u8(&self) -> u8 {
-        self.slice.as_ref()[9]
+        field::read_u8(self.slice.as_ref(), 9)
     }
 
     pub fn ihl_u8(&self) -> u8 {
-        self.slice.as_ref()[0] & 0xF
+        field::read_u8(self.slice.as_ref(), 0) & 0xF
     }
 
     pub fn size(&self) -> IPv4Size {
@@ -264,3 +287,120 @@ impl IPv4Size {
         })
     }
 }
+
+/// Owned IPv4 header, options are not kept and `emit` writes a 20 byte
+/// header. The checksum is copied as is, see `IPv4::update_csum`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct IPv4Repr {
+    pub dscp: u8,
+    pub ecn: u8,
+    pub total_length: u16,
+    pub identification: u16,
+    pub dont_fragment: bool,
+    pub more_fragments: bool,
+    /// In 8 byte units.
+    pub fragment_offset: u16,
+    pub ttl: u8,
+    pub protocol: InetProtocol,
+    pub checksum: u16,
+    pub source: Ipv4Addr,
+    pub destination: Ipv4Addr,
+}
+
+impl<P: AsRef<[u8]>> From<&IPv4<P>> for IPv4Repr {
+    fn from(ip: &IPv4<P>) -> Self {
+        Self {
+            dscp: ip.dscp(),
+            ecn: ip.ecn(),
+            total_length: ip.total_length(),
+            identification: ip.identification(),
+            dont_fragment: ip.dont_fragment(),
+            more_fragments: ip.more_fragments(),
+            fragment_offset: u16::from_be_bytes(ip.fragment_offset()),
+            ttl: ip.ttl(),
+            protocol: ip.protocol(),
+            checksum: ip.csum(),
+            source: Ipv4Addr::from(*ip.source()),
+            destination: Ipv4Addr::from(*ip.destination()),
+        }
+    }
+}
+
+impl IPv4Repr {
+    /// Writes the header to the start of `slice`.
+    pub fn emit<'pkt>(
+        &self,
+        slice: &'pkt mut [u8],
+    ) -> Result<(IPv4<&'pkt mut [u8]>, &'pkt mut [u8]), Error> {
+        if slice.len() < IPv4::MIN_LEN {
+            return Err(Error::InvalidSize(slice.len()));
+        }
+        let flags = u16::from(self.dont_fragment) << 14
+            | u16::from(self.more_fragments) << 13
+            | self.fragment_offset & 0x1fff;
+        field::write_u8(slice, 0, 0x45);
+        field::write(slice, 6, &flags.to_be_bytes());
+        field::write_u8(slice, 9, u8::from(self.protocol));
+        let (mut ip, rem) = IPv4::new_mut(slice)?;
+        ip.set_dscp(self.dscp);
+        ip.set_ecn(self.ecn);
+        ip.set_total_length_u16(self.total_length);
+        ip.set_identification(self.identification);
+        ip.set_ttl(self.ttl);
+        ip.set_csum(self.checksum);
+        ip.set_source(&self.source.octets());
+        ip.set_destination(&self.destination.octets());
+        Ok((ip, rem))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csum() {
+        let packet = [
+            0x45, 0x00, 0x00, 0x73, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11, 0xb8, 0x61, 0xc0, 0xa8,
+            0x00, 0x01, 0xc0, 0xa8, 0x00, 0xc7,
+        ];
+        let (ip, rem) = IPv4::new(&packet).unwrap();
+        assert!(rem.is_empty());
+        assert_eq!(ip.calc_csum(), 0xb861);
+        assert_eq!(ip.csum(), 0xb861);
+        assert_eq!(ip.fragment_offset(), [0, 0]);
+        assert!(ip.dont_fragment());
+        assert_eq!(ip.destination(), &[192, 168, 0, 199]);
+    }
+
+    #[test]
+    fn repr_round_trip() {
+        let packet = [
+            0x45, 0x00, 0x00, 0x73, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11, 0xb8, 0x61, 0xc0, 0xa8,
+            0x00, 0x01, 0xc0, 0xa8, 0x00, 0xc7,
+        ];
+        let (ip, _) = IPv4::new(&packet).unwrap();
+        let repr = IPv4Repr::from(&ip);
+        assert_eq!(repr.protocol, InetProtocol::UDP);
+        assert_eq!(repr.destination, Ipv4Addr::new(192, 168, 0, 199));
+        assert!(repr.dont_fragment);
+
+        let mut buf = [0; 24];
+        let (emitted, rem) = repr.emit(&mut buf).unwrap();
+        assert_eq!(emitted.slice(), packet);
+        assert_eq!(rem.len(), 4);
+        assert!(matches!(
+            repr.emit(&mut [0; 19]),
+            Err(Error::InvalidSize(19))
+        ));
+
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&repr).unwrap();
+            assert!(json.contains(r#""source":"192.168.0.1","destination":"192.168.0.199""#));
+            assert_eq!(serde_json::from_str::<IPv4Repr>(&json).unwrap(), repr);
+        }
+    }
+}
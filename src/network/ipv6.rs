@@ -0,0 +1,250 @@
+use super::ipnum::InetProtocol;
+use crate::field;
+use core::net::Ipv6Addr;
+
+pub struct IPv6<P = ()> {
+    slice: P,
+}
+
+impl<'pkt> IPv6<&'pkt [u8]> {
+    pub fn new(slice: &'pkt [u8]) -> Result<(Self, &'pkt [u8]), Error> {
+        if slice.len() < IPv6::SIZE {
+            return Err(Error::InvalidSize(slice.len()));
+        }
+
+        if slice[0] >> 4 != 6 {
+            return Err(Error::InvalidVersion(slice[0] >> 4));
+        }
+
+        let (slice, rem) = slice.split_at(IPv6::SIZE);
+        Ok((Self { slice }, rem))
+    }
+}
+
+impl<'pkt> IPv6<&'pkt mut [u8]> {
+    pub fn new_mut(slice: &'pkt mut [u8]) -> Result<(Self, &'pkt mut [u8]), Error> {
+        if slice.len() < IPv6::SIZE {
+            return Err(Error::InvalidSize(slice.len()));
+        }
+
+        if slice[0] >> 4 != 6 {
+            return Err(Error::InvalidVersion(slice[0] >> 4));
+        }
+
+        let (slice, rem) = slice.split_at_mut(IPv6::SIZE);
+        Ok((Self { slice }, rem))
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidSize(usize),
+    InvalidVersion(u8),
+}
+
+impl IPv6<()> {
+    pub const SIZE: usize = 40;
+}
+
+impl<P: AsMut<[u8]> + AsRef<[u8]>> IPv6<P> {
+    /// Swaps source and destination addresses.
+    pub fn reflect(&mut self) {
+        let source = *self.source();
+        let destination = *self.destination();
+        self.set_source(&destination);
+        self.set_destination(&source);
+    }
+
+    pub fn slice_mut(&mut self) -> &mut [u8] {
+        self.slice.as_mut()
+    }
+
+    pub fn set_traffic_class(&mut self, traffic_class: u8) {
+        let slice = self.slice.as_mut();
+        slice[0] = (6 << 4) | (traffic_class >> 4);
+        slice[1] = (traffic_class << 4) | (slice[1] & 0xF);
+    }
+
+    pub fn set_dscp(&mut self, dscp: u8) {
+        self.set_traffic_class((dscp << 2) | self.ecn())
+    }
+
+    pub fn set_ecn(&mut self, ecn: u8) {
+        self.set_traffic_class((self.dscp() << 2) | (ecn & 0b11))
+    }
+
+    pub fn set_flow_label(&mut self, flow_label: u32) {
+        let slice = self.slice.as_mut();
+        let bytes = flow_label.to_be_bytes();
+        slice[1] = (slice[1] & 0xF0) | (bytes[1] & 0xF);
+        slice[2] = bytes[2];
+        slice[3] = bytes[3];
+    }
+
+    pub fn set_payload_length(&mut self, value: u16) {
+        self.slice.as_mut()[4..6].copy_from_slice(&value.to_be_bytes())
+    }
+
+    pub fn set_next_header(&mut self, protocol: InetProtocol) {
+        self.slice.as_mut()[6] = u8::from(protocol);
+    }
+
+    pub fn set_hop_limit(&mut self, hop_limit: u8) {
+        self.slice.as_mut()[7] = hop_limit;
+    }
+
+    pub fn set_source(&mut self, source: &[u8; 16]) {
+        self.slice.as_mut()[8..24].copy_from_slice(source)
+    }
+
+    pub fn set_destination(&mut self, destination: &[u8; 16]) {
+        self.slice.as_mut()[24..40].copy_from_slice(destination)
+    }
+}
+
+impl<P: AsRef<[u8]>> IPv6<P> {
+    pub fn slice(&self) -> &[u8] {
+        self.slice.as_ref()
+    }
+
+    pub fn version(&self) -> u8 {
+        self.slice.as_ref()[0] >> 4
+    }
+
+    pub fn traffic_class(&self) -> u8 {
+        (self.slice.as_ref()[0] << 4) | (self.slice.as_ref()[1] >> 4)
+    }
+
+    pub fn dscp(&self) -> u8 {
+        self.traffic_class() >> 2
+    }
+
+    pub fn ecn(&self) -> u8 {
+        self.traffic_class() & 0b11
+    }
+
+    pub fn flow_label(&self) -> u32 {
+        u32::from_be_bytes([
+            0,
+            self.slice.as_ref()[1] & 0xF,
+            self.slice.as_ref()[2],
+            self.slice.as_ref()[3],
+        ])
+    }
+
+    pub fn payload_length(&self) -> u16 {
+        u16::from_be_bytes(*self.slice.as_ref()[4..6].first_chunk::<2>().unwrap())
+    }
+
+    pub fn next_header(&self) -> InetProtocol {
+        InetProtocol::from(self.slice.as_ref()[6])
+    }
+
+    pub fn next_header_u8(&self) -> u8 {
+        self.slice.as_ref()[6]
+    }
+
+    pub fn hop_limit(&self) -> u8 {
+        self.slice.as_ref()[7]
+    }
+
+    pub fn source(&self) -> &[u8; 16] {
+        self.slice.as_ref()[8..24].first_chunk::<16>().unwrap()
+    }
+
+    pub fn source_u128(&self) -> u128 {
+        u128::from_be_bytes(*self.source())
+    }
+
+    pub fn destination(&self) -> &[u8; 16] {
+        self.slice.as_ref()[24..40].first_chunk::<16>().unwrap()
+    }
+
+    pub fn destination_u128(&self) -> u128 {
+        u128::from_be_bytes(*self.destination())
+    }
+}
+
+/// Owned IPv6 header, extension headers are not followed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct IPv6Repr {
+    pub traffic_class: u8,
+    pub flow_label: u32,
+    pub payload_length: u16,
+    pub next_header: InetProtocol,
+    pub hop_limit: u8,
+    pub source: Ipv6Addr,
+    pub destination: Ipv6Addr,
+}
+
+impl<P: AsRef<[u8]>> From<&IPv6<P>> for IPv6Repr {
+    fn from(ip: &IPv6<P>) -> Self {
+        Self {
+            traffic_class: ip.traffic_class(),
+            flow_label: ip.flow_label(),
+            payload_length: ip.payload_length(),
+            next_header: ip.next_header(),
+            hop_limit: ip.hop_limit(),
+            source: Ipv6Addr::from(*ip.source()),
+            destination: Ipv6Addr::from(*ip.destination()),
+        }
+    }
+}
+
+impl IPv6Repr {
+    /// Writes the header to the start of `slice`.
+    pub fn emit<'pkt>(
+        &self,
+        slice: &'pkt mut [u8],
+    ) -> Result<(IPv6<&'pkt mut [u8]>, &'pkt mut [u8]), Error> {
+        if slice.len() < IPv6::SIZE {
+            return Err(Error::InvalidSize(slice.len()));
+        }
+        field::write_u8(slice, 0, 0x60);
+        let (mut ip, rem) = IPv6::new_mut(slice)?;
+        ip.set_traffic_class(self.traffic_class);
+        ip.set_flow_label(self.flow_label);
+        ip.set_payload_length(self.payload_length);
+        ip.set_next_header(self.next_header);
+        ip.set_hop_limit(self.hop_limit);
+        ip.set_source(&self.source.octets());
+        ip.set_destination(&self.destination.octets());
+        Ok((ip, rem))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repr_round_trip() {
+        let mut packet = [0; 40];
+        packet[..8].copy_from_slice(&[0x6b, 0x81, 0x23, 0x45, 0x00, 0x08, 0x11, 0x40]);
+        packet[8..24].copy_from_slice(&Ipv6Addr::LOCALHOST.octets());
+        packet[24..40].copy_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        let (ip, _) = IPv6::new(&packet).unwrap();
+        let repr = IPv6Repr::from(&ip);
+        assert_eq!(repr.traffic_class, 0xb8);
+        assert_eq!(repr.flow_label, 0x12345);
+        assert_eq!(repr.next_header, InetProtocol::UDP);
+        assert_eq!(repr.source, Ipv6Addr::LOCALHOST);
+
+        let mut buf = [0xff; 48];
+        let (emitted, rem) = repr.emit(&mut buf).unwrap();
+        assert_eq!(emitted.slice(), packet);
+        assert_eq!(rem.len(), 8);
+        let mut short = [0xff; 39];
+        assert!(matches!(repr.emit(&mut short), Err(Error::InvalidSize(39))));
+        assert_eq!(short, [0xff; 39]);
+
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&repr).unwrap();
+            assert!(json.contains(r#""source":"::1","destination":"2001:db8::1""#));
+            assert_eq!(serde_json::from_str::<IPv6Repr>(&json).unwrap(), repr);
+        }
+    }
+}
@@ -1,5 +1,9 @@
 pub use ipnum::*;
 pub use ipv4::*;
+pub use ipv6::{IPv6, IPv6Repr};
 
-pub mod ipv4;
 pub mod ipnum;
+pub mod ipsec;
+pub mod ipv4;
+pub mod ipv6;
+pub mod tunnel;
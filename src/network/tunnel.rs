@@ -0,0 +1,433 @@
+//! IP-in-IP (RFC 2003), 6in4 (RFC 4213) and IPv6 tunnel (RFC 2473)
+//! encapsulation over mutable buffers.
+//!
+//! `encap_*` expects the inner packet to start right after enough headroom for
+//! the outer header, `decap_*` leaves the buffer in place and returns the
+//! offset at which the inner header starts so the caller can drop the outer
+//! header (e.g. with `bpf_xdp_adjust_head`).
+
+use super::ipnum::InetProtocol;
+use super::ipv4::{self, IPv4};
+use super::ipv6::{self, IPv6};
+
+pub const NOT_ECT: u8 = 0b00;
+pub const ECT_1: u8 = 0b01;
+pub const ECT_0: u8 = 0b10;
+pub const CE: u8 = 0b11;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TtlPolicy {
+    /// Pipe model \[[RFC2983](https://datatracker.ietf.org/doc/html/rfc2983)\]: the outer
+    /// header gets the given TTL and the inner header is never modified.
+    Pipe(u8),
+    /// Uniform model \[[RFC2983](https://datatracker.ietf.org/doc/html/rfc2983)\]: the inner
+    /// TTL is decremented and copied to the outer header on encap, and lowered
+    /// to the outer TTL on decap.
+    Uniform,
+}
+
+/// Encapsulation modes from [RFC6040](https://datatracker.ietf.org/doc/html/rfc6040#section-4.1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EcnMode {
+    /// The ECN field is copied from the inner to the outer header.
+    Normal,
+    /// The outer header is always Not-ECT, for decapsulators that predate RFC 6040.
+    Compatibility,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    IPv4(ipv4::Error),
+    IPv6(ipv6::Error),
+    InvalidHeadroom(usize),
+    InvalidInnerVersion(u8),
+    InvalidLength(usize),
+    NotTunnel(InetProtocol),
+    TtlExceeded,
+    /// Outer header marked CE over a Not-ECT inner packet, RFC 6040 requires
+    /// the packet to be dropped.
+    EcnDrop,
+}
+
+impl From<ipv4::Error> for Error {
+    fn from(value: ipv4::Error) -> Self {
+        Error::IPv4(value)
+    }
+}
+
+impl From<ipv6::Error> for Error {
+    fn from(value: ipv6::Error) -> Self {
+        Error::IPv6(value)
+    }
+}
+
+/// ECN field of the outer header built at ingress.
+pub fn ecn_ingress(inner: u8, mode: EcnMode) -> u8 {
+    match mode {
+        EcnMode::Normal => inner & 0b11,
+        EcnMode::Compatibility => NOT_ECT,
+    }
+}
+
+/// ECN field of the inner header after egress, `None` when the packet must be dropped.
+pub fn ecn_egress(outer: u8, inner: u8) -> Option<u8> {
+    match (inner & 0b11, outer & 0b11) {
+        (NOT_ECT, CE) => None,
+        (NOT_ECT, _) => Some(NOT_ECT),
+        (_, CE) => Some(CE),
+        (ECT_0, ECT_1) => Some(ECT_1),
+        (x, _) => Some(x),
+    }
+}
+
+enum Inner<'pkt> {
+    V4(IPv4<&'pkt mut [u8]>),
+    V6(IPv6<&'pkt mut [u8]>),
+}
+
+impl<'pkt> Inner<'pkt> {
+    fn new(slice: &'pkt mut [u8]) -> Result<Self, Error> {
+        match slice.first().map(|b| b >> 4) {
+            Some(4) => Ok(Inner::V4(IPv4::new_mut(slice)?.0)),
+            Some(6) => Ok(Inner::V6(IPv6::new_mut(slice)?.0)),
+            Some(x) => Err(Error::InvalidInnerVersion(x)),
+            None => Err(Error::InvalidLength(0)),
+        }
+    }
+
+    fn version(&self) -> u8 {
+        match self {
+            Inner::V4(_) => 4,
+            Inner::V6(_) => 6,
+        }
+    }
+
+    fn protocol(&self) -> InetProtocol {
+        match self {
+            Inner::V4(_) => InetProtocol::IPV4,
+            Inner::V6(_) => InetProtocol::IPV6,
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Inner::V4(ip) => ip.total_length() as usize,
+            Inner::V6(ip) => IPv6::SIZE + ip.payload_length() as usize,
+        }
+    }
+
+    fn ttl(&self) -> u8 {
+        match self {
+            Inner::V4(ip) => ip.ttl(),
+            Inner::V6(ip) => ip.hop_limit(),
+        }
+    }
+
+    fn set_ttl(&mut self, ttl: u8) {
+        match self {
+            Inner::V4(ip) => ip.set_ttl(ttl),
+            Inner::V6(ip) => ip.set_hop_limit(ttl),
+        }
+    }
+
+    fn dscp(&self) -> u8 {
+        match self {
+            Inner::V4(ip) => ip.dscp(),
+            Inner::V6(ip) => ip.dscp(),
+        }
+    }
+
+    fn ecn(&self) -> u8 {
+        match self {
+            Inner::V4(ip) => ip.ecn(),
+            Inner::V6(ip) => ip.ecn(),
+        }
+    }
+
+    fn set_ecn(&mut self, ecn: u8) {
+        match self {
+            Inner::V4(ip) => ip.set_ecn(ecn),
+            Inner::V6(ip) => ip.set_ecn(ecn),
+        }
+    }
+
+    fn dont_fragment(&self) -> bool {
+        match self {
+            Inner::V4(ip) => ip.dont_fragment(),
+            Inner::V6(_) => true,
+        }
+    }
+
+    /// Outer TTL for `policy`, decrementing the inner TTL for the uniform model.
+    fn encap_ttl(&mut self, policy: TtlPolicy) -> Result<u8, Error> {
+        match policy {
+            TtlPolicy::Pipe(ttl) => Ok(ttl),
+            TtlPolicy::Uniform => {
+                let ttl = self.ttl();
+                if ttl <= 1 {
+                    return Err(Error::TtlExceeded);
+                }
+                self.set_ttl(ttl - 1);
+                Ok(ttl - 1)
+            }
+        }
+    }
+
+    fn apply_egress(
+        &mut self,
+        outer_ttl: u8,
+        outer_ecn: u8,
+        policy: TtlPolicy,
+    ) -> Result<(), Error> {
+        let ecn = ecn_egress(outer_ecn, self.ecn()).ok_or(Error::EcnDrop)?;
+        self.set_ecn(ecn);
+
+        if policy == TtlPolicy::Uniform && outer_ttl < self.ttl() {
+            self.set_ttl(outer_ttl);
+        }
+
+        self.finish();
+        Ok(())
+    }
+
+    fn finish(&mut self) {
+        if let Inner::V4(ip) = self {
+            ip.update_csum();
+        }
+    }
+}
+
+/// Writes an outer IPv4 header in the first `IPv4::MIN_LEN` bytes of `buf`,
+/// in front of the IPv4 or IPv6 packet that follows.
+pub fn encap_ipv4(
+    buf: &mut [u8],
+    source: &[u8; 4],
+    destination: &[u8; 4],
+    ttl: TtlPolicy,
+    ecn: EcnMode,
+) -> Result<(), Error> {
+    if buf.len() < IPv4::MIN_LEN {
+        return Err(Error::InvalidHeadroom(buf.len()));
+    }
+
+    let (outer, inner) = buf.split_at_mut(IPv4::MIN_LEN);
+    let mut inner = Inner::new(inner)?;
+
+    let total_length = IPv4::MIN_LEN + inner.len();
+    if total_length > u16::MAX as usize {
+        return Err(Error::InvalidLength(total_length));
+    }
+
+    let outer_ttl = inner.encap_ttl(ttl)?;
+
+    outer.fill(0);
+    outer[0] = (4 << 4) | 5;
+    let (mut ip, _) = IPv4::new_mut(outer)?;
+    ip.set_dscp(inner.dscp());
+    ip.set_ecn(ecn_ingress(inner.ecn(), ecn));
+    ip.set_total_length_u16(total_length as u16);
+    ip.set_dont_fragment(inner.dont_fragment());
+    ip.set_ttl(outer_ttl);
+    ip.set_protocol(inner.protocol());
+    ip.set_source(source);
+    ip.set_destination(destination);
+    ip.update_csum();
+
+    inner.finish();
+    Ok(())
+}
+
+/// Applies RFC 6040 egress processing and `ttl` to the packet carried behind
+/// the outer IPv4 header, returning the offset of the inner header.
+pub fn decap_ipv4(buf: &mut [u8], ttl: TtlPolicy) -> Result<usize, Error> {
+    let (outer, rem) = IPv4::new_mut(buf)?;
+
+    let expected = match outer.protocol() {
+        InetProtocol::IPV4 | InetProtocol::IPIP => 4,
+        InetProtocol::IPV6 => 6,
+        x => return Err(Error::NotTunnel(x)),
+    };
+
+    let mut inner = Inner::new(rem)?;
+    if inner.version() != expected {
+        return Err(Error::InvalidInnerVersion(inner.version()));
+    }
+
+    inner.apply_egress(outer.ttl(), outer.ecn(), ttl)?;
+    Ok(outer.size() as usize)
+}
+
+/// Writes an outer IPv6 header in the first `IPv6::SIZE` bytes of `buf`,
+/// in front of the IPv4 or IPv6 packet that follows.
+pub fn encap_ipv6(
+    buf: &mut [u8],
+    source: &[u8; 16],
+    destination: &[u8; 16],
+    ttl: TtlPolicy,
+    ecn: EcnMode,
+) -> Result<(), Error> {
+    if buf.len() < IPv6::SIZE {
+        return Err(Error::InvalidHeadroom(buf.len()));
+    }
+
+    let (outer, inner) = buf.split_at_mut(IPv6::SIZE);
+    let mut inner = Inner::new(inner)?;
+
+    let payload_length = inner.len();
+    if payload_length > u16::MAX as usize {
+        return Err(Error::InvalidLength(payload_length));
+    }
+
+    let outer_ttl = inner.encap_ttl(ttl)?;
+
+    outer.fill(0);
+    outer[0] = 6 << 4;
+    let (mut ip, _) = IPv6::new_mut(outer)?;
+    ip.set_traffic_class((inner.dscp() << 2) | ecn_ingress(inner.ecn(), ecn));
+    ip.set_payload_length(payload_length as u16);
+    ip.set_next_header(inner.protocol());
+    ip.set_hop_limit(outer_ttl);
+    ip.set_source(source);
+    ip.set_destination(destination);
+
+    inner.finish();
+    Ok(())
+}
+
+/// Applies RFC 6040 egress processing and `ttl` to the packet carried behind
+/// the outer IPv6 header, returning the offset of the inner header.
+pub fn decap_ipv6(buf: &mut [u8], ttl: TtlPolicy) -> Result<usize, Error> {
+    let (outer, rem) = IPv6::new_mut(buf)?;
+
+    let expected = match outer.next_header() {
+        InetProtocol::IPV4 | InetProtocol::IPIP => 4,
+        InetProtocol::IPV6 => 6,
+        x => return Err(Error::NotTunnel(x)),
+    };
+
+    let mut inner = Inner::new(rem)?;
+    if inner.version() != expected {
+        return Err(Error::InvalidInnerVersion(inner.version()));
+    }
+
+    inner.apply_egress(outer.hop_limit(), outer.ecn(), ttl)?;
+    Ok(IPv6::SIZE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INNER: [u8; 20] = [
+        0x45, 0x02, 0x00, 0x14, 0x00, 0x01, 0x40, 0x00, 0x40, 0x11, 0x00, 0x00, 0x0a, 0x00, 0x00,
+        0x01, 0x0a, 0x00, 0x00, 0x02,
+    ];
+
+    fn packet() -> [u8; 40] {
+        let mut buf = [0; 40];
+        buf[20..].copy_from_slice(&INNER);
+        let (mut ip, _) = IPv4::new_mut(&mut buf[20..]).unwrap();
+        ip.update_csum();
+        buf
+    }
+
+    #[test]
+    fn ipip_roundtrip() {
+        let mut buf = packet();
+        encap_ipv4(
+            &mut buf,
+            &[192, 0, 2, 1],
+            &[192, 0, 2, 2],
+            TtlPolicy::Uniform,
+            EcnMode::Normal,
+        )
+        .unwrap();
+
+        let (outer, rem) = IPv4::new(&buf).unwrap();
+        assert_eq!(outer.total_length(), 40);
+        assert_eq!(outer.protocol(), InetProtocol::IPV4);
+        assert_eq!(outer.ttl(), 63);
+        assert_eq!(outer.ecn(), ECT_0);
+        assert!(outer.dont_fragment());
+        assert_eq!(outer.csum(), outer.calc_csum());
+
+        let (inner, _) = IPv4::new(rem).unwrap();
+        assert_eq!(inner.ttl(), 63);
+        assert_eq!(inner.csum(), inner.calc_csum());
+
+        // Congestion experienced on the tunnel path.
+        let (mut outer, _) = IPv4::new_mut(&mut buf).unwrap();
+        outer.set_ecn(CE);
+        outer.set_ttl(10);
+
+        let offset = decap_ipv4(&mut buf, TtlPolicy::Uniform).unwrap();
+        assert_eq!(offset, 20);
+
+        let (inner, _) = IPv4::new(&buf[offset..]).unwrap();
+        assert_eq!(inner.ecn(), CE);
+        assert_eq!(inner.ttl(), 10);
+        assert_eq!(inner.csum(), inner.calc_csum());
+    }
+
+    #[test]
+    fn ce_over_not_ect_is_dropped() {
+        let mut buf = packet();
+        let (mut inner, _) = IPv4::new_mut(&mut buf[20..]).unwrap();
+        inner.set_ecn(NOT_ECT);
+        inner.update_csum();
+
+        encap_ipv4(
+            &mut buf,
+            &[192, 0, 2, 1],
+            &[192, 0, 2, 2],
+            TtlPolicy::Pipe(64),
+            EcnMode::Compatibility,
+        )
+        .unwrap();
+
+        let (mut outer, _) = IPv4::new_mut(&mut buf).unwrap();
+        assert_eq!(outer.ecn(), NOT_ECT);
+        assert_eq!(outer.ttl(), 64);
+        outer.set_ecn(CE);
+
+        assert!(matches!(
+            decap_ipv4(&mut buf, TtlPolicy::Pipe(64)),
+            Err(Error::EcnDrop)
+        ));
+    }
+
+    #[test]
+    fn ipv4_in_ipv6() {
+        let mut buf = [0; 60];
+        buf[40..].copy_from_slice(&packet()[20..]);
+
+        encap_ipv6(
+            &mut buf,
+            &[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+            &[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2],
+            TtlPolicy::Pipe(255),
+            EcnMode::Normal,
+        )
+        .unwrap();
+
+        let (outer, _) = IPv6::new(&buf).unwrap();
+        assert_eq!(outer.payload_length(), 20);
+        assert_eq!(outer.next_header(), InetProtocol::IPV4);
+        assert_eq!(outer.hop_limit(), 255);
+        assert_eq!(outer.ecn(), ECT_0);
+
+        assert_eq!(decap_ipv6(&mut buf, TtlPolicy::Pipe(255)).unwrap(), 40);
+        let (inner, _) = IPv4::new(&buf[40..]).unwrap();
+        assert_eq!(inner.ttl(), 64);
+    }
+
+    #[test]
+    fn egress_table() {
+        assert_eq!(ecn_egress(ECT_0, NOT_ECT), Some(NOT_ECT));
+        assert_eq!(ecn_egress(CE, NOT_ECT), None);
+        assert_eq!(ecn_egress(ECT_1, ECT_0), Some(ECT_1));
+        assert_eq!(ecn_egress(ECT_0, ECT_1), Some(ECT_1));
+        assert_eq!(ecn_egress(CE, ECT_1), Some(CE));
+        assert_eq!(ecn_egress(NOT_ECT, CE), Some(CE));
+    }
+}
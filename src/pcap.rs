@@ -0,0 +1,544 @@
+//! Classic libpcap capture files.
+//!
+//! `Records` iterates over a file held in memory (e.g. mmap'd) without
+//! copying, and with the `std` feature `Reader` streams from any `Read`.
+//! Record data is the captured frame as is, ready for the parser of the
+//! file's `LinkType`, e.g. `Ethernet::new`.
+
+use core::time::Duration;
+
+use crate::decode::{self, Decoded};
+
+/// Link-layer header types of the tcpdump.org registry, shared by pcap and pcapng.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LinkType {
+    /// BSD loopback, a 4-byte protocol family in host byte order.
+    Null,
+    Ethernet,
+    /// IEEE 802.11 wireless LAN.
+    Ieee80211,
+    /// Raw IPv4 or IPv6, the version is in the first nibble.
+    Raw,
+    /// OpenBSD loopback, a 4-byte protocol family in network byte order.
+    Loop,
+    /// Linux "cooked" capture, version 1.
+    LinuxSll,
+    Ipv4,
+    Ipv6,
+    /// Linux "cooked" capture, version 2.
+    LinuxSll2,
+    Other(u16),
+}
+
+impl From<u16> for LinkType {
+    fn from(value: u16) -> Self {
+        match value {
+            0 => Self::Null,
+            1 => Self::Ethernet,
+            105 => Self::Ieee80211,
+            101 => Self::Raw,
+            108 => Self::Loop,
+            113 => Self::LinuxSll,
+            228 => Self::Ipv4,
+            229 => Self::Ipv6,
+            276 => Self::LinuxSll2,
+            x => Self::Other(x),
+        }
+    }
+}
+
+impl From<LinkType> for u16 {
+    fn from(value: LinkType) -> Self {
+        match value {
+            LinkType::Null => 0,
+            LinkType::Ethernet => 1,
+            LinkType::Ieee80211 => 105,
+            LinkType::Raw => 101,
+            LinkType::Loop => 108,
+            LinkType::LinuxSll => 113,
+            LinkType::Ipv4 => 228,
+            LinkType::Ipv6 => 229,
+            LinkType::LinuxSll2 => 276,
+            LinkType::Other(x) => x,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endianness {
+    Big,
+    Little,
+}
+
+impl Endianness {
+    /// Byte order of the machine, the one writers use.
+    pub const NATIVE: Endianness = if cfg!(target_endian = "big") {
+        Endianness::Big
+    } else {
+        Endianness::Little
+    };
+
+    pub(crate) fn u16(self, slice: &[u8], offset: usize) -> Option<u16> {
+        let bytes = *slice.get(offset..)?.first_chunk::<2>()?;
+        Some(match self {
+            Endianness::Big => u16::from_be_bytes(bytes),
+            Endianness::Little => u16::from_le_bytes(bytes),
+        })
+    }
+
+    pub(crate) fn u32(self, slice: &[u8], offset: usize) -> Option<u32> {
+        let bytes = *slice.get(offset..)?.first_chunk::<4>()?;
+        Some(match self {
+            Endianness::Big => u32::from_be_bytes(bytes),
+            Endianness::Little => u32::from_le_bytes(bytes),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    Micro,
+    Nano,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// Not a pcap file.
+    InvalidMagic(u32),
+    /// The file ends within a header or record.
+    Truncated,
+    /// Captured length over `Header::MAX_RECORD_LEN`, the file is likely corrupt.
+    TooLarge(u32),
+    #[cfg(feature = "std")]
+    Io(std::io::Error),
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        match value.kind() {
+            std::io::ErrorKind::UnexpectedEof => Error::Truncated,
+            _ => Error::Io(value),
+        }
+    }
+}
+
+/// Global header at the start of the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub endianness: Endianness,
+    pub resolution: Resolution,
+    pub version_major: u16,
+    pub version_minor: u16,
+    /// GMT to local correction in seconds, always zero in practice.
+    pub thiszone: i32,
+    pub snaplen: u32,
+    pub link_type: LinkType,
+    /// Upper bits of the link type field, carrying the FCS length when present.
+    pub link_flags: u16,
+}
+
+impl Header {
+    pub const LEN: usize = 24;
+    pub const RECORD_LEN: usize = 16;
+    /// Largest captured length accepted for a record.
+    pub const MAX_RECORD_LEN: u32 = 16 << 20;
+
+    pub const MAGIC_MICRO: u32 = 0xa1b2c3d4;
+    pub const MAGIC_NANO: u32 = 0xa1b23c4d;
+
+    pub fn parse(slice: &[u8]) -> Result<(Self, &[u8]), Error> {
+        let (header, rem) = slice.split_at_checked(Self::LEN).ok_or(Error::Truncated)?;
+        let magic = Endianness::Big.u32(header, 0).ok_or(Error::Truncated)?;
+        let (endianness, resolution) = match (magic, magic.swap_bytes()) {
+            (Self::MAGIC_MICRO, _) => (Endianness::Big, Resolution::Micro),
+            (Self::MAGIC_NANO, _) => (Endianness::Big, Resolution::Nano),
+            (_, Self::MAGIC_MICRO) => (Endianness::Little, Resolution::Micro),
+            (_, Self::MAGIC_NANO) => (Endianness::Little, Resolution::Nano),
+            (x, _) => return Err(Error::InvalidMagic(x)),
+        };
+        let e = endianness;
+        let network = e.u32(header, 20).ok_or(Error::Truncated)?;
+        Ok((
+            Self {
+                endianness,
+                resolution,
+                version_major: e.u16(header, 4).ok_or(Error::Truncated)?,
+                version_minor: e.u16(header, 6).ok_or(Error::Truncated)?,
+                thiszone: e.u32(header, 8).ok_or(Error::Truncated)? as i32,
+                snaplen: e.u32(header, 16).ok_or(Error::Truncated)?,
+                link_type: LinkType::from(network as u16),
+                link_flags: (network >> 16) as u16,
+            },
+            rem,
+        ))
+    }
+
+    /// Timestamp, captured and original length of a record header.
+    fn record(&self, slice: &[u8]) -> Result<(Duration, u32, u32), Error> {
+        let e = self.endianness;
+        let field = |offset| e.u32(slice, offset).ok_or(Error::Truncated);
+        let (seconds, fraction) = (field(0)? as u64, field(4)?);
+        let timestamp = match self.resolution {
+            Resolution::Micro => {
+                Duration::from_secs(seconds) + Duration::from_micros(fraction as u64)
+            }
+            Resolution::Nano => {
+                Duration::from_secs(seconds) + Duration::from_nanos(fraction as u64)
+            }
+        };
+        let captured = field(8)?;
+        if captured > Self::MAX_RECORD_LEN {
+            return Err(Error::TooLarge(captured));
+        }
+        Ok((timestamp, captured, field(12)?))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Record<'a> {
+    /// Time since the Unix epoch.
+    pub timestamp: Duration,
+    /// Length of the packet on the wire, larger than `data` when truncated by the snaplen.
+    pub original_len: u32,
+    pub link_type: LinkType,
+    pub data: &'a [u8],
+}
+
+impl<'a> Record<'a> {
+    /// Decodes the record with the parser of the file's link type.
+    pub fn decode(&self) -> Result<Decoded<'a>, decode::Error> {
+        decode::decode(self.link_type, self.data)
+    }
+}
+
+/// Zero-copy iterator over the records of a file in memory. Iteration stops
+/// after the first error.
+#[derive(Debug, Clone)]
+pub struct Records<'a> {
+    header: Header,
+    rem: &'a [u8],
+}
+
+impl<'a> Records<'a> {
+    pub fn new(slice: &'a [u8]) -> Result<Self, Error> {
+        let (header, rem) = Header::parse(slice)?;
+        Ok(Self { header, rem })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    fn parse(&mut self) -> Result<Record<'a>, Error> {
+        let (record, rem) = self
+            .rem
+            .split_at_checked(Header::RECORD_LEN)
+            .ok_or(Error::Truncated)?;
+        let (timestamp, captured, original_len) = self.header.record(record)?;
+        let (data, rem) = rem
+            .split_at_checked(captured as usize)
+            .ok_or(Error::Truncated)?;
+        self.rem = rem;
+        Ok(Record {
+            timestamp,
+            original_len,
+            link_type: self.header.link_type,
+            data,
+        })
+    }
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = Result<Record<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rem.is_empty() {
+            return None;
+        }
+        let record = self.parse();
+        if record.is_err() {
+            self.rem = &[];
+        }
+        Some(record)
+    }
+}
+
+/// Streaming reader, the data of a record stays valid until the next call.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct Reader<R> {
+    inner: R,
+    header: Header,
+    buf: Vec<u8>,
+}
+
+#[cfg(feature = "std")]
+impl<R: std::io::Read> Reader<R> {
+    pub fn new(mut inner: R) -> Result<Self, Error> {
+        let mut header = [0; Header::LEN];
+        inner.read_exact(&mut header)?;
+        let (header, _) = Header::parse(&header)?;
+        Ok(Self {
+            inner,
+            header,
+            buf: Vec::new(),
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Next record, `None` at the end of the file.
+    pub fn next_record(&mut self) -> Result<Option<Record<'_>>, Error> {
+        let mut record = [0; Header::RECORD_LEN];
+        let mut filled = 0;
+        while filled < record.len() {
+            match self.inner.read(&mut record[filled..]) {
+                Ok(0) if filled == 0 => return Ok(None),
+                Ok(0) => return Err(Error::Truncated),
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        let (timestamp, captured, original_len) = self.header.record(&record)?;
+        self.buf.resize(captured as usize, 0);
+        self.inner.read_exact(&mut self.buf)?;
+        Ok(Some(Record {
+            timestamp,
+            original_len,
+            link_type: self.header.link_type,
+            data: &self.buf,
+        }))
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+/// Writes files in native byte order, packets longer than the snaplen are truncated.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct Writer<W> {
+    inner: W,
+    header: Header,
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write> Writer<W> {
+    pub fn new(
+        mut inner: W,
+        link_type: LinkType,
+        snaplen: u32,
+        resolution: Resolution,
+    ) -> Result<Self, Error> {
+        let magic = match resolution {
+            Resolution::Micro => Header::MAGIC_MICRO,
+            Resolution::Nano => Header::MAGIC_NANO,
+        };
+        let mut header = [0; Header::LEN];
+        header[0..4].copy_from_slice(&magic.to_ne_bytes());
+        header[4..6].copy_from_slice(&2u16.to_ne_bytes());
+        header[6..8].copy_from_slice(&4u16.to_ne_bytes());
+        header[16..20].copy_from_slice(&snaplen.to_ne_bytes());
+        header[20..24].copy_from_slice(&(u16::from(link_type) as u32).to_ne_bytes());
+        inner.write_all(&header)?;
+        let (header, _) = Header::parse(&header)?;
+        Ok(Self { inner, header })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Writes a packet captured at `timestamp`, since the Unix epoch.
+    pub fn write(&mut self, timestamp: Duration, data: &[u8]) -> Result<(), Error> {
+        self.write_truncated(timestamp, data, data.len() as u32)
+    }
+
+    /// Writes a record, e.g. one read from another file, keeping its original length.
+    pub fn write_record(&mut self, record: &Record) -> Result<(), Error> {
+        self.write_truncated(record.timestamp, record.data, record.original_len)
+    }
+
+    fn write_truncated(
+        &mut self,
+        timestamp: Duration,
+        data: &[u8],
+        original_len: u32,
+    ) -> Result<(), Error> {
+        let data = data.get(..self.header.snaplen as usize).unwrap_or(data);
+        let fraction = match self.header.resolution {
+            Resolution::Micro => timestamp.subsec_micros(),
+            Resolution::Nano => timestamp.subsec_nanos(),
+        };
+        let mut record = [0; Header::RECORD_LEN];
+        record[0..4].copy_from_slice(&(timestamp.as_secs() as u32).to_ne_bytes());
+        record[4..8].copy_from_slice(&fraction.to_ne_bytes());
+        record[8..12].copy_from_slice(&(data.len() as u32).to_ne_bytes());
+        record[12..16].copy_from_slice(&original_len.max(data.len() as u32).to_ne_bytes());
+        self.inner.write_all(&record)?;
+        self.inner.write_all(data)?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), Error> {
+        Ok(self.inner.flush()?)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link::{EtherType, Ethernet};
+
+    const FRAME: [u8; 14] = [0x02, 0, 0, 0, 0, 0x02, 0x02, 0, 0, 0, 0, 0x01, 0x86, 0xdd];
+
+    fn u16_bytes(e: Endianness, v: u16) -> [u8; 2] {
+        match e {
+            Endianness::Big => v.to_be_bytes(),
+            Endianness::Little => v.to_le_bytes(),
+        }
+    }
+
+    fn u32_bytes(e: Endianness, v: u32) -> [u8; 4] {
+        match e {
+            Endianness::Big => v.to_be_bytes(),
+            Endianness::Little => v.to_le_bytes(),
+        }
+    }
+
+    /// File with two Ethernet records.
+    fn file(e: Endianness, magic: u32) -> [u8; 84] {
+        let mut file = [0; 84];
+        file[0..4].copy_from_slice(&u32_bytes(e, magic));
+        file[4..6].copy_from_slice(&u16_bytes(e, 2));
+        file[6..8].copy_from_slice(&u16_bytes(e, 4));
+        file[16..20].copy_from_slice(&u32_bytes(e, 65535));
+        file[20..24].copy_from_slice(&u32_bytes(e, 1));
+        for (i, record) in file[24..].chunks_exact_mut(30).enumerate() {
+            record[0..4].copy_from_slice(&u32_bytes(e, 1_700_000_000 + i as u32));
+            record[4..8].copy_from_slice(&u32_bytes(e, 500));
+            record[8..12].copy_from_slice(&u32_bytes(e, 14));
+            record[12..16].copy_from_slice(&u32_bytes(e, 60));
+            record[16..].copy_from_slice(&FRAME);
+        }
+        file
+    }
+
+    #[test]
+    fn endianness_and_resolution() {
+        for (endianness, magic, resolution, fraction) in [
+            (
+                Endianness::Little,
+                Header::MAGIC_MICRO,
+                Resolution::Micro,
+                Duration::from_micros(500),
+            ),
+            (
+                Endianness::Big,
+                Header::MAGIC_NANO,
+                Resolution::Nano,
+                Duration::from_nanos(500),
+            ),
+        ] {
+            let file = file(endianness, magic);
+            let mut records = Records::new(&file).unwrap();
+            assert_eq!(records.header().endianness, endianness);
+            assert_eq!(records.header().resolution, resolution);
+            assert_eq!(
+                (
+                    records.header().version_major,
+                    records.header().version_minor
+                ),
+                (2, 4)
+            );
+            assert_eq!(records.header().link_type, LinkType::Ethernet);
+            assert_eq!(records.header().snaplen, 65535);
+
+            let record = records.next().unwrap().unwrap();
+            assert_eq!(
+                record.timestamp,
+                Duration::from_secs(1_700_000_000) + fraction
+            );
+            assert_eq!(record.original_len, 60);
+            let (eth, _) = Ethernet::new(record.data).unwrap();
+            assert_eq!(eth.ethertype(), EtherType::IPv6);
+            // Dispatched to the IPv6 parser, the frame ends after Ethernet.
+            assert!(matches!(record.decode(), Err(decode::Error::IPv6(_))));
+            assert!(records.next().unwrap().is_ok());
+            assert!(records.next().is_none());
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let file = file(Endianness::Little, Header::MAGIC_MICRO);
+        assert!(matches!(Records::new(&file[..10]), Err(Error::Truncated)));
+        assert!(matches!(
+            Records::new(&[0; 24]),
+            Err(Error::InvalidMagic(0))
+        ));
+
+        let mut records = Records::new(&file[..file.len() - 1]).unwrap();
+        assert!(records.next().unwrap().is_ok());
+        assert!(matches!(records.next(), Some(Err(Error::Truncated))));
+        assert!(records.next().is_none());
+
+        let mut large = file;
+        large[32..36].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            Records::new(&large).unwrap().next(),
+            Some(Err(Error::TooLarge(u32::MAX)))
+        ));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn reader() {
+        let file = file(Endianness::Big, Header::MAGIC_MICRO);
+        let mut reader = Reader::new(&file[..]).unwrap();
+        let mut count = 0;
+        while let Some(record) = reader.next_record().unwrap() {
+            assert_eq!(record.data, FRAME);
+            count += 1;
+        }
+        assert_eq!(count, 2);
+
+        let mut reader = Reader::new(&file[..file.len() - 20]).unwrap();
+        assert!(reader.next_record().unwrap().is_some());
+        assert!(matches!(reader.next_record(), Err(Error::Truncated)));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn writer() {
+        let timestamp = Duration::new(1_700_000_000, 123_456_789);
+        let mut writer = Writer::new(Vec::new(), LinkType::Ethernet, 10, Resolution::Nano).unwrap();
+        writer.write(timestamp, &FRAME).unwrap();
+        let file = writer.into_inner();
+
+        let mut records = Records::new(&file).unwrap();
+        assert_eq!(records.header().endianness, Endianness::NATIVE);
+        assert_eq!(records.header().snaplen, 10);
+        let record = records.next().unwrap().unwrap();
+        assert_eq!(record.timestamp, timestamp);
+        assert_eq!(record.data, &FRAME[..10]);
+        assert_eq!(record.original_len, 14);
+
+        let mut writer = Writer::new(Vec::new(), LinkType::Raw, 65535, Resolution::Micro).unwrap();
+        writer.write_record(&record).unwrap();
+        let file = writer.into_inner();
+        let record = Records::new(&file).unwrap().next().unwrap().unwrap();
+        assert_eq!(record.timestamp, Duration::new(1_700_000_000, 123_456_000));
+        assert_eq!((record.data.len(), record.original_len), (10, 14));
+        assert_eq!(record.link_type, LinkType::Raw);
+    }
+}
@@ -0,0 +1,881 @@
+//! pcapng capture files \[[draft-ietf-opsawg-pcapng](https://datatracker.ietf.org/doc/draft-ietf-opsawg-pcapng/)\].
+//!
+//! A file is a sequence of blocks: a section header, interface descriptions
+//! carrying the link type and snaplen of each interface, then packet blocks
+//! referring to an interface by its index in the section.
+//!
+//! `Blocks` parses the blocks of a file in memory without copying. With the
+//! `std` feature `Packets` also keeps track of the interfaces of the current
+//! section, resolving the link type and timestamp of each packet so it can
+//! be handed to `decode`.
+
+use core::time::Duration;
+
+use crate::decode::{self, Decoded};
+use crate::pcap::{Endianness, LinkType};
+
+/// Block type codes.
+pub const SECTION_HEADER: u32 = 0x0a0d_0d0a;
+pub const INTERFACE_DESCRIPTION: u32 = 0x0000_0001;
+pub const SIMPLE_PACKET: u32 = 0x0000_0003;
+pub const NAME_RESOLUTION: u32 = 0x0000_0004;
+pub const INTERFACE_STATISTICS: u32 = 0x0000_0005;
+pub const ENHANCED_PACKET: u32 = 0x0000_0006;
+pub const CUSTOM: u32 = 0x0000_0bad;
+/// Custom block that must not be copied to other files.
+pub const CUSTOM_NO_COPY: u32 = 0x4000_0bad;
+
+/// Written in the section header in the byte order of the section.
+pub const BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+
+/// Option codes.
+pub const OPT_END: u16 = 0;
+pub const OPT_COMMENT: u16 = 1;
+pub const IF_NAME: u16 = 2;
+pub const IF_TSRESOL: u16 = 9;
+pub const IF_TSOFFSET: u16 = 14;
+
+/// Name resolution record types.
+pub const NRB_END: u16 = 0;
+pub const NRB_IPV4: u16 = 1;
+pub const NRB_IPV6: u16 = 2;
+
+#[derive(Debug)]
+pub enum Error {
+    /// Byte order magic of a section header not recognized.
+    InvalidMagic(u32),
+    /// The file ends within a block.
+    Truncated,
+    /// Block total length too small, unaligned or not repeated at the end.
+    InvalidLength(u32),
+    /// A block comes before the first section header.
+    NoSection,
+    /// A packet refers to an interface not described in the section.
+    UnknownInterface(u32),
+    #[cfg(feature = "std")]
+    Io(std::io::Error),
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        Error::Io(value)
+    }
+}
+
+/// Options at the end of a block as `(code, value)` pairs, up to the end of
+/// options marker or the first malformed option.
+#[derive(Debug, Clone, Copy)]
+pub struct Options<'a> {
+    endianness: Endianness,
+    rem: &'a [u8],
+}
+
+impl<'a> Options<'a> {
+    fn new(endianness: Endianness, rem: &'a [u8]) -> Self {
+        Self { endianness, rem }
+    }
+
+    /// Value of the first option with `code`.
+    pub fn get(&self, code: u16) -> Option<&'a [u8]> {
+        self.clone().find(|(c, _)| *c == code).map(|(_, v)| v)
+    }
+
+    /// `opt_comment` values, which may appear several times.
+    pub fn comments(&self) -> impl Iterator<Item = &'a str> {
+        (*self)
+            .filter(|(code, _)| *code == OPT_COMMENT)
+            .filter_map(|(_, value)| core::str::from_utf8(value).ok())
+    }
+}
+
+impl<'a> Iterator for Options<'a> {
+    type Item = (u16, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let e = self.endianness;
+        let code = e.u16(self.rem, 0)?;
+        let len = e.u16(self.rem, 2)? as usize;
+        let value = self.rem.get(4..4 + len);
+        let next = self.rem.get(4 + len.next_multiple_of(4)..);
+        match (code, value) {
+            (OPT_END, _) | (_, None) => {
+                self.rem = &[];
+                None
+            }
+            (code, Some(value)) => {
+                self.rem = next.unwrap_or(&[]);
+                Some((code, value))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SectionHeader<'a> {
+    pub endianness: Endianness,
+    pub version_major: u16,
+    pub version_minor: u16,
+    /// Length of the section in bytes, -1 when not specified.
+    pub section_length: i64,
+    pub options: Options<'a>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct InterfaceDescription<'a> {
+    pub link_type: LinkType,
+    /// Zero for no limit.
+    pub snaplen: u32,
+    pub options: Options<'a>,
+}
+
+impl InterfaceDescription<'_> {
+    pub fn name(&self) -> Option<&str> {
+        core::str::from_utf8(self.options.get(IF_NAME)?).ok()
+    }
+
+    /// Timestamp units per second, from `if_tsresol` (microseconds by default).
+    pub fn units_per_second(&self) -> u128 {
+        let tsresol = self
+            .options
+            .get(IF_TSRESOL)
+            .and_then(|v| v.first().copied())
+            .unwrap_or(6);
+        let exp = (tsresol & 0x7f) as u32;
+        let units = match tsresol & 0x80 {
+            0 => 10u128.checked_pow(exp),
+            _ => 1u128.checked_shl(exp),
+        };
+        units.unwrap_or(u128::MAX)
+    }
+
+    /// Time since the Unix epoch of a timestamp of this interface, with
+    /// `if_tsoffset` applied.
+    pub fn timestamp(&self, raw: u64) -> Duration {
+        let units = self.units_per_second();
+        let seconds = raw as u128 / units;
+        let nanos = (raw as u128 % units) * 1_000_000_000 / units;
+        let timestamp = Duration::new(seconds as u64, nanos as u32);
+        let offset = self
+            .options
+            .get(IF_TSOFFSET)
+            .and_then(|v| v.first_chunk::<8>())
+            .map_or(0, |b| match self.options.endianness {
+                Endianness::Big => i64::from_be_bytes(*b),
+                Endianness::Little => i64::from_le_bytes(*b),
+            });
+        let shifted = match offset {
+            x if x >= 0 => timestamp.checked_add(Duration::from_secs(x as u64)),
+            x => timestamp.checked_sub(Duration::from_secs(x.unsigned_abs())),
+        };
+        shifted.unwrap_or(timestamp)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct EnhancedPacket<'a> {
+    pub interface_id: u32,
+    /// Raw timestamp in units of the interface, see `InterfaceDescription::timestamp`.
+    pub timestamp: u64,
+    pub original_len: u32,
+    pub data: &'a [u8],
+    pub options: Options<'a>,
+}
+
+/// Packet of interface 0 without timestamp. `data` may include up to three
+/// bytes of padding when the packet was truncated by the snaplen, `Packets`
+/// strips them.
+#[derive(Debug, Clone, Copy)]
+pub struct SimplePacket<'a> {
+    pub original_len: u32,
+    pub data: &'a [u8],
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct NameResolution<'a> {
+    endianness: Endianness,
+    records: &'a [u8],
+    pub options: Options<'a>,
+}
+
+impl<'a> NameResolution<'a> {
+    pub fn records(&self) -> NameRecords<'a> {
+        NameRecords {
+            endianness: self.endianness,
+            rem: self.records,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameRecord<'a> {
+    /// Address and its names, each terminated by a zero byte.
+    Ipv4(&'a [u8; 4], &'a [u8]),
+    Ipv6(&'a [u8; 16], &'a [u8]),
+    Other(u16, &'a [u8]),
+}
+
+impl<'a> NameRecord<'a> {
+    /// Names of an address record.
+    pub fn names(&self) -> impl Iterator<Item = &'a str> {
+        let names = match self {
+            NameRecord::Ipv4(_, names) | NameRecord::Ipv6(_, names) => *names,
+            NameRecord::Other(..) => &[],
+        };
+        names
+            .split(|b| *b == 0)
+            .filter(|name| !name.is_empty())
+            .filter_map(|name| core::str::from_utf8(name).ok())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NameRecords<'a> {
+    endianness: Endianness,
+    rem: &'a [u8],
+}
+
+impl<'a> Iterator for NameRecords<'a> {
+    type Item = NameRecord<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (kind, value, rem) = name_record(self.endianness, self.rem)?;
+        self.rem = rem;
+        Some(match kind {
+            NRB_IPV4 => match value.split_first_chunk::<4>() {
+                Some((address, names)) => NameRecord::Ipv4(address, names),
+                None => NameRecord::Other(kind, value),
+            },
+            NRB_IPV6 => match value.split_first_chunk::<16>() {
+                Some((address, names)) => NameRecord::Ipv6(address, names),
+                None => NameRecord::Other(kind, value),
+            },
+            x => NameRecord::Other(x, value),
+        })
+    }
+}
+
+/// Type, value and rest after a name resolution record, `None` at the end record.
+fn name_record(endianness: Endianness, rem: &[u8]) -> Option<(u16, &[u8], &[u8])> {
+    let kind = endianness.u16(rem, 0)?;
+    let len = endianness.u16(rem, 2)? as usize;
+    if kind == NRB_END {
+        return None;
+    }
+    let value = rem.get(4..4 + len)?;
+    let rem = rem.get(4 + len.next_multiple_of(4)..).unwrap_or(&[]);
+    Some((kind, value, rem))
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Custom<'a> {
+    /// IANA Private Enterprise Number of the owner of the format.
+    pub pen: u32,
+    /// Custom data followed by options, their split is owner specific.
+    pub data: &'a [u8],
+    pub copyable: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Block<'a> {
+    SectionHeader(SectionHeader<'a>),
+    InterfaceDescription(InterfaceDescription<'a>),
+    EnhancedPacket(EnhancedPacket<'a>),
+    SimplePacket(SimplePacket<'a>),
+    NameResolution(NameResolution<'a>),
+    Custom(Custom<'a>),
+    /// Any other block, e.g. interface statistics, with its body.
+    Other(u32, &'a [u8]),
+}
+
+/// Zero-copy iterator over the blocks of a file in memory, following the
+/// byte order of each section. Iteration stops after the first error.
+#[derive(Debug, Clone)]
+pub struct Blocks<'a> {
+    rem: &'a [u8],
+    endianness: Option<Endianness>,
+}
+
+impl<'a> Blocks<'a> {
+    pub fn new(slice: &'a [u8]) -> Self {
+        Self {
+            rem: slice,
+            endianness: None,
+        }
+    }
+
+    fn parse(&mut self) -> Result<Block<'a>, Error> {
+        let kind = Endianness::Big.u32(self.rem, 0).ok_or(Error::Truncated)?;
+        if kind == SECTION_HEADER {
+            let magic = Endianness::Big.u32(self.rem, 8).ok_or(Error::Truncated)?;
+            self.endianness = Some(match magic {
+                BYTE_ORDER_MAGIC => Endianness::Big,
+                x if x.swap_bytes() == BYTE_ORDER_MAGIC => Endianness::Little,
+                x => return Err(Error::InvalidMagic(x)),
+            });
+        }
+        let e = self.endianness.ok_or(Error::NoSection)?;
+        let kind = e.u32(self.rem, 0).ok_or(Error::Truncated)?;
+        let len = e.u32(self.rem, 4).ok_or(Error::Truncated)?;
+        if len < 12 || len % 4 != 0 {
+            return Err(Error::InvalidLength(len));
+        }
+        let (block, rem) = self
+            .rem
+            .split_at_checked(len as usize)
+            .ok_or(Error::Truncated)?;
+        if e.u32(block, len as usize - 4) != Some(len) {
+            return Err(Error::InvalidLength(len));
+        }
+        self.rem = rem;
+        let body = block.get(8..len as usize - 4).unwrap_or(&[]);
+        parse_block(e, kind, body)
+    }
+}
+
+impl<'a> Iterator for Blocks<'a> {
+    type Item = Result<Block<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rem.is_empty() {
+            return None;
+        }
+        let block = self.parse();
+        if block.is_err() {
+            self.rem = &[];
+        }
+        Some(block)
+    }
+}
+
+fn parse_block(e: Endianness, kind: u32, body: &[u8]) -> Result<Block<'_>, Error> {
+    let u16_at = |offset| e.u16(body, offset).ok_or(Error::Truncated);
+    let u32_at = |offset| e.u32(body, offset).ok_or(Error::Truncated);
+    let rest = |offset| body.get(offset..).ok_or(Error::Truncated);
+    Ok(match kind {
+        SECTION_HEADER => {
+            let length = body
+                .get(8..16)
+                .and_then(|b| b.first_chunk::<8>())
+                .ok_or(Error::Truncated)?;
+            Block::SectionHeader(SectionHeader {
+                endianness: e,
+                version_major: u16_at(4)?,
+                version_minor: u16_at(6)?,
+                section_length: match e {
+                    Endianness::Big => i64::from_be_bytes(*length),
+                    Endianness::Little => i64::from_le_bytes(*length),
+                },
+                options: Options::new(e, rest(16)?),
+            })
+        }
+        INTERFACE_DESCRIPTION => Block::InterfaceDescription(InterfaceDescription {
+            link_type: LinkType::from(u16_at(0)?),
+            snaplen: u32_at(4)?,
+            options: Options::new(e, rest(8)?),
+        }),
+        ENHANCED_PACKET => {
+            let captured = u32_at(12)? as usize;
+            let data = body.get(20..20 + captured).ok_or(Error::Truncated)?;
+            Block::EnhancedPacket(EnhancedPacket {
+                interface_id: u32_at(0)?,
+                timestamp: (u32_at(4)? as u64) << 32 | u32_at(8)? as u64,
+                original_len: u32_at(16)?,
+                data,
+                options: Options::new(e, rest(20 + captured.next_multiple_of(4))?),
+            })
+        }
+        SIMPLE_PACKET => {
+            let original_len = u32_at(0)?;
+            let data = rest(4)?;
+            Block::SimplePacket(SimplePacket {
+                original_len,
+                data: data.get(..original_len as usize).unwrap_or(data),
+            })
+        }
+        NAME_RESOLUTION => {
+            let mut rem = body;
+            while let Some((_, _, next)) = name_record(e, rem) {
+                rem = next;
+            }
+            // Skip the end record.
+            let options = rem.get(4..).unwrap_or(&[]);
+            Block::NameResolution(NameResolution {
+                endianness: e,
+                records: body.get(..body.len() - rem.len()).unwrap_or(&[]),
+                options: Options::new(e, options),
+            })
+        }
+        CUSTOM | CUSTOM_NO_COPY => Block::Custom(Custom {
+            pen: u32_at(0)?,
+            data: rest(4)?,
+            copyable: kind == CUSTOM,
+        }),
+        x => Block::Other(x, body),
+    })
+}
+
+/// Packet of an enhanced or simple packet block with its interface resolved.
+#[derive(Debug, Clone, Copy)]
+pub struct Packet<'a> {
+    pub interface_id: u32,
+    pub link_type: LinkType,
+    /// Time since the Unix epoch, `None` for simple packet blocks.
+    pub timestamp: Option<Duration>,
+    pub original_len: u32,
+    pub data: &'a [u8],
+    pub options: Options<'a>,
+}
+
+impl<'a> Packet<'a> {
+    /// Decodes the packet with the parser of its interface's link type.
+    pub fn decode(&self) -> Result<Decoded<'a>, decode::Error> {
+        decode::decode(self.link_type, self.data)
+    }
+}
+
+/// Iterator over the packets of a file in memory. Iteration stops after
+/// the first error.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct Packets<'a> {
+    blocks: Blocks<'a>,
+    interfaces: Vec<InterfaceDescription<'a>>,
+}
+
+#[cfg(feature = "std")]
+impl<'a> Packets<'a> {
+    pub fn new(slice: &'a [u8]) -> Self {
+        Self {
+            blocks: Blocks::new(slice),
+            interfaces: Vec::new(),
+        }
+    }
+
+    /// Interfaces of the current section.
+    pub fn interfaces(&self) -> &[InterfaceDescription<'a>] {
+        &self.interfaces
+    }
+
+    fn interface(&self, id: u32) -> Result<&InterfaceDescription<'a>, Error> {
+        self.interfaces
+            .get(id as usize)
+            .ok_or(Error::UnknownInterface(id))
+    }
+
+    fn packet(&mut self, block: Block<'a>) -> Result<Option<Packet<'a>>, Error> {
+        Ok(Some(match block {
+            Block::SectionHeader(_) => {
+                self.interfaces.clear();
+                return Ok(None);
+            }
+            Block::InterfaceDescription(interface) => {
+                self.interfaces.push(interface);
+                return Ok(None);
+            }
+            Block::EnhancedPacket(epb) => {
+                let interface = self.interface(epb.interface_id)?;
+                Packet {
+                    interface_id: epb.interface_id,
+                    link_type: interface.link_type,
+                    timestamp: Some(interface.timestamp(epb.timestamp)),
+                    original_len: epb.original_len,
+                    data: epb.data,
+                    options: epb.options,
+                }
+            }
+            Block::SimplePacket(spb) => {
+                let interface = self.interface(0)?;
+                let data = match interface.snaplen {
+                    0 => spb.data,
+                    x => spb.data.get(..x as usize).unwrap_or(spb.data),
+                };
+                Packet {
+                    interface_id: 0,
+                    link_type: interface.link_type,
+                    timestamp: None,
+                    original_len: spb.original_len,
+                    data,
+                    options: Options::new(Endianness::NATIVE, &[]),
+                }
+            }
+            _ => return Ok(None),
+        }))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a> Iterator for Packets<'a> {
+    type Item = Result<Packet<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let packet = self.blocks.next()?.and_then(|block| self.packet(block));
+            match packet {
+                Ok(Some(packet)) => return Some(Ok(packet)),
+                Ok(None) => {}
+                Err(e) => {
+                    self.blocks.rem = &[];
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+/// Writes a single section in native byte order. Interfaces use nanosecond
+/// timestamps and packets longer than their snaplen are truncated.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct Writer<W> {
+    inner: W,
+    /// Snaplen of each interface, zero for no limit.
+    interfaces: Vec<u32>,
+    block: Vec<u8>,
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write> Writer<W> {
+    pub fn new(inner: W) -> Result<Self, Error> {
+        let mut writer = Self {
+            inner,
+            interfaces: Vec::new(),
+            block: Vec::new(),
+        };
+        writer.begin(SECTION_HEADER);
+        writer.put(&BYTE_ORDER_MAGIC.to_ne_bytes());
+        writer.put(&1u16.to_ne_bytes());
+        writer.put(&0u16.to_ne_bytes());
+        // Section length not specified.
+        writer.put(&(-1i64).to_ne_bytes());
+        writer.finish(false)?;
+        Ok(writer)
+    }
+
+    /// Describes a new interface, returning the index packets refer to it by.
+    pub fn add_interface(
+        &mut self,
+        link_type: LinkType,
+        snaplen: u32,
+        name: Option<&str>,
+    ) -> Result<u32, Error> {
+        self.begin(INTERFACE_DESCRIPTION);
+        self.put(&u16::from(link_type).to_ne_bytes());
+        self.put(&0u16.to_ne_bytes());
+        self.put(&snaplen.to_ne_bytes());
+        self.option(IF_TSRESOL, &[9]);
+        if let Some(name) = name {
+            self.option(IF_NAME, name.as_bytes());
+        }
+        self.finish(true)?;
+        self.interfaces.push(snaplen);
+        Ok(self.interfaces.len() as u32 - 1)
+    }
+
+    /// Writes an enhanced packet block captured at `timestamp`, since the Unix epoch.
+    pub fn write(
+        &mut self,
+        interface: u32,
+        timestamp: Duration,
+        data: &[u8],
+        comment: Option<&str>,
+    ) -> Result<(), Error> {
+        let snaplen = *self
+            .interfaces
+            .get(interface as usize)
+            .ok_or(Error::UnknownInterface(interface))?;
+        let captured = match snaplen {
+            0 => data,
+            x => data.get(..x as usize).unwrap_or(data),
+        };
+        let nanos = timestamp.as_nanos() as u64;
+
+        self.begin(ENHANCED_PACKET);
+        self.put(&interface.to_ne_bytes());
+        self.put(&((nanos >> 32) as u32).to_ne_bytes());
+        self.put(&(nanos as u32).to_ne_bytes());
+        self.put(&(captured.len() as u32).to_ne_bytes());
+        self.put(&(data.len() as u32).to_ne_bytes());
+        self.put(captured);
+        self.pad();
+        if let Some(comment) = comment {
+            self.option(OPT_COMMENT, comment.as_bytes());
+        }
+        self.finish(comment.is_some())
+    }
+
+    pub fn flush(&mut self) -> Result<(), Error> {
+        Ok(self.inner.flush()?)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    fn begin(&mut self, kind: u32) {
+        self.block.clear();
+        self.put(&kind.to_ne_bytes());
+        // Total length, set by `finish`.
+        self.put(&[0; 4]);
+    }
+
+    fn put(&mut self, bytes: &[u8]) {
+        self.block.extend_from_slice(bytes);
+    }
+
+    fn pad(&mut self) {
+        let len = self.block.len().next_multiple_of(4);
+        self.block.resize(len, 0);
+    }
+
+    fn option(&mut self, code: u16, value: &[u8]) {
+        self.put(&code.to_ne_bytes());
+        self.put(&(value.len() as u16).to_ne_bytes());
+        self.put(value);
+        self.pad();
+    }
+
+    fn finish(&mut self, options: bool) -> Result<(), Error> {
+        if options {
+            self.option(OPT_END, &[]);
+        }
+        let total = (self.block.len() as u32 + 4).to_ne_bytes();
+        if let Some(len) = self.block.get_mut(4..8) {
+            len.copy_from_slice(&total);
+        }
+        self.put(&total);
+        self.inner.write_all(&self.block)?;
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writer() {
+        let ne = |bytes: &[u8], offset: usize| {
+            u32::from_ne_bytes(bytes[offset..offset + 4].try_into().unwrap())
+        };
+        let mut writer = Writer::new(Vec::new()).unwrap();
+        assert_eq!(
+            writer
+                .add_interface(LinkType::Ethernet, 0, Some("eth0"))
+                .unwrap(),
+            0
+        );
+        assert_eq!(writer.add_interface(LinkType::Raw, 4, None).unwrap(), 1);
+        let timestamp = Duration::new(1_700_000_000, 5);
+        writer
+            .write(1, timestamp, &[0x45, 0, 0, 20, 0, 0], Some("xdp out"))
+            .unwrap();
+        assert!(matches!(
+            writer.write(2, timestamp, &[], None),
+            Err(Error::UnknownInterface(2))
+        ));
+        let file = writer.into_inner();
+
+        let mut blocks = Vec::new();
+        let mut rest = &file[..];
+        while !rest.is_empty() {
+            let len = ne(rest, 4) as usize;
+            assert_eq!(len % 4, 0);
+            assert_eq!(ne(rest, len - 4) as usize, len);
+            blocks.push(&rest[..len]);
+            rest = &rest[len..];
+        }
+        let kinds: Vec<u32> = blocks.iter().map(|b| ne(b, 0)).collect();
+        assert_eq!(
+            kinds,
+            [
+                SECTION_HEADER,
+                INTERFACE_DESCRIPTION,
+                INTERFACE_DESCRIPTION,
+                ENHANCED_PACKET
+            ]
+        );
+        assert_eq!(ne(blocks[0], 8), BYTE_ORDER_MAGIC);
+        assert_eq!(
+            &blocks[1][8..10],
+            u16::from(LinkType::Ethernet).to_ne_bytes()
+        );
+
+        let epb = blocks[3];
+        let nanos = (ne(epb, 12) as u64) << 32 | ne(epb, 16) as u64;
+        assert_eq!(nanos, timestamp.as_nanos() as u64);
+        assert_eq!((ne(epb, 8), ne(epb, 20), ne(epb, 24)), (1, 4, 6));
+        assert_eq!(&epb[28..32], [0x45, 0, 0, 20]);
+        assert_eq!(&epb[36..43], b"xdp out");
+    }
+
+    const FRAME: [u8; 14] = [0x02, 0, 0, 0, 0, 0x02, 0x02, 0, 0, 0, 0, 0x01, 0x88, 0xcc];
+
+    #[test]
+    fn write_and_read() {
+        let timestamp = Duration::new(1_700_000_000, 123_456_789);
+        let mut writer = Writer::new(Vec::new()).unwrap();
+        writer.add_interface(LinkType::Raw, 0, None).unwrap();
+        writer
+            .add_interface(LinkType::Ethernet, 0, Some("eth0"))
+            .unwrap();
+        writer.write(1, timestamp, &FRAME, Some("in")).unwrap();
+        let file = writer.into_inner();
+
+        let mut packets = Packets::new(&file);
+        let packet = packets.next().unwrap().unwrap();
+        assert!(packets.next().is_none());
+        assert_eq!(packets.interfaces()[1].name(), Some("eth0"));
+        assert_eq!(
+            (packet.interface_id, packet.link_type),
+            (1, LinkType::Ethernet)
+        );
+        assert_eq!(packet.timestamp, Some(timestamp));
+        assert_eq!(packet.data, FRAME);
+        assert_eq!(packet.options.comments().collect::<Vec<_>>(), ["in"]);
+        let decoded = packet.decode().unwrap();
+        assert_eq!(
+            decoded.link.ethertype(),
+            crate::link::EtherType::Other(0x88cc)
+        );
+        assert!(decoded.network.is_none());
+    }
+
+    fn block(kind: u32, body: &[u8]) -> Vec<u8> {
+        let len = (12 + body.len().next_multiple_of(4)) as u32;
+        let mut block = [&kind.to_be_bytes()[..], &len.to_be_bytes(), body].concat();
+        block.resize(len as usize - 4, 0);
+        block.extend_from_slice(&len.to_be_bytes());
+        block
+    }
+
+    /// Big endian file with every supported block type.
+    fn big_endian() -> Vec<u8> {
+        let shb = [
+            &BYTE_ORDER_MAGIC.to_be_bytes()[..],
+            &[0, 1, 0, 0],
+            &(-1i64).to_be_bytes(),
+        ]
+        .concat();
+        // Eighths of a second, shifted by 10 s.
+        let idb = [
+            &[0, 1, 0, 0, 0, 0, 0, 8][..],
+            &[0, 9, 0, 1, 0x83, 0, 0, 0],
+            &[0, 14, 0, 8],
+            &10i64.to_be_bytes(),
+            &[0, 0, 0, 0],
+        ]
+        .concat();
+        let epb = [
+            &[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 20][..],
+            &[0, 0, 0, 14, 0, 0, 0, 60],
+            &FRAME,
+        ]
+        .concat();
+        let spb = [&[0, 0, 0, 60][..], &FRAME].concat();
+        let nrb = [
+            &[0, 1, 0, 16, 10, 0, 0, 1][..],
+            b"a\0b.example\0",
+            &[0, 0, 0, 0],
+            &[0, 1, 0, 3],
+            b"dns\0",
+        ]
+        .concat();
+        let custom = [&32473u32.to_be_bytes()[..], b"data"].concat();
+        [
+            block(SECTION_HEADER, &shb),
+            block(INTERFACE_DESCRIPTION, &idb),
+            block(ENHANCED_PACKET, &epb),
+            block(SIMPLE_PACKET, &spb),
+            block(NAME_RESOLUTION, &nrb),
+            block(CUSTOM_NO_COPY, &custom),
+            block(INTERFACE_STATISTICS, &[0, 0, 0, 0]),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn blocks() {
+        let file = big_endian();
+        let blocks: Vec<Block> = Blocks::new(&file).map(Result::unwrap).collect();
+        assert_eq!(blocks.len(), 7);
+        let Block::SectionHeader(shb) = blocks[0] else {
+            panic!()
+        };
+        assert_eq!(
+            (shb.endianness, shb.version_major, shb.section_length),
+            (Endianness::Big, 1, -1)
+        );
+
+        let Block::InterfaceDescription(idb) = blocks[1] else {
+            panic!()
+        };
+        assert_eq!(
+            (idb.link_type, idb.snaplen, idb.units_per_second()),
+            (LinkType::Ethernet, 8, 8)
+        );
+        assert_eq!(idb.timestamp(20), Duration::from_millis(12_500));
+
+        let Block::NameResolution(nrb) = blocks[4] else {
+            panic!()
+        };
+        let records: Vec<NameRecord> = nrb.records().collect();
+        assert_eq!(
+            records,
+            [NameRecord::Ipv4(&[10, 0, 0, 1], b"a\0b.example\0")]
+        );
+        assert_eq!(records[0].names().collect::<Vec<_>>(), ["a", "b.example"]);
+        assert_eq!(nrb.options.get(1), Some(&b"dns"[..]));
+
+        let Block::Custom(custom) = blocks[5] else {
+            panic!()
+        };
+        assert_eq!(
+            (custom.pen, custom.data, custom.copyable),
+            (32473, &b"data"[..], false)
+        );
+        assert!(matches!(blocks[6], Block::Other(INTERFACE_STATISTICS, _)));
+
+        let packets: Vec<Packet> = Packets::new(&file).map(Result::unwrap).collect();
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0].timestamp, Some(Duration::from_millis(12_500)));
+        assert_eq!((packets[0].data, packets[0].original_len), (&FRAME[..], 60));
+        // Truncated to the snaplen.
+        assert_eq!((packets[1].timestamp, packets[1].data), (None, &FRAME[..8]));
+    }
+
+    #[test]
+    fn errors() {
+        let file = big_endian();
+        assert!(matches!(
+            Blocks::new(&file[28..]).next(),
+            Some(Err(Error::NoSection))
+        ));
+        assert!(matches!(
+            Blocks::new(&file[..27]).next(),
+            Some(Err(Error::Truncated))
+        ));
+        let mut bad = file.clone();
+        bad[8] = 0;
+        assert!(matches!(
+            Blocks::new(&bad).next(),
+            Some(Err(Error::InvalidMagic(_)))
+        ));
+        let mut bad = file.clone();
+        bad[7] = 27;
+        assert!(matches!(
+            Blocks::new(&bad).next(),
+            Some(Err(Error::InvalidLength(27)))
+        ));
+
+        let mut packets = Packets::new(&file[..28]);
+        assert!(packets.next().is_none());
+        let no_interface = [&file[..28], &block(SIMPLE_PACKET, &[0, 0, 0, 0])].concat();
+        let mut packets = Packets::new(&no_interface);
+        assert!(matches!(
+            packets.next(),
+            Some(Err(Error::UnknownInterface(0)))
+        ));
+        assert!(packets.next().is_none());
+    }
+}
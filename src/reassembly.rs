@@ -0,0 +1,495 @@
+//! TCP stream reassembly into ordered byte streams, for protocol analysis.
+//!
+//! Segments are fed per `FlowKey` together with their payload and come out
+//! as contiguous chunks per direction through a callback. Sequence numbers
+//! are turned into 64-bit stream offsets relative to the first segment seen,
+//! so wraparound is handled transparently and streams can be picked up
+//! midstream. When a memory cap is hit the hole in front of the buffered data
+//! is skipped and reported as a `Gap` instead of stalling the stream.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::conntrack::Direction;
+use crate::flow::FlowKey;
+use crate::transport::tcp::Tcp;
+
+/// Which copy wins when segments overlap with different contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverlapPolicy {
+    /// Keep the bytes received first (BSD, Windows).
+    #[default]
+    First,
+    /// Keep the bytes received last (Linux for most overlaps, Solaris).
+    Last,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    pub policy: OverlapPolicy,
+    /// Bytes buffered out of order per direction before skipping a hole.
+    pub max_buffered: usize,
+    /// Bytes buffered out of order over all streams.
+    pub max_total: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            policy: OverlapPolicy::First,
+            max_buffered: 1 << 20,
+            max_total: 64 << 20,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event<'a> {
+    /// Next bytes of the stream.
+    Data(&'a [u8]),
+    /// Number of bytes never received, skipped because of the memory caps or
+    /// by `finish`.
+    Gap(u64),
+    /// All data up to the FIN was delivered.
+    Fin,
+    /// The connection was reset, the stream state is dropped.
+    Reset,
+}
+
+#[derive(Debug, Default)]
+struct Half {
+    /// Sequence number of stream offset zero, `None` until the first segment.
+    base: Option<u32>,
+    /// Offset of the next byte to deliver.
+    delivered: u64,
+    /// Out of order data by offset, never overlapping.
+    segments: BTreeMap<u64, Vec<u8>>,
+    buffered: usize,
+    /// Offset of the FIN.
+    fin: Option<u64>,
+    done: bool,
+}
+
+impl Half {
+    /// Stream offset of `seq`, negative for retransmissions that start
+    /// before the first segment seen.
+    fn offset(&self, base: u32, seq: u32) -> i64 {
+        let next = base.wrapping_add(self.delivered as u32);
+        let delta = seq.wrapping_sub(next) as i32 as i64;
+        self.delivered as i64 + delta
+    }
+
+    fn put(&mut self, start: u64, data: &[u8]) {
+        if !data.is_empty() {
+            self.buffered += data.len();
+            self.segments.insert(start, data.to_vec());
+        }
+    }
+
+    fn insert(&mut self, start: u64, data: &[u8], policy: OverlapPolicy) {
+        // Drop what was already delivered.
+        let skip = self.delivered.saturating_sub(start).min(data.len() as u64) as usize;
+        let (start, data) = (start + skip as u64, data.get(skip..).unwrap_or(&[]));
+        if data.is_empty() {
+            return;
+        }
+        let end = start + data.len() as u64;
+        let overlapping: Vec<(u64, u64)> = self
+            .segments
+            .range(..end)
+            .rev()
+            .map(|(&k, v)| (k, k + v.len() as u64))
+            .take_while(|&(_, e)| e > start)
+            .collect();
+        let slice = |from: u64, to: u64| data.get((from - start) as usize..(to - start) as usize);
+
+        match policy {
+            OverlapPolicy::First => {
+                let mut pos = start;
+                for &(s, e) in overlapping.iter().rev() {
+                    if s > pos {
+                        self.put(pos, slice(pos, s).unwrap_or(&[]));
+                    }
+                    pos = pos.max(e);
+                }
+                if pos < end {
+                    self.put(pos, slice(pos, end).unwrap_or(&[]));
+                }
+            }
+            OverlapPolicy::Last => {
+                for &(s, e) in &overlapping {
+                    let Some(old) = self.segments.remove(&s) else {
+                        continue;
+                    };
+                    self.buffered -= old.len();
+                    if s < start {
+                        self.put(s, old.get(..(start - s) as usize).unwrap_or(&[]));
+                    }
+                    if e > end {
+                        self.put(end, old.get((end - s) as usize..).unwrap_or(&[]));
+                    }
+                }
+                self.put(start, data);
+            }
+        }
+    }
+
+    /// Delivers the contiguous data at the head of the buffer.
+    fn drain(&mut self, emit: &mut impl FnMut(Event)) {
+        while let Some(entry) = self.segments.first_entry() {
+            if *entry.key() != self.delivered {
+                break;
+            }
+            let data = entry.remove();
+            self.buffered -= data.len();
+            self.delivered += data.len() as u64;
+            emit(Event::Data(&data));
+        }
+        if self.fin.is_some_and(|fin| fin <= self.delivered) && !self.done {
+            self.done = true;
+            self.buffered = 0;
+            self.segments.clear();
+            emit(Event::Fin);
+        }
+    }
+
+    /// Skips the hole in front of the buffered data, false when nothing is buffered.
+    fn skip_gap(&mut self, emit: &mut impl FnMut(Event)) -> bool {
+        let Some(&start) = self.segments.keys().next() else {
+            return false;
+        };
+        emit(Event::Gap(start - self.delivered));
+        self.delivered = start;
+        self.drain(emit);
+        true
+    }
+}
+
+#[derive(Debug, Default)]
+struct Stream {
+    halves: [Half; 2],
+}
+
+impl Stream {
+    fn half_mut(&mut self, direction: Direction) -> &mut Half {
+        let [original, reply] = &mut self.halves;
+        match direction {
+            Direction::Original => original,
+            Direction::Reply => reply,
+        }
+    }
+
+    fn buffered(&self) -> usize {
+        self.halves.iter().map(|h| h.buffered).sum()
+    }
+}
+
+/// Reassembles the streams of many connections. Streams are keyed by the
+/// flow key of their first segment, which defines the `Original` direction.
+#[derive(Debug, Default)]
+pub struct Reassembler {
+    config: Config,
+    streams: HashMap<FlowKey, Stream>,
+    buffered: usize,
+}
+
+impl Reassembler {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    /// Number of streams tracked.
+    pub fn len(&self) -> usize {
+        self.streams.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.streams.is_empty()
+    }
+
+    /// Bytes buffered out of order over all streams.
+    pub fn buffered(&self) -> usize {
+        self.buffered
+    }
+
+    /// Feeds a segment of flow `key`. `payload` is the TCP payload, without
+    /// link layer padding. Events are reported with the key of the stream.
+    pub fn process<P: AsRef<[u8]>, F: FnMut(&FlowKey, Direction, Event)>(
+        &mut self,
+        key: &FlowKey,
+        tcp: &Tcp<P>,
+        payload: &[u8],
+        mut on_event: F,
+    ) -> Direction {
+        let reversed = key.reversed();
+        let (stream_key, direction) = if self.streams.contains_key(key) {
+            (*key, Direction::Original)
+        } else if self.streams.contains_key(&reversed) {
+            (reversed, Direction::Reply)
+        } else {
+            (*key, Direction::Original)
+        };
+        let mut emit = |event: Event| on_event(&stream_key, direction, event);
+
+        if tcp.rst() {
+            if let Some(stream) = self.streams.remove(&stream_key) {
+                self.buffered -= stream.buffered();
+                emit(Event::Reset);
+            }
+            return direction;
+        }
+        // A new SYN on a finished or reused tuple starts over.
+        if tcp.syn() && !tcp.ack() {
+            if let Some(stream) = self.streams.remove(&stream_key) {
+                self.buffered -= stream.buffered();
+            }
+        }
+
+        let stream = self.streams.entry(stream_key).or_default();
+        let half = stream.half_mut(direction);
+        if half.done {
+            return direction;
+        }
+        let seq = tcp.sequence_num().wrapping_add(tcp.syn() as u32);
+        let base = *half.base.get_or_insert(seq);
+        let start = half.offset(base, seq);
+        let end = start + payload.len() as i64;
+        if tcp.fin() && half.fin.is_none() {
+            half.fin = Some(end.max(0) as u64);
+        }
+        // Bytes in front of offset zero were never part of the stream.
+        let trim = (-start).clamp(0, payload.len() as i64) as usize;
+        let (start, payload) = ((start + trim as i64) as u64, &payload[trim..]);
+
+        let before = half.buffered;
+        half.insert(start, payload, self.config.policy);
+        half.drain(&mut emit);
+        self.buffered = self.buffered + half.buffered - before;
+        while half.buffered > self.config.max_buffered || self.buffered > self.config.max_total {
+            let before = half.buffered;
+            if !half.skip_gap(&mut emit) {
+                break;
+            }
+            self.buffered = self.buffered + half.buffered - before;
+        }
+
+        if stream.halves.iter().all(|h| h.done) {
+            self.streams.remove(&stream_key);
+        }
+        direction
+    }
+
+    /// Drops the stream of `key` in either direction, e.g. on timeout.
+    pub fn remove(&mut self, key: &FlowKey) {
+        for key in [*key, key.reversed()] {
+            if let Some(stream) = self.streams.remove(&key) {
+                self.buffered -= stream.buffered();
+            }
+        }
+    }
+
+    /// Delivers everything still buffered, skipping holes, and drops all
+    /// streams. Halves that saw a FIN end with `Event::Fin`.
+    pub fn finish<F: FnMut(&FlowKey, Direction, Event)>(&mut self, mut on_event: F) {
+        for (key, mut stream) in self.streams.drain() {
+            for direction in [Direction::Original, Direction::Reply] {
+                let half = stream.half_mut(direction);
+                let mut emit = |event: Event| on_event(&key, direction, event);
+                while half.skip_gap(&mut emit) {}
+                // A FIN behind a hole with nothing buffered after it.
+                if let Some(fin) = half.fin.filter(|_| !half.done) {
+                    emit(Event::Gap(fin - half.delivered));
+                    half.done = true;
+                    emit(Event::Fin);
+                }
+            }
+        }
+        self.buffered = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::InetProtocol;
+
+    const SYN: u8 = 0x02;
+    const RST: u8 = 0x04;
+    const ACK: u8 = 0x10;
+    const FIN: u8 = 0x01;
+
+    #[derive(Debug, PartialEq)]
+    enum Owned {
+        Data(Direction, Vec<u8>),
+        Gap(u64),
+        Fin(Direction),
+        Reset,
+    }
+
+    struct Harness {
+        reassembler: Reassembler,
+        key: FlowKey,
+        events: Vec<Owned>,
+    }
+
+    impl Harness {
+        fn new(config: Config) -> Self {
+            Self {
+                reassembler: Reassembler::new(config),
+                key: FlowKey::ipv4(&[10, 0, 0, 1], &[10, 0, 0, 2], InetProtocol::TCP, 40000, 80),
+                events: Vec::new(),
+            }
+        }
+
+        fn send(&mut self, direction: Direction, seq: u32, flags: u8, payload: &[u8]) {
+            let mut header = [0u8; 20];
+            header[4..8].copy_from_slice(&seq.to_be_bytes());
+            header[12] = 5 << 4;
+            header[13] = flags;
+            let (tcp, _) = Tcp::new(&header).unwrap();
+            let key = match direction {
+                Direction::Original => self.key,
+                Direction::Reply => self.key.reversed(),
+            };
+            let events = &mut self.events;
+            let dir = self
+                .reassembler
+                .process(&key, &tcp, payload, |_, d, event| {
+                    events.push(owned(d, event))
+                });
+            assert_eq!(dir, direction);
+        }
+
+        fn data(&self, direction: Direction) -> Vec<u8> {
+            self.events
+                .iter()
+                .filter_map(|e| match e {
+                    Owned::Data(d, data) if *d == direction => Some(data.as_slice()),
+                    _ => None,
+                })
+                .flatten()
+                .copied()
+                .collect()
+        }
+    }
+
+    fn owned(direction: Direction, event: Event) -> Owned {
+        match event {
+            Event::Data(data) => Owned::Data(direction, data.to_vec()),
+            Event::Gap(n) => Owned::Gap(n),
+            Event::Fin => Owned::Fin(direction),
+            Event::Reset => Owned::Reset,
+        }
+    }
+
+    use Direction::{Original, Reply};
+
+    #[test]
+    fn out_of_order_and_fin() {
+        let mut h = Harness::new(Config::default());
+        h.send(Original, 1000, SYN, b"");
+        h.send(Reply, 5000, SYN | ACK, b"");
+        h.send(Original, 1007, ACK, b"world");
+        h.send(Original, 1004, ACK, b"lo ");
+        h.send(Original, 1001, ACK, b"hel");
+        h.send(Original, 1001, ACK, b"hel");
+        assert_eq!(h.data(Original), b"hello world");
+        assert_eq!(h.reassembler.buffered(), 0);
+
+        h.send(Reply, 5001, ACK | FIN, b"bye");
+        h.send(Original, 1012, ACK | FIN, b"");
+        assert!(h.events.contains(&Owned::Fin(Reply)));
+        assert!(h.events.contains(&Owned::Fin(Original)));
+        assert_eq!(h.data(Reply), b"bye");
+        assert!(h.reassembler.is_empty());
+    }
+
+    #[test]
+    fn overlap_policies() {
+        for (policy, expected) in [
+            (OverlapPolicy::First, b"abYdefXX"),
+            (OverlapPolicy::Last, b"abcYYYXX"),
+        ] {
+            let mut h = Harness::new(Config {
+                policy,
+                ..Default::default()
+            });
+            h.send(Original, 99, SYN, b"");
+            h.send(Original, 104, ACK, b"ef");
+            h.send(Original, 103, ACK, b"d");
+            h.send(Original, 106, ACK, b"XX");
+            h.send(Original, 102, ACK, b"YYYY");
+            h.send(Original, 100, ACK, b"abc");
+            assert_eq!(h.data(Original), expected, "{policy:?}");
+        }
+    }
+
+    #[test]
+    fn wraparound_midstream() {
+        let mut h = Harness::new(Config::default());
+        h.send(Original, u32::MAX - 2, ACK, b"abc");
+        h.send(Original, 3, ACK, b"gh");
+        h.send(Original, 0, ACK, b"de");
+        h.send(Original, 2, ACK, b"");
+        assert_eq!(h.data(Original), b"abcde");
+        h.send(Original, 2, ACK, b"fgh");
+        assert_eq!(h.data(Original), b"abcdefgh");
+    }
+
+    #[test]
+    fn retransmission_before_first_segment() {
+        let mut h = Harness::new(Config::default());
+        h.send(Original, 1000, ACK, b"abc");
+        h.send(Original, 995, ACK, b"VWXYZabcdef");
+        h.send(Original, 990, ACK, b"VWXYZ");
+        assert_eq!(h.data(Original), b"abcdef");
+        assert_eq!(h.reassembler.buffered(), 0);
+    }
+
+    #[test]
+    fn caps_and_reset() {
+        let mut h = Harness::new(Config {
+            max_buffered: 4,
+            ..Default::default()
+        });
+        h.send(Original, 0, ACK, b"a");
+        h.send(Original, 5, ACK, b"xyz");
+        assert_eq!(h.reassembler.buffered(), 3);
+        h.send(Original, 10, ACK, b"uv");
+        assert_eq!(h.events[1], Owned::Gap(4));
+        assert_eq!(h.data(Original), b"axyz");
+        assert_eq!(h.reassembler.buffered(), 2);
+
+        h.send(Reply, 0, RST, b"");
+        assert_eq!(h.events.last(), Some(&Owned::Reset));
+        assert_eq!(h.reassembler.buffered(), 0);
+        assert!(h.reassembler.is_empty());
+    }
+
+    #[test]
+    fn finish() {
+        let mut h = Harness::new(Config::default());
+        h.send(Original, 0, ACK, b"a");
+        h.send(Original, 3, ACK, b"d");
+        h.send(Original, 5, ACK | FIN, b"f");
+        h.send(Reply, 0, ACK, b"b");
+        h.send(Reply, 5, ACK | FIN, b"");
+        let events = &mut h.events;
+        h.reassembler.finish(|_, d, e| events.push(owned(d, e)));
+        assert_eq!(
+            h.events[2..],
+            [
+                Owned::Gap(2),
+                Owned::Data(Original, b"d".to_vec()),
+                Owned::Gap(1),
+                Owned::Data(Original, b"f".to_vec()),
+                Owned::Fin(Original),
+                Owned::Gap(4),
+                Owned::Fin(Reply),
+            ]
+        );
+        assert!(h.reassembler.is_empty());
+    }
+}
@@ -0,0 +1,161 @@
+//! Sends a packet back where it came from by swapping the endpoints of every
+//! layer, as done by XDP responders before returning `XDP_TX`.
+//!
+//! Swapping keeps every checksum valid: the IPv4 header sum does not depend
+//! on the order of its words and the TCP/UDP pseudo header sees the same
+//! addresses, just swapped along with the ports.
+
+use crate::link::{eth, EtherType, Ethernet};
+use crate::network::{ipv4, ipv6, IPv4, IPv6, InetProtocol};
+use crate::transport::tcp::{self, Tcp};
+use crate::transport::udp::{self, Udp};
+
+#[derive(Debug)]
+pub enum Error {
+    Ethernet(eth::Error),
+    IPv4(ipv4::Error),
+    IPv6(ipv6::Error),
+    Tcp(tcp::Error),
+    Udp(udp::Error),
+    Unsupported(EtherType),
+}
+
+impl From<eth::Error> for Error {
+    fn from(value: eth::Error) -> Self {
+        Error::Ethernet(value)
+    }
+}
+
+impl From<ipv4::Error> for Error {
+    fn from(value: ipv4::Error) -> Self {
+        Error::IPv4(value)
+    }
+}
+
+impl From<ipv6::Error> for Error {
+    fn from(value: ipv6::Error) -> Self {
+        Error::IPv6(value)
+    }
+}
+
+impl From<tcp::Error> for Error {
+    fn from(value: tcp::Error) -> Self {
+        Error::Tcp(value)
+    }
+}
+
+impl From<udp::Error> for Error {
+    fn from(value: udp::Error) -> Self {
+        Error::Udp(value)
+    }
+}
+
+/// Where the headers of a reflected frame start, for responders that go on
+/// rewriting the L4 header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reflected {
+    pub protocol: InetProtocol,
+    pub l3_offset: usize,
+    pub l4_offset: usize,
+}
+
+/// Reflects an Ethernet frame carrying IPv4 or IPv6, swapping MAC and IP
+/// addresses plus TCP/UDP ports. Other transport protocols and non-first
+/// fragments only get their addresses swapped.
+pub fn reflect(packet: &mut [u8]) -> Result<Reflected, Error> {
+    let (mut eth, rem) = Ethernet::new_mut(packet)?;
+    let l3_offset = eth.size_usize();
+
+    let (protocol, l4_len) = match eth.ethertype() {
+        EtherType::IPv4 => reflect_ipv4(rem)?,
+        EtherType::IPv6 => reflect_ipv6(rem)?,
+        x => return Err(Error::Unsupported(x)),
+    };
+    eth.reflect();
+
+    Ok(Reflected {
+        protocol,
+        l3_offset,
+        l4_offset: l3_offset + l4_len,
+    })
+}
+
+/// Reflects an IPv4 packet, returning its protocol and header length.
+pub fn reflect_ipv4(packet: &mut [u8]) -> Result<(InetProtocol, usize), Error> {
+    let (mut ip, l4) = IPv4::new_mut(packet)?;
+    if ip.fragment_offset() == [0, 0] {
+        reflect_l4(ip.protocol(), l4)?;
+    }
+    ip.reflect();
+    Ok((ip.protocol(), ip.size() as usize))
+}
+
+/// Reflects an IPv6 packet, returning its next header and header length.
+/// Extension headers are not followed.
+pub fn reflect_ipv6(packet: &mut [u8]) -> Result<(InetProtocol, usize), Error> {
+    let (mut ip, l4) = IPv6::new_mut(packet)?;
+    reflect_l4(ip.next_header(), l4)?;
+    ip.reflect();
+    Ok((ip.next_header(), IPv6::SIZE))
+}
+
+fn reflect_l4(protocol: InetProtocol, l4: &mut [u8]) -> Result<(), Error> {
+    match protocol {
+        InetProtocol::TCP => Tcp::new_mut(l4)?.0.reflect(),
+        InetProtocol::UDP => Udp::new_mut(l4)?.0.reflect(),
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checksum;
+
+    #[test]
+    fn reflect_udp() {
+        let mut packet = [
+            0x02, 0x00, 0x00, 0x00, 0x00, 0x02, 0x02, 0x00, 0x00, 0x00, 0x00, 0x01, 0x08, 0x00,
+            0x45, 0x00, 0x00, 0x20, 0x00, 0x01, 0x40, 0x00, 0x40, 0x11, 0x00, 0x00, 0x0a, 0x00,
+            0x00, 0x01, 0x0a, 0x00, 0x00, 0x02, 0x30, 0x39, 0x00, 0x35, 0x00, 0x0c, 0x00, 0x00,
+            0xde, 0xad, 0xbe, 0xef,
+        ];
+        let (mut ip, rem) = IPv4::new_mut(&mut packet[14..]).unwrap();
+        ip.update_csum();
+        let sum = checksum::pseudo_ipv4(ip.source(), ip.destination(), 17, 12);
+        let csum = checksum::finish(checksum::sum(sum, rem));
+        rem[6..8].copy_from_slice(&csum.to_be_bytes());
+
+        let reflected = reflect(&mut packet).unwrap();
+        assert_eq!(
+            reflected,
+            Reflected {
+                protocol: InetProtocol::UDP,
+                l3_offset: 14,
+                l4_offset: 34
+            }
+        );
+
+        let (eth, rem) = Ethernet::new(&packet).unwrap();
+        assert_eq!(eth.source(), &[0x02, 0, 0, 0, 0, 0x02]);
+        assert_eq!(eth.destination(), &[0x02, 0, 0, 0, 0, 0x01]);
+        let (ip, rem) = IPv4::new(rem).unwrap();
+        assert_eq!(ip.source(), &[10, 0, 0, 2]);
+        assert_eq!(ip.csum(), ip.calc_csum());
+        let sum = checksum::pseudo_ipv4(ip.source(), ip.destination(), 17, 12);
+        assert_eq!(checksum::finish(checksum::sum(sum, rem)), 0);
+        let (udp, _) = Udp::new(rem).unwrap();
+        assert_eq!((udp.source(), udp.destination()), (53, 12345));
+    }
+
+    #[test]
+    fn unsupported() {
+        let mut packet = [0u8; 14];
+        packet[12..14].copy_from_slice(&[0x08, 0x06]);
+        assert!(matches!(
+            reflect(&mut packet),
+            Err(Error::Unsupported(EtherType::Arp))
+        ));
+    }
+}
@@ -0,0 +1,311 @@
+//! Toeplitz hash as computed by NICs for receive side scaling, to find out
+//! which queue a packet lands on.
+//!
+//! Inputs follow the Microsoft RSS specification: source address, destination
+//! address, then source and destination ports for the 4-tuple variants, all
+//! in network byte order.
+
+use crate::flow::FlowKey;
+use crate::network::{IPv4, IPv6, InetProtocol};
+use crate::transport::tcp::Tcp;
+use crate::transport::udp::Udp;
+
+/// Key of the Microsoft verification suite, also the default of many drivers.
+pub const DEFAULT_KEY: [u8; 40] = [
+    0x6d, 0x5a, 0x56, 0xda, 0x25, 0x5b, 0x0e, 0xc2, 0x41, 0x67, 0x25, 0x3d, 0x43, 0xa3, 0x8f, 0xb0,
+    0xd0, 0xca, 0x2b, 0xcb, 0xae, 0x7b, 0x30, 0xb4, 0x77, 0xcb, 0x2d, 0xa3, 0x80, 0x30, 0xf2, 0x0c,
+    0x6a, 0x42, 0xb7, 0x3b, 0xbe, 0xac, 0x01, 0xfa,
+];
+
+/// Toeplitz hash of `input`. The key needs at least 4 bytes more than the
+/// input, missing key bits are taken as zero.
+pub fn toeplitz(key: &[u8], input: &[u8]) -> u32 {
+    let mut window = key
+        .first_chunk::<4>()
+        .map_or(0, |bytes| u32::from_be_bytes(*bytes));
+    let mut result = 0;
+    for (i, byte) in input.iter().enumerate() {
+        let next = key.get(i + 4).copied().unwrap_or(0);
+        for bit in 0..8 {
+            if byte & (0x80 >> bit) != 0 {
+                result ^= window;
+            }
+            window = (window << 1) | ((next >> (7 - bit)) & 1) as u32;
+        }
+    }
+    result
+}
+
+/// Hash input, up to the 36 bytes of an IPv6 4-tuple.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Input {
+    bytes: [u8; 36],
+    len: usize,
+}
+
+impl Input {
+    pub const MAX_LEN: usize = 36;
+
+    fn from_parts(parts: &[&[u8]]) -> Self {
+        let mut bytes = [0; Input::MAX_LEN];
+        let mut len = 0;
+        for part in parts {
+            if let Some(dst) = bytes.get_mut(len..len + part.len()) {
+                dst.copy_from_slice(part);
+                len += part.len();
+            }
+        }
+        Self { bytes, len }
+    }
+
+    pub fn ipv4(source: &[u8; 4], destination: &[u8; 4]) -> Self {
+        Self::from_parts(&[source, destination])
+    }
+
+    pub fn ipv4_ports(
+        source: &[u8; 4],
+        destination: &[u8; 4],
+        source_port: u16,
+        destination_port: u16,
+    ) -> Self {
+        Self::from_parts(&[
+            source,
+            destination,
+            &source_port.to_be_bytes(),
+            &destination_port.to_be_bytes(),
+        ])
+    }
+
+    pub fn ipv6(source: &[u8; 16], destination: &[u8; 16]) -> Self {
+        Self::from_parts(&[source, destination])
+    }
+
+    pub fn ipv6_ports(
+        source: &[u8; 16],
+        destination: &[u8; 16],
+        source_port: u16,
+        destination_port: u16,
+    ) -> Self {
+        Self::from_parts(&[
+            source,
+            destination,
+            &source_port.to_be_bytes(),
+            &destination_port.to_be_bytes(),
+        ])
+    }
+
+    /// 4-tuple for unfragmented TCP and UDP, 2-tuple otherwise. Like NICs,
+    /// the first fragment is hashed without ports too so that all fragments
+    /// of a datagram land on the same queue.
+    pub fn from_ipv4<P: AsRef<[u8]>>(ip: &IPv4<P>, l4: &[u8]) -> Self {
+        match ports(ip.protocol(), l4) {
+            Some((s, d)) if ip.fragment_offset() == [0, 0] && !ip.more_fragments() => {
+                Self::ipv4_ports(ip.source(), ip.destination(), s, d)
+            }
+            _ => Self::ipv4(ip.source(), ip.destination()),
+        }
+    }
+
+    /// 4-tuple for TCP and UDP, 2-tuple otherwise. Extension headers are not followed.
+    pub fn from_ipv6<P: AsRef<[u8]>>(ip: &IPv6<P>, l4: &[u8]) -> Self {
+        match ports(ip.next_header(), l4) {
+            Some((s, d)) => Self::ipv6_ports(ip.source(), ip.destination(), s, d),
+            None => Self::ipv6(ip.source(), ip.destination()),
+        }
+    }
+
+    /// 4-tuple when the key has ports, 2-tuple otherwise.
+    pub fn from_flow(key: &FlowKey) -> Self {
+        let has_ports = key.source_port != 0 || key.destination_port != 0;
+        match (key.source_ipv4(), key.destination_ipv4()) {
+            (Some(s), Some(d)) if has_ports => {
+                Self::ipv4_ports(&s, &d, key.source_port, key.destination_port)
+            }
+            (Some(s), Some(d)) => Self::ipv4(&s, &d),
+            _ if has_ports => Self::ipv6_ports(
+                &key.source,
+                &key.destination,
+                key.source_port,
+                key.destination_port,
+            ),
+            _ => Self::ipv6(&key.source, &key.destination),
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.bytes.get(..self.len).unwrap_or(&[])
+    }
+
+    pub fn hash(&self, key: &[u8]) -> u32 {
+        toeplitz(key, self.as_bytes())
+    }
+}
+
+fn ports(protocol: InetProtocol, l4: &[u8]) -> Option<(u16, u16)> {
+    match protocol {
+        InetProtocol::TCP => Tcp::new(l4)
+            .ok()
+            .map(|(t, _)| (t.source(), t.destination())),
+        InetProtocol::UDP => Udp::new(l4)
+            .ok()
+            .map(|(u, _)| (u.source(), u.destination())),
+        _ => None,
+    }
+}
+
+/// Maps hashes to receive queues like the NIC's redirection table, indexed
+/// by the hash modulo the table length. For the usual power-of-two lengths
+/// that is the low bits of the hash, as on hardware.
+pub struct IndirectionTable<'a> {
+    entries: &'a [u16],
+}
+
+impl<'a> IndirectionTable<'a> {
+    pub fn new(entries: &'a [u16]) -> Self {
+        Self { entries }
+    }
+
+    /// Spreads `queues` evenly over `entries`, the default of `ethtool -X equal`.
+    pub fn fill_equal(entries: &mut [u16], queues: u16) {
+        for (i, entry) in entries.iter_mut().enumerate() {
+            *entry = i.checked_rem(queues as usize).unwrap_or(0) as u16;
+        }
+    }
+
+    /// Queue for `hash`, `None` for an empty table.
+    pub fn queue(&self, hash: u32) -> Option<u16> {
+        let index = (hash as usize).checked_rem(self.entries.len())?;
+        self.entries.get(index).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::net::{Ipv4Addr, Ipv6Addr};
+
+    fn v4(s: &str) -> [u8; 4] {
+        s.parse::<Ipv4Addr>().unwrap().octets()
+    }
+
+    fn v6(s: &str) -> [u8; 16] {
+        s.parse::<Ipv6Addr>().unwrap().octets()
+    }
+
+    #[test]
+    fn microsoft_ipv4() {
+        let vectors = [
+            (
+                "66.9.149.187",
+                2794,
+                "161.142.100.80",
+                1766,
+                0x323e8fc2,
+                0x51ccc178,
+            ),
+            (
+                "199.92.111.2",
+                14230,
+                "65.69.140.83",
+                4739,
+                0xd718262a,
+                0xc626b0ea,
+            ),
+            (
+                "24.19.198.95",
+                12898,
+                "12.22.207.184",
+                38024,
+                0xd2d0a5de,
+                0x5c2b394a,
+            ),
+            (
+                "38.27.205.30",
+                48228,
+                "209.142.163.6",
+                2217,
+                0x82989176,
+                0xafc7327f,
+            ),
+            (
+                "153.39.163.191",
+                44251,
+                "202.188.127.2",
+                1303,
+                0x5d1809c5,
+                0x10e828a2,
+            ),
+        ];
+        for (src, sport, dst, dport, two, four) in vectors {
+            let (src, dst) = (v4(src), v4(dst));
+            assert_eq!(Input::ipv4(&src, &dst).hash(&DEFAULT_KEY), two);
+            assert_eq!(
+                Input::ipv4_ports(&src, &dst, sport, dport).hash(&DEFAULT_KEY),
+                four
+            );
+        }
+    }
+
+    #[test]
+    fn microsoft_ipv6() {
+        let vectors = [
+            (
+                "3ffe:2501:200:1fff::7",
+                2794,
+                "3ffe:2501:200:3::1",
+                1766,
+                0x2cc18cd5,
+                0x40207d3d,
+            ),
+            (
+                "3ffe:501:8::260:97ff:fe40:efab",
+                14230,
+                "ff02::1",
+                4739,
+                0x0f0c461c,
+                0xdde51bbf,
+            ),
+            (
+                "3ffe:1900:4545:3:200:f8ff:fe21:67cf",
+                44251,
+                "fe80::200:f8ff:fe21:67cf",
+                38024,
+                0x4b61e985,
+                0x02d1feef,
+            ),
+        ];
+        for (src, sport, dst, dport, two, four) in vectors {
+            let (src, dst) = (v6(src), v6(dst));
+            assert_eq!(Input::ipv6(&src, &dst).hash(&DEFAULT_KEY), two);
+            assert_eq!(
+                Input::ipv6_ports(&src, &dst, sport, dport).hash(&DEFAULT_KEY),
+                four
+            );
+        }
+    }
+
+    #[test]
+    fn from_headers() {
+        let packet = [
+            0x45, 0x00, 0x00, 0x1c, 0x00, 0x00, 0x00, 0x00, 0x40, 0x11, 0x00, 0x00, 66, 9, 149,
+            187, 161, 142, 100, 80, 0x0a, 0xea, 0x06, 0xe6, 0x00, 0x08, 0x00, 0x00,
+        ];
+        let (ip, l4) = IPv4::new(&packet).unwrap();
+        let input = Input::from_ipv4(&ip, l4);
+        assert_eq!(input.hash(&DEFAULT_KEY), 0x51ccc178);
+        assert_eq!(Input::from_flow(&FlowKey::from_ipv4(&ip, l4)), input);
+
+        let mut first_fragment = packet;
+        first_fragment[6] = 0x20;
+        let (ip, l4) = IPv4::new(&first_fragment).unwrap();
+        let input = Input::from_ipv4(&ip, l4);
+        assert_eq!(input.hash(&DEFAULT_KEY), 0x323e8fc2);
+        assert_eq!(Input::from_flow(&FlowKey::from_ipv4(&ip, l4)), input);
+
+        let mut entries = [0; 128];
+        IndirectionTable::fill_equal(&mut entries, 6);
+        let table = IndirectionTable::new(&entries);
+        assert_eq!(table.queue(0x51ccc178), Some((0x78 % 6) as u16));
+        assert_eq!(IndirectionTable::new(&[]).queue(1), None);
+    }
+}
@@ -0,0 +1,259 @@
+//! tcpdump-style one-line summaries of decoded packets, e.g.
+//! `IP 10.0.0.1.443 > 10.0.0.2.51514: Flags [S.], seq 1, ack 2, win 65535, length 0`.
+//!
+//! Everything goes through [`core::fmt::Write`], so summaries can be written
+//! into a fixed buffer without allocating; `Decoded` also implements
+//! `Display` on top of [`write`].
+
+use core::fmt::{self, Display, Write};
+use core::net::{Ipv4Addr, Ipv6Addr};
+
+use crate::decode::{Decoded, Network, Transport};
+use crate::network::InetProtocol;
+use crate::transport::icmp::IcmpType;
+use crate::transport::icmpv6::Icmpv6Type;
+use crate::transport::tcp::Tcp;
+
+/// Writes the summary line of `decoded` to `out`, without a trailing newline.
+pub fn write<W: Write>(out: &mut W, decoded: &Decoded) -> fmt::Result {
+    let Some(network) = &decoded.network else {
+        let ethertype = decoded.link.ethertype();
+        return write!(
+            out,
+            "ethertype {:?} (0x{:04x}), length {}",
+            ethertype,
+            u16::from(ethertype),
+            decoded.payload.len()
+        );
+    };
+    match network {
+        Network::IPv4(ip) => {
+            let source = Ipv4Addr::from(*ip.source());
+            let destination = Ipv4Addr::from(*ip.destination());
+            out.write_str("IP ")?;
+            write_transport(out, decoded, source, destination)
+        }
+        Network::IPv6(ip) => {
+            let source = Ipv6Addr::from(*ip.source());
+            let destination = Ipv6Addr::from(*ip.destination());
+            out.write_str("IP6 ")?;
+            write_transport(out, decoded, source, destination)
+        }
+    }
+}
+
+fn write_transport<W: Write, A: Display>(
+    out: &mut W,
+    decoded: &Decoded,
+    source: A,
+    destination: A,
+) -> fmt::Result {
+    let length = decoded.payload.len();
+    match &decoded.transport {
+        Some(Transport::Tcp(tcp)) => {
+            write!(
+                out,
+                "{}.{} > {}.{}: Flags [",
+                source,
+                tcp.source(),
+                destination,
+                tcp.destination()
+            )?;
+            write_flags(out, tcp)?;
+            write!(out, "], seq {}", tcp.sequence_num())?;
+            if length > 0 {
+                let end = tcp.sequence_num().wrapping_add(length as u32);
+                write!(out, ":{}", end)?;
+            }
+            if tcp.ack() {
+                write!(out, ", ack {}", tcp.ack_num())?;
+            }
+            write!(out, ", win {}, length {}", tcp.window_size(), length)
+        }
+        Some(Transport::Udp(udp)) => write!(
+            out,
+            "{}.{} > {}.{}: UDP, length {}",
+            source,
+            udp.source(),
+            destination,
+            udp.destination(),
+            length
+        ),
+        Some(Transport::Icmp(icmp)) => {
+            write!(out, "{} > {}: ICMP ", source, destination)?;
+            let icmp_type = icmp.icmp_type();
+            match icmp_type {
+                IcmpType::EchoRequest => out.write_str("echo request")?,
+                IcmpType::EchoReply => out.write_str("echo reply")?,
+                IcmpType::DestinationUnreachable => out.write_str("unreachable")?,
+                IcmpType::TimeExceeded => out.write_str("time exceeded in-transit")?,
+                IcmpType::Other(x) => write!(out, "type-#{}", x)?,
+                x => write!(out, "{:?}", x)?,
+            }
+            if icmp_type.is_query() {
+                write!(
+                    out,
+                    ", id {}, seq {}",
+                    icmp.identifier(),
+                    icmp.sequence_num()
+                )?;
+            }
+            write!(out, ", length {}", icmp.slice().len() + length)
+        }
+        Some(Transport::Icmpv6(icmp)) => {
+            write!(out, "{} > {}: ICMP6, ", source, destination)?;
+            let icmp_type = icmp.icmp_type();
+            match icmp_type {
+                Icmpv6Type::EchoRequest => out.write_str("echo request")?,
+                Icmpv6Type::EchoReply => out.write_str("echo reply")?,
+                Icmpv6Type::DestinationUnreachable => out.write_str("destination unreachable")?,
+                Icmpv6Type::PacketTooBig => out.write_str("packet too big")?,
+                Icmpv6Type::TimeExceeded => out.write_str("time exceeded in-transit")?,
+                Icmpv6Type::NeighborSolicitation => out.write_str("neighbor solicitation")?,
+                Icmpv6Type::NeighborAdvertisement => out.write_str("neighbor advertisement")?,
+                Icmpv6Type::Other(x) => write!(out, "type-#{}", x)?,
+                x => write!(out, "{:?}", x)?,
+            }
+            if matches!(icmp_type, Icmpv6Type::EchoRequest | Icmpv6Type::EchoReply) {
+                write!(
+                    out,
+                    ", id {}, seq {}",
+                    icmp.identifier(),
+                    icmp.sequence_num()
+                )?;
+            }
+            write!(out, ", length {}", icmp.slice().len() + length)
+        }
+        None => {
+            let protocol = decoded
+                .network
+                .as_ref()
+                .map_or(InetProtocol::Other(0), Network::protocol);
+            write!(out, "{} > {}: ", source, destination)?;
+            match protocol {
+                InetProtocol::Other(x) => write!(out, "ip-proto-{}", x)?,
+                x => write!(out, "{:?}", x)?,
+            }
+            write!(out, ", length {}", length)
+        }
+    }
+}
+
+/// Flags in tcpdump's notation, `.` for ACK and `none` if no flag is set.
+fn write_flags<W: Write>(out: &mut W, tcp: &Tcp<&[u8]>) -> fmt::Result {
+    let flags = [
+        (tcp.fin(), 'F'),
+        (tcp.syn(), 'S'),
+        (tcp.rst(), 'R'),
+        (tcp.psh(), 'P'),
+        (tcp.ack(), '.'),
+        (tcp.urg(), 'U'),
+        (tcp.ece(), 'E'),
+        (tcp.cwr(), 'W'),
+    ];
+    if flags.iter().all(|(set, _)| !set) {
+        return out.write_str("none");
+    }
+    for (_, c) in flags.iter().filter(|(set, _)| *set) {
+        out.write_char(*c)?;
+    }
+    Ok(())
+}
+
+impl Display for Decoded<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write(f, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::decode;
+    use crate::pcap::LinkType;
+
+    /// Fixed buffer, the way a no_std caller would write a summary.
+    struct Buf {
+        data: [u8; 128],
+        len: usize,
+    }
+
+    impl Write for Buf {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            let dst = self
+                .data
+                .get_mut(self.len..self.len + s.len())
+                .ok_or(fmt::Error)?;
+            dst.copy_from_slice(s.as_bytes());
+            self.len += s.len();
+            Ok(())
+        }
+    }
+
+    fn summary(link_type: LinkType, data: &[u8]) -> Buf {
+        let decoded = decode(link_type, data).unwrap();
+        let mut buf = Buf {
+            data: [0; 128],
+            len: 0,
+        };
+        write(&mut buf, &decoded).unwrap();
+        buf
+    }
+
+    fn as_str(buf: &Buf) -> &str {
+        core::str::from_utf8(&buf.data[..buf.len]).unwrap()
+    }
+
+    #[test]
+    fn tcp_udp_icmp() {
+        let ip = [
+            0x45, 0, 0, 40, 0, 0, 0x40, 0, 64, 6, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2, //
+        ];
+        let tcp = [
+            0x01, 0xbb, 0xc9, 0x3a, 0, 0, 0, 1, 0, 0, 0, 2, 0x50, 0x12, 0xff, 0xff, 0, 0, 0, 0,
+        ];
+        let packet = [&ip[..], &tcp].concat();
+        assert_eq!(
+            as_str(&summary(LinkType::Raw, &packet)),
+            "IP 10.0.0.1.443 > 10.0.0.2.51514: Flags [S.], seq 1, ack 2, win 65535, length 0"
+        );
+
+        let mut packet = [&ip[..], &tcp, b"abc"].concat();
+        packet[3] = 43;
+        packet[33] = 0x18;
+        assert_eq!(
+            as_str(&summary(LinkType::Raw, &packet)),
+            "IP 10.0.0.1.443 > 10.0.0.2.51514: Flags [P.], seq 1:4, ack 2, win 65535, length 3"
+        );
+
+        let mut packet = [&ip[..], &[0, 53, 0x30, 0x39, 0, 10, 0, 0], b"hi"].concat();
+        packet[3] = 30;
+        packet[9] = 17;
+        assert_eq!(
+            as_str(&summary(LinkType::Raw, &packet)),
+            "IP 10.0.0.1.53 > 10.0.0.2.12345: UDP, length 2"
+        );
+
+        let mut packet = [&ip[..], &[8, 0, 0, 0, 0, 7, 0, 1], b"ping"].concat();
+        packet[3] = 32;
+        packet[9] = 1;
+        let decoded = decode(LinkType::Raw, &packet).unwrap();
+        let mut buf = Buf {
+            data: [0; 128],
+            len: 0,
+        };
+        core::write!(buf, "{}", decoded).unwrap();
+        assert_eq!(
+            as_str(&buf),
+            "IP 10.0.0.1 > 10.0.0.2: ICMP echo request, id 7, seq 1, length 12"
+        );
+
+        let mut packet = [&ip[..], b"gre!"].concat();
+        packet[3] = 24;
+        packet[9] = 47;
+        assert_eq!(
+            as_str(&summary(LinkType::Raw, &packet)),
+            "IP 10.0.0.1 > 10.0.0.2: GRE, length 4"
+        );
+    }
+}
@@ -0,0 +1,235 @@
+use crate::checksum;
+
+/// ICMP header \[[RFC792](https://datatracker.ietf.org/doc/html/rfc792)\], the type
+/// specific "rest of header" word is exposed both raw and as echo identifier
+/// and sequence number.
+pub struct Icmp<P = ()> {
+    slice: P,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[repr(u8)]
+pub enum IcmpType {
+    EchoReply = 0,
+    DestinationUnreachable = 3,
+    SourceQuench = 4,
+    Redirect = 5,
+    EchoRequest = 8,
+    RouterAdvertisement = 9,
+    RouterSolicitation = 10,
+    TimeExceeded = 11,
+    ParameterProblem = 12,
+    Timestamp = 13,
+    TimestampReply = 14,
+    Other(u8),
+}
+
+impl From<u8> for IcmpType {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => IcmpType::EchoReply,
+            3 => IcmpType::DestinationUnreachable,
+            4 => IcmpType::SourceQuench,
+            5 => IcmpType::Redirect,
+            8 => IcmpType::EchoRequest,
+            9 => IcmpType::RouterAdvertisement,
+            10 => IcmpType::RouterSolicitation,
+            11 => IcmpType::TimeExceeded,
+            12 => IcmpType::ParameterProblem,
+            13 => IcmpType::Timestamp,
+            14 => IcmpType::TimestampReply,
+            x => IcmpType::Other(x),
+        }
+    }
+}
+
+impl From<IcmpType> for u8 {
+    fn from(value: IcmpType) -> Self {
+        match value {
+            IcmpType::EchoReply => 0,
+            IcmpType::DestinationUnreachable => 3,
+            IcmpType::SourceQuench => 4,
+            IcmpType::Redirect => 5,
+            IcmpType::EchoRequest => 8,
+            IcmpType::RouterAdvertisement => 9,
+            IcmpType::RouterSolicitation => 10,
+            IcmpType::TimeExceeded => 11,
+            IcmpType::ParameterProblem => 12,
+            IcmpType::Timestamp => 13,
+            IcmpType::TimestampReply => 14,
+            IcmpType::Other(x) => x,
+        }
+    }
+}
+
+impl IcmpType {
+    /// Error messages carry the offending IP header plus at least 8 bytes of its payload.
+    pub fn is_error(&self) -> bool {
+        matches!(
+            self,
+            IcmpType::DestinationUnreachable
+                | IcmpType::SourceQuench
+                | IcmpType::Redirect
+                | IcmpType::TimeExceeded
+                | IcmpType::ParameterProblem
+        )
+    }
+
+    /// Query messages identified by the echo identifier field.
+    pub fn is_query(&self) -> bool {
+        matches!(
+            self,
+            IcmpType::EchoRequest
+                | IcmpType::EchoReply
+                | IcmpType::Timestamp
+                | IcmpType::TimestampReply
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidSize(usize),
+}
+
+impl Icmp<()> {
+    pub const SIZE: usize = 8;
+}
+
+impl<'pkt> Icmp<&'pkt [u8]> {
+    pub fn new(slice: &'pkt [u8]) -> Result<(Self, &'pkt [u8]), Error> {
+        if slice.len() < Icmp::SIZE {
+            return Err(Error::InvalidSize(slice.len()));
+        }
+
+        let (slice, rem) = slice.split_at(Icmp::SIZE);
+        Ok((Self { slice }, rem))
+    }
+}
+
+impl<'pkt> Icmp<&'pkt mut [u8]> {
+    pub fn new_mut(slice: &'pkt mut [u8]) -> Result<(Self, &'pkt mut [u8]), Error> {
+        if slice.len() < Icmp::SIZE {
+            return Err(Error::InvalidSize(slice.len()));
+        }
+
+        let (slice, rem) = slice.split_at_mut(Icmp::SIZE);
+        Ok((Self { slice }, rem))
+    }
+}
+
+impl<P: AsMut<[u8]> + AsRef<[u8]>> Icmp<P> {
+    pub fn set_icmp_type(&mut self, icmp_type: IcmpType) {
+        self.slice.as_mut()[0] = u8::from(icmp_type);
+    }
+
+    pub fn set_code(&mut self, code: u8) {
+        self.slice.as_mut()[1] = code;
+    }
+
+    pub fn set_csum(&mut self, csum: u16) {
+        self.slice.as_mut()[2..4].copy_from_slice(&csum.to_be_bytes())
+    }
+
+    /// Recomputes the checksum over the header and `payload`.
+    pub fn update_csum(&mut self, payload: &[u8]) {
+        self.set_csum(self.calc_csum(payload))
+    }
+
+    pub fn set_rest_of_header(&mut self, rest: &[u8; 4]) {
+        self.slice.as_mut()[4..8].copy_from_slice(rest)
+    }
+
+    pub fn set_identifier(&mut self, identifier: u16) {
+        self.slice.as_mut()[4..6].copy_from_slice(&identifier.to_be_bytes())
+    }
+
+    pub fn set_sequence_num(&mut self, sequence_num: u16) {
+        self.slice.as_mut()[6..8].copy_from_slice(&sequence_num.to_be_bytes())
+    }
+
+    pub fn slice_mut(&mut self) -> &mut [u8] {
+        self.slice.as_mut()
+    }
+}
+
+impl<P: AsRef<[u8]>> Icmp<P> {
+    pub fn icmp_type(&self) -> IcmpType {
+        IcmpType::from(self.slice.as_ref()[0])
+    }
+
+    pub fn icmp_type_u8(&self) -> u8 {
+        self.slice.as_ref()[0]
+    }
+
+    pub fn code(&self) -> u8 {
+        self.slice.as_ref()[1]
+    }
+
+    pub fn csum(&self) -> u16 {
+        u16::from_be_bytes(*self.slice.as_ref()[2..4].first_chunk::<2>().unwrap())
+    }
+
+    /// Checksum of the header and `payload`, ignoring the current checksum field.
+    pub fn calc_csum(&self, payload: &[u8]) -> u16 {
+        let slice = self.slice.as_ref();
+        let sum = checksum::sum(0, &slice[0..2]);
+        let sum = checksum::sum(sum, &slice[4..8]);
+        checksum::finish(checksum::sum(sum, payload))
+    }
+
+    pub fn rest_of_header(&self) -> &[u8; 4] {
+        self.slice.as_ref()[4..8].first_chunk::<4>().unwrap()
+    }
+
+    pub fn identifier(&self) -> u16 {
+        u16::from_be_bytes(*self.slice.as_ref()[4..6].first_chunk::<2>().unwrap())
+    }
+
+    pub fn sequence_num(&self) -> u16 {
+        u16::from_be_bytes(*self.slice.as_ref()[6..8].first_chunk::<2>().unwrap())
+    }
+
+    pub fn slice(&self) -> &[u8] {
+        self.slice.as_ref()
+    }
+}
+
+/// Owned ICMP header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct IcmpRepr {
+    pub icmp_type: IcmpType,
+    pub code: u8,
+    pub checksum: u16,
+    pub rest_of_header: [u8; 4],
+}
+
+impl<P: AsRef<[u8]>> From<&Icmp<P>> for IcmpRepr {
+    fn from(icmp: &Icmp<P>) -> Self {
+        Self {
+            icmp_type: icmp.icmp_type(),
+            code: icmp.code(),
+            checksum: icmp.csum(),
+            rest_of_header: *icmp.rest_of_header(),
+        }
+    }
+}
+
+impl IcmpRepr {
+    /// Writes the header to the start of `slice`.
+    pub fn emit<'pkt>(
+        &self,
+        slice: &'pkt mut [u8],
+    ) -> Result<(Icmp<&'pkt mut [u8]>, &'pkt mut [u8]), Error> {
+        let (mut icmp, rem) = Icmp::new_mut(slice)?;
+        icmp.set_icmp_type(self.icmp_type);
+        icmp.set_code(self.code);
+        icmp.set_csum(self.checksum);
+        icmp.set_rest_of_header(&self.rest_of_header);
+        Ok((icmp, rem))
+    }
+}
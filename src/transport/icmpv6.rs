@@ -0,0 +1,223 @@
+use crate::checksum;
+use crate::network::InetProtocol;
+
+/// ICMPv6 header \[[RFC4443](https://datatracker.ietf.org/doc/html/rfc4443)\].
+pub struct Icmpv6<P = ()> {
+    slice: P,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[repr(u8)]
+pub enum Icmpv6Type {
+    DestinationUnreachable = 1,
+    PacketTooBig = 2,
+    TimeExceeded = 3,
+    ParameterProblem = 4,
+    EchoRequest = 128,
+    EchoReply = 129,
+    RouterSolicitation = 133,
+    RouterAdvertisement = 134,
+    NeighborSolicitation = 135,
+    NeighborAdvertisement = 136,
+    Redirect = 137,
+    Other(u8),
+}
+
+impl From<u8> for Icmpv6Type {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => Icmpv6Type::DestinationUnreachable,
+            2 => Icmpv6Type::PacketTooBig,
+            3 => Icmpv6Type::TimeExceeded,
+            4 => Icmpv6Type::ParameterProblem,
+            128 => Icmpv6Type::EchoRequest,
+            129 => Icmpv6Type::EchoReply,
+            133 => Icmpv6Type::RouterSolicitation,
+            134 => Icmpv6Type::RouterAdvertisement,
+            135 => Icmpv6Type::NeighborSolicitation,
+            136 => Icmpv6Type::NeighborAdvertisement,
+            137 => Icmpv6Type::Redirect,
+            x => Icmpv6Type::Other(x),
+        }
+    }
+}
+
+impl From<Icmpv6Type> for u8 {
+    fn from(value: Icmpv6Type) -> Self {
+        match value {
+            Icmpv6Type::DestinationUnreachable => 1,
+            Icmpv6Type::PacketTooBig => 2,
+            Icmpv6Type::TimeExceeded => 3,
+            Icmpv6Type::ParameterProblem => 4,
+            Icmpv6Type::EchoRequest => 128,
+            Icmpv6Type::EchoReply => 129,
+            Icmpv6Type::RouterSolicitation => 133,
+            Icmpv6Type::RouterAdvertisement => 134,
+            Icmpv6Type::NeighborSolicitation => 135,
+            Icmpv6Type::NeighborAdvertisement => 136,
+            Icmpv6Type::Redirect => 137,
+            Icmpv6Type::Other(x) => x,
+        }
+    }
+}
+
+impl Icmpv6Type {
+    /// Error messages have the high bit of the type cleared.
+    pub fn is_error(&self) -> bool {
+        u8::from(*self) < 128
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidSize(usize),
+}
+
+impl Icmpv6<()> {
+    pub const SIZE: usize = 8;
+}
+
+impl<'pkt> Icmpv6<&'pkt [u8]> {
+    pub fn new(slice: &'pkt [u8]) -> Result<(Self, &'pkt [u8]), Error> {
+        if slice.len() < Icmpv6::SIZE {
+            return Err(Error::InvalidSize(slice.len()));
+        }
+
+        let (slice, rem) = slice.split_at(Icmpv6::SIZE);
+        Ok((Self { slice }, rem))
+    }
+}
+
+impl<'pkt> Icmpv6<&'pkt mut [u8]> {
+    pub fn new_mut(slice: &'pkt mut [u8]) -> Result<(Self, &'pkt mut [u8]), Error> {
+        if slice.len() < Icmpv6::SIZE {
+            return Err(Error::InvalidSize(slice.len()));
+        }
+
+        let (slice, rem) = slice.split_at_mut(Icmpv6::SIZE);
+        Ok((Self { slice }, rem))
+    }
+}
+
+impl<P: AsMut<[u8]> + AsRef<[u8]>> Icmpv6<P> {
+    pub fn set_icmp_type(&mut self, icmp_type: Icmpv6Type) {
+        self.slice.as_mut()[0] = u8::from(icmp_type);
+    }
+
+    pub fn set_code(&mut self, code: u8) {
+        self.slice.as_mut()[1] = code;
+    }
+
+    pub fn set_csum(&mut self, csum: u16) {
+        self.slice.as_mut()[2..4].copy_from_slice(&csum.to_be_bytes())
+    }
+
+    /// Recomputes the checksum over the IPv6 pseudo header, this header and `payload`.
+    pub fn update_csum(&mut self, source: &[u8; 16], destination: &[u8; 16], payload: &[u8]) {
+        self.set_csum(self.calc_csum(source, destination, payload))
+    }
+
+    pub fn set_rest_of_header(&mut self, rest: &[u8; 4]) {
+        self.slice.as_mut()[4..8].copy_from_slice(rest)
+    }
+
+    pub fn set_identifier(&mut self, identifier: u16) {
+        self.slice.as_mut()[4..6].copy_from_slice(&identifier.to_be_bytes())
+    }
+
+    pub fn set_sequence_num(&mut self, sequence_num: u16) {
+        self.slice.as_mut()[6..8].copy_from_slice(&sequence_num.to_be_bytes())
+    }
+
+    pub fn slice_mut(&mut self) -> &mut [u8] {
+        self.slice.as_mut()
+    }
+}
+
+impl<P: AsRef<[u8]>> Icmpv6<P> {
+    pub fn icmp_type(&self) -> Icmpv6Type {
+        Icmpv6Type::from(self.slice.as_ref()[0])
+    }
+
+    pub fn icmp_type_u8(&self) -> u8 {
+        self.slice.as_ref()[0]
+    }
+
+    pub fn code(&self) -> u8 {
+        self.slice.as_ref()[1]
+    }
+
+    pub fn csum(&self) -> u16 {
+        u16::from_be_bytes(*self.slice.as_ref()[2..4].first_chunk::<2>().unwrap())
+    }
+
+    /// Checksum over the IPv6 pseudo header, this header and `payload`,
+    /// ignoring the current checksum field.
+    pub fn calc_csum(&self, source: &[u8; 16], destination: &[u8; 16], payload: &[u8]) -> u16 {
+        let slice = self.slice.as_ref();
+        let sum = checksum::pseudo_ipv6(
+            source,
+            destination,
+            u8::from(InetProtocol::IPV6_ICMP),
+            (Icmpv6::SIZE + payload.len()) as u32,
+        );
+        let sum = checksum::sum(sum, &slice[0..2]);
+        let sum = checksum::sum(sum, &slice[4..8]);
+        checksum::finish(checksum::sum(sum, payload))
+    }
+
+    pub fn rest_of_header(&self) -> &[u8; 4] {
+        self.slice.as_ref()[4..8].first_chunk::<4>().unwrap()
+    }
+
+    pub fn identifier(&self) -> u16 {
+        u16::from_be_bytes(*self.slice.as_ref()[4..6].first_chunk::<2>().unwrap())
+    }
+
+    pub fn sequence_num(&self) -> u16 {
+        u16::from_be_bytes(*self.slice.as_ref()[6..8].first_chunk::<2>().unwrap())
+    }
+
+    pub fn slice(&self) -> &[u8] {
+        self.slice.as_ref()
+    }
+}
+
+/// Owned ICMPv6 header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Icmpv6Repr {
+    pub icmp_type: Icmpv6Type,
+    pub code: u8,
+    pub checksum: u16,
+    pub rest_of_header: [u8; 4],
+}
+
+impl<P: AsRef<[u8]>> From<&Icmpv6<P>> for Icmpv6Repr {
+    fn from(icmp: &Icmpv6<P>) -> Self {
+        Self {
+            icmp_type: icmp.icmp_type(),
+            code: icmp.code(),
+            checksum: icmp.csum(),
+            rest_of_header: *icmp.rest_of_header(),
+        }
+    }
+}
+
+impl Icmpv6Repr {
+    /// Writes the header to the start of `slice`.
+    pub fn emit<'pkt>(
+        &self,
+        slice: &'pkt mut [u8],
+    ) -> Result<(Icmpv6<&'pkt mut [u8]>, &'pkt mut [u8]), Error> {
+        let (mut icmp, rem) = Icmpv6::new_mut(slice)?;
+        icmp.set_icmp_type(self.icmp_type);
+        icmp.set_code(self.code);
+        icmp.set_csum(self.checksum);
+        icmp.set_rest_of_header(&self.rest_of_header);
+        Ok((icmp, rem))
+    }
+}
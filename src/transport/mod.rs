@@ -1,2 +1,4 @@
+pub mod icmp;
+pub mod icmpv6;
 pub mod tcp;
 pub mod udp;
\ No newline at end of file
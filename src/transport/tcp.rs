@@ -1,119 +1,183 @@
-pub struct Tcp<'pkt> {
-    slice: &'pkt [u8],
+use crate::field;
+
+pub struct Tcp<P = ()> {
+    slice: P,
     size: TcpSize,
 }
 
+#[derive(Debug)]
 pub enum Error {
     InvalidSize(usize),
     InvalidSizeForOffset(usize, TcpSize),
     InvalidDataOffset(DataOffsetError),
 }
 
-impl<'pkt> Tcp<'pkt> {
+impl Tcp<()> {
     pub const MIN_LEN: usize = 20;
     pub const MAX_LEN: usize = 60;
+}
 
+impl<'pkt> Tcp<&'pkt [u8]> {
     pub fn new(slice: &'pkt [u8]) -> Result<(Self, &'pkt [u8]), Error> {
-        if slice.len() < Self::MIN_LEN {
+        if slice.len() < Tcp::MIN_LEN {
             return Err(Error::InvalidSize(slice.len()));
         }
 
-        let size =
-            TcpSize::try_from_data_offset_u8(slice[12] >> 4).map_err(Error::InvalidDataOffset)?;
+        let size = TcpSize::try_from_data_offset_u8(field::read_u8(slice, 12) >> 4)
+            .map_err(Error::InvalidDataOffset)?;
+
+        let len = slice.len();
+        let (slice, rem) = slice
+            .split_at_checked(size as usize)
+            .ok_or(Error::InvalidSizeForOffset(len, size))?;
+
+        Ok((Self { slice, size }, rem))
+    }
+}
 
-        if slice.len() < size as usize {
-            return Err(Error::InvalidSizeForOffset(slice.len(), size));
+impl<'pkt> Tcp<&'pkt mut [u8]> {
+    pub fn new_mut(slice: &'pkt mut [u8]) -> Result<(Self, &'pkt mut [u8]), Error> {
+        if slice.len() < Tcp::MIN_LEN {
+            return Err(Error::InvalidSize(slice.len()));
         }
 
-        let (slice, rem) = slice.split_at(size as usize);
+        let size = TcpSize::try_from_data_offset_u8(field::read_u8(slice, 12) >> 4)
+            .map_err(Error::InvalidDataOffset)?;
+
+        let len = slice.len();
+        let (slice, rem) = slice
+            .split_at_mut_checked(size as usize)
+            .ok_or(Error::InvalidSizeForOffset(len, size))?;
 
         Ok((Self { slice, size }, rem))
     }
 }
 
-impl Tcp<'_> {
+impl<P: AsMut<[u8]> + AsRef<[u8]>> Tcp<P> {
+    pub fn set_source(&mut self, source: u16) {
+        field::write(self.slice.as_mut(), 0, &source.to_be_bytes())
+    }
+
+    pub fn set_destination(&mut self, destination: u16) {
+        field::write(self.slice.as_mut(), 2, &destination.to_be_bytes())
+    }
+
+    pub fn set_sequence_num(&mut self, sequence_num: u32) {
+        field::write(self.slice.as_mut(), 4, &sequence_num.to_be_bytes())
+    }
+
+    pub fn set_ack_num(&mut self, ack_num: u32) {
+        field::write(self.slice.as_mut(), 8, &ack_num.to_be_bytes())
+    }
+
+    pub fn set_flags(&mut self, flags: u8) {
+        field::write_u8(self.slice.as_mut(), 13, flags);
+    }
+
+    pub fn set_window_size(&mut self, window_size: u16) {
+        field::write(self.slice.as_mut(), 14, &window_size.to_be_bytes())
+    }
+
+    pub fn set_csum(&mut self, csum: u16) {
+        field::write(self.slice.as_mut(), 16, &csum.to_be_bytes())
+    }
+
+    /// Swaps source and destination ports. The checksum stays valid as long
+    /// as the addresses of the pseudo header are swapped as well.
+    pub fn reflect(&mut self) {
+        let source = self.source();
+        let destination = self.destination();
+        self.set_source(destination);
+        self.set_destination(source);
+    }
+
+    pub fn slice_mut(&mut self) -> &mut [u8] {
+        self.slice.as_mut()
+    }
+}
+
+impl<P: AsRef<[u8]>> Tcp<P> {
     pub fn size(&self) -> TcpSize {
         self.size
     }
-
     pub fn destination(&self) -> u16 {
-        u16::from_be_bytes(*self.slice[2..4].first_chunk::<2>().unwrap())
+        field::read_u16(self.slice.as_ref(), 2)
     }
 
     pub fn source(&self) -> u16 {
-        u16::from_be_bytes(*self.slice[0..2].first_chunk::<2>().unwrap())
+        field::read_u16(self.slice.as_ref(), 0)
     }
 
     pub fn window_size(&self) -> u16 {
-        u16::from_be_bytes(*self.slice[14..16].first_chunk::<2>().unwrap())
+        field::read_u16(self.slice.as_ref(), 14)
     }
 
     pub fn slice(&self) -> &[u8] {
-        self.slice
+        self.slice.as_ref()
     }
 
     pub fn csum(&self) -> u16 {
-        u16::from_be_bytes(*self.slice[16..18].first_chunk::<2>().unwrap())
+        field::read_u16(self.slice.as_ref(), 16)
     }
 
     pub fn urgent_pointer(&self) -> u16 {
-        u16::from_be_bytes(*self.slice[18..20].first_chunk::<2>().unwrap())
+        field::read_u16(self.slice.as_ref(), 18)
     }
 
     pub fn sequence_num(&self) -> u32 {
-        u32::from_be_bytes(*self.slice[4..8].first_chunk::<4>().unwrap())
+        field::read_u32(self.slice.as_ref(), 4)
     }
 
     pub fn ack_num(&self) -> u32 {
-        u32::from_be_bytes(*self.slice[8..12].first_chunk::<4>().unwrap())
+        field::read_u32(self.slice.as_ref(), 8)
     }
 
     pub fn data_offset(&self) -> u8 {
-        self.slice[12] >> 4
+        field::read_u8(self.slice.as_ref(), 12) >> 4
     }
 
     pub fn flags(&self) -> u8 {
-        self.slice[13]
+        field::read_u8(self.slice.as_ref(), 13)
     }
 
     pub fn options(&self) -> &[u8] {
-        &self.slice[Self::MIN_LEN..self.size as usize]
+        field::range(self.slice.as_ref(), Tcp::MIN_LEN, self.size as usize)
     }
 
     pub fn cwr(&self) -> bool {
-        self.slice[13] >> 7 == 1
+        field::read_u8(self.slice.as_ref(), 13) >> 7 == 1
     }
 
     pub fn ece(&self) -> bool {
-        (self.slice[13] >> 6) & 1 == 1
+        (field::read_u8(self.slice.as_ref(), 13) >> 6) & 1 == 1
     }
 
     pub fn urg(&self) -> bool {
-        (self.slice[13] >> 5) & 1 == 1
+        (field::read_u8(self.slice.as_ref(), 13) >> 5) & 1 == 1
     }
 
     pub fn ack(&self) -> bool {
-        (self.slice[13] >> 4) & 1 == 1
+        (field::read_u8(self.slice.as_ref(), 13) >> 4) & 1 == 1
     }
 
     pub fn psh(&self) -> bool {
-        (self.slice[13] >> 3) & 1 == 1
+        (field::read_u8(self.slice.as_ref(), 13) >> 3) & 1 == 1
     }
 
     pub fn rst(&self) -> bool {
-        (self.slice[13] >> 2) & 1 == 1
+        (field::read_u8(self.slice.as_ref(), 13) >> 2) & 1 == 1
     }
 
     pub fn syn(&self) -> bool {
-        (self.slice[13] >> 1) & 1 == 1
+        (field::read_u8(self.slice.as_ref(), 13) >> 1) & 1 == 1
     }
 
     pub fn fin(&self) -> bool {
-        self.slice[13] & 1 == 1
+        field::read_u8(self.slice.as_ref(), 13) & 1 == 1
     }
 
     pub fn ns(&self) -> bool {
-        self.slice[12] & 1 == 1
+        field::read_u8(self.slice.as_ref(), 12) & 1 == 1
     }
 }
 
@@ -158,3 +222,94 @@ impl TcpSize {
 pub enum DataOffsetError {
     InvalidOffset(u8),
 }
+
+/// Owned TCP header, options are not kept and `emit` writes a 20 byte
+/// header. The checksum is copied as is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct TcpRepr {
+    pub source: u16,
+    pub destination: u16,
+    pub sequence_num: u32,
+    pub ack_num: u32,
+    /// CWR down to FIN, as in byte 13 of the header.
+    pub flags: u8,
+    pub window_size: u16,
+    pub checksum: u16,
+    pub urgent_pointer: u16,
+}
+
+impl<P: AsRef<[u8]>> From<&Tcp<P>> for TcpRepr {
+    fn from(tcp: &Tcp<P>) -> Self {
+        Self {
+            source: tcp.source(),
+            destination: tcp.destination(),
+            sequence_num: tcp.sequence_num(),
+            ack_num: tcp.ack_num(),
+            flags: tcp.flags(),
+            window_size: tcp.window_size(),
+            checksum: tcp.csum(),
+            urgent_pointer: tcp.urgent_pointer(),
+        }
+    }
+}
+
+impl TcpRepr {
+    /// Writes the header to the start of `slice`.
+    pub fn emit<'pkt>(
+        &self,
+        slice: &'pkt mut [u8],
+    ) -> Result<(Tcp<&'pkt mut [u8]>, &'pkt mut [u8]), Error> {
+        if slice.len() < Tcp::MIN_LEN {
+            return Err(Error::InvalidSize(slice.len()));
+        }
+        field::write_u8(slice, 12, 5 << 4);
+        field::write(slice, 18, &self.urgent_pointer.to_be_bytes());
+        let (mut tcp, rem) = Tcp::new_mut(slice)?;
+        tcp.set_source(self.source);
+        tcp.set_destination(self.destination);
+        tcp.set_sequence_num(self.sequence_num);
+        tcp.set_ack_num(self.ack_num);
+        tcp.set_flags(self.flags);
+        tcp.set_window_size(self.window_size);
+        tcp.set_csum(self.checksum);
+        Ok((tcp, rem))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repr_round_trip() {
+        let packet = [
+            0xc9, 0x3a, 0x01, 0xbb, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x50, 0x02,
+            0xff, 0xff, 0x12, 0x34, 0x00, 0x00,
+        ];
+        let (tcp, _) = Tcp::new(&packet).unwrap();
+        let repr = TcpRepr::from(&tcp);
+        assert_eq!(repr.destination, 443);
+        assert_eq!(repr.flags, 0x02);
+        assert_eq!(repr.checksum, 0x1234);
+
+        let mut buf = [0xff; 24];
+        let (emitted, rem) = repr.emit(&mut buf).unwrap();
+        assert_eq!(emitted.slice(), packet);
+        assert_eq!(rem.len(), 4);
+        let mut short = [0xff; 19];
+        assert!(matches!(repr.emit(&mut short), Err(Error::InvalidSize(19))));
+        assert_eq!(short, [0xff; 19]);
+
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&repr).unwrap();
+            assert_eq!(
+                json,
+                r#"{"source":51514,"destination":443,"sequence_num":1,"ack_num":0,"flags":2,"window_size":65535,"checksum":4660,"urgent_pointer":0}"#
+            );
+            assert_eq!(serde_json::from_str::<TcpRepr>(&json).unwrap(), repr);
+        }
+    }
+}
@@ -1,58 +1,167 @@
-pub struct Udp<'pkt> {
-    slice: &'pkt mut [u8],
+use crate::field;
+
+pub struct Udp<P = ()> {
+    slice: P,
 }
 
+#[derive(Debug)]
 pub enum Error {
     InvalidLength(usize),
 }
 
-impl<'pkt> Udp<'pkt> {
+impl Udp<()> {
     pub const SIZE: usize = 8;
-    pub fn new(slice: &'pkt mut [u8]) -> Result<(Self, &'pkt mut [u8]), Error> {
-        if slice.len() < Self::SIZE {
-            return Err(Error::InvalidLength(slice.len()));
-        }
+}
 
-        let (slice, rem) = slice.split_at_mut(Self::SIZE);
+impl<'pkt> Udp<&'pkt [u8]> {
+    pub fn new(slice: &'pkt [u8]) -> Result<(Self, &'pkt [u8]), Error> {
+        let len = slice.len();
+        let (slice, rem) = slice
+            .split_at_checked(Udp::SIZE)
+            .ok_or(Error::InvalidLength(len))?;
 
         Ok((Self { slice }, rem))
     }
 }
 
-impl Udp<'_> {
+impl<'pkt> Udp<&'pkt mut [u8]> {
+    pub fn new_mut(slice: &'pkt mut [u8]) -> Result<(Self, &'pkt mut [u8]), Error> {
+        let len = slice.len();
+        let (slice, rem) = slice
+            .split_at_mut_checked(Udp::SIZE)
+            .ok_or(Error::InvalidLength(len))?;
+
+        Ok((Self { slice }, rem))
+    }
+}
+
+impl<P: AsRef<[u8]>> Udp<P> {
     pub fn source(&self) -> u16 {
-        u16::from_be_bytes(*self.slice[0..2].first_chunk::<2>().unwrap())
+        field::read_u16(self.slice.as_ref(), 0)
     }
 
     pub fn destination(&self) -> u16 {
-        u16::from_be_bytes(*self.slice[2..4].first_chunk::<2>().unwrap())
+        field::read_u16(self.slice.as_ref(), 2)
     }
 
     pub fn length(&self) -> u16 {
-        u16::from_be_bytes(*self.slice[4..6].first_chunk::<2>().unwrap())
+        field::read_u16(self.slice.as_ref(), 4)
     }
 
     pub fn checksum(&self) -> &[u8; 2] {
-        self.slice[6..8].first_chunk::<2>().unwrap()
+        field::array(self.slice.as_ref(), 6).unwrap_or(&[0; 2])
     }
 
+    pub fn checksum_u16(&self) -> u16 {
+        u16::from_be_bytes(*self.checksum())
+    }
+
+    pub fn slice(&self) -> &[u8] {
+        self.slice.as_ref()
+    }
+}
+
+impl<P: AsMut<[u8]> + AsRef<[u8]>> Udp<P> {
     pub fn set_source(&mut self, source: u16) {
-        self.slice[0..2].copy_from_slice(&source.to_be_bytes())
+        field::write(self.slice.as_mut(), 0, &source.to_be_bytes())
     }
 
     pub fn set_destination(&mut self, destination: u16) {
-        self.slice[2..4].copy_from_slice(&destination.to_be_bytes())
+        field::write(self.slice.as_mut(), 2, &destination.to_be_bytes())
     }
 
     pub fn set_length(&mut self, length: u16) {
-        self.slice[4..6].copy_from_slice(&length.to_be_bytes())
+        field::write(self.slice.as_mut(), 4, &length.to_be_bytes())
     }
 
     pub fn set_checksum(&mut self, checksum: u16) {
-        self.slice[6..8].copy_from_slice(&checksum.to_be_bytes())
+        field::write(self.slice.as_mut(), 6, &checksum.to_be_bytes())
     }
 
     pub fn set_checksum_zero(&mut self) {
         self.set_checksum(0);
     }
+
+    /// Swaps source and destination ports. The checksum stays valid as long
+    /// as the addresses of the pseudo header are swapped as well.
+    pub fn reflect(&mut self) {
+        let source = self.source();
+        let destination = self.destination();
+        self.set_source(destination);
+        self.set_destination(source);
+    }
+
+    pub fn slice_mut(&mut self) -> &mut [u8] {
+        self.slice.as_mut()
+    }
+}
+
+/// Owned UDP header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct UdpRepr {
+    pub source: u16,
+    pub destination: u16,
+    pub length: u16,
+    pub checksum: u16,
+}
+
+impl<P: AsRef<[u8]>> From<&Udp<P>> for UdpRepr {
+    fn from(udp: &Udp<P>) -> Self {
+        Self {
+            source: udp.source(),
+            destination: udp.destination(),
+            length: udp.length(),
+            checksum: udp.checksum_u16(),
+        }
+    }
+}
+
+impl UdpRepr {
+    /// Writes the header to the start of `slice`.
+    pub fn emit<'pkt>(
+        &self,
+        slice: &'pkt mut [u8],
+    ) -> Result<(Udp<&'pkt mut [u8]>, &'pkt mut [u8]), Error> {
+        let (mut udp, rem) = Udp::new_mut(slice)?;
+        udp.set_source(self.source);
+        udp.set_destination(self.destination);
+        udp.set_length(self.length);
+        udp.set_checksum(self.checksum);
+        Ok((udp, rem))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repr_round_trip() {
+        let packet = [0x13, 0x88, 0x00, 0x35, 0x00, 0x0c, 0xab, 0xcd];
+        let (udp, _) = Udp::new(&packet).unwrap();
+        let repr = UdpRepr::from(&udp);
+        assert_eq!(repr.source, 5000);
+        assert_eq!(repr.length, 12);
+
+        let mut buf = [0xff; 12];
+        let (emitted, rem) = repr.emit(&mut buf).unwrap();
+        assert_eq!(emitted.slice(), packet);
+        assert_eq!(rem.len(), 4);
+        assert!(matches!(
+            repr.emit(&mut [0; 7]),
+            Err(Error::InvalidLength(7))
+        ));
+
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&repr).unwrap();
+            assert_eq!(
+                json,
+                r#"{"source":5000,"destination":53,"length":12,"checksum":43981}"#
+            );
+            assert_eq!(serde_json::from_str::<UdpRepr>(&json).unwrap(), repr);
+        }
+    }
 }
@@ -0,0 +1,101 @@
+//! Instantiates the header accessors for `&[u8]` and `&mut [u8]` so the
+//! generated code can be inspected for panic paths, see `tests/no_panic.rs`.
+#![no_std]
+
+use core::panic::PanicInfo;
+
+use netp::link::Ethernet;
+use netp::network::{IPv4, InetProtocol};
+use netp::transport::tcp::Tcp;
+use netp::transport::udp::Udp;
+
+#[no_mangle]
+pub fn read(packet: &[u8]) -> u64 {
+    let Ok((eth, rem)) = Ethernet::new(packet) else {
+        return 0;
+    };
+    let Ok((ip, rem)) = IPv4::new(rem) else {
+        return 0;
+    };
+    let mut acc = u16::from(eth.ethertype()) as u64
+        + eth.source()[0] as u64
+        + eth.destination()[5] as u64
+        + ip.source_u32() as u64
+        + ip.destination()[3] as u64
+        + ip.ttl() as u64
+        + ip.dscp() as u64
+        + ip.ecn() as u64
+        + ip.total_length() as u64
+        + ip.identification() as u64
+        + ip.fragment_offset()[1] as u64
+        + ip.dont_fragment() as u64
+        + ip.more_fragments() as u64
+        + ip.options().len() as u64
+        + ip.calc_csum() as u64
+        + ip.csum() as u64;
+    match ip.protocol() {
+        InetProtocol::TCP => {
+            if let Ok((tcp, _)) = Tcp::new(rem) {
+                acc += tcp.source() as u64
+                    + tcp.destination() as u64
+                    + tcp.sequence_num() as u64
+                    + tcp.ack_num() as u64
+                    + tcp.window_size() as u64
+                    + tcp.csum() as u64
+                    + tcp.urgent_pointer() as u64
+                    + tcp.flags() as u64
+                    + tcp.syn() as u64
+                    + tcp.options().len() as u64;
+            }
+        }
+        InetProtocol::UDP => {
+            if let Ok((udp, _)) = Udp::new(rem) {
+                acc += udp.source() as u64
+                    + udp.destination() as u64
+                    + udp.length() as u64
+                    + udp.checksum_u16() as u64;
+            }
+        }
+        _ => {}
+    }
+    acc
+}
+
+#[no_mangle]
+pub fn write(packet: &mut [u8]) {
+    let Ok((mut eth, rem)) = Ethernet::new_mut(packet) else {
+        return;
+    };
+    eth.set_source(&[0; 6]);
+    eth.set_destination(&[0xff; 6]);
+    let Ok((mut ip, rem)) = IPv4::new_mut(rem) else {
+        return;
+    };
+    ip.set_source(&[10, 0, 0, 1]);
+    ip.set_destination_u32(0x0a000002);
+    ip.set_ttl(64);
+    ip.set_dscp(46);
+    ip.set_ecn(1);
+    ip.set_dont_fragment(true);
+    ip.set_identification(1);
+    ip.update_csum();
+    if let Ok((mut tcp, _)) = Tcp::new_mut(rem) {
+        tcp.set_source(1);
+        tcp.set_destination(2);
+        tcp.set_sequence_num(3);
+        tcp.set_ack_num(4);
+        tcp.set_flags(0x12);
+        tcp.set_window_size(5);
+        tcp.set_csum(0);
+    } else if let Ok((mut udp, _)) = Udp::new_mut(rem) {
+        udp.set_source(1);
+        udp.set_destination(2);
+        udp.set_length(8);
+        udp.set_checksum(0);
+    }
+}
+
+#[panic_handler]
+fn panic(_: &PanicInfo) -> ! {
+    loop {}
+}
@@ -0,0 +1,62 @@
+//! Builds `tests/no-panic`, which calls every accessor of the `Ethernet`,
+//! `IPv4`, `Tcp` and `Udp` views, for `bpfel-unknown-none` with LTO and
+//! checks that no `core::panicking` symbol survives in the result.
+//!
+//! Needs a nightly toolchain with the `rust-src` and `llvm-tools` components:
+//!
+//! ```text
+//! cargo test --test no_panic -- --ignored
+//! ```
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn nightly_llvm_nm() -> PathBuf {
+    let output = |args: &[&str]| {
+        let out = Command::new("rustc").args(args).output().unwrap();
+        assert!(out.status.success(), "rustc {args:?} failed");
+        String::from_utf8(out.stdout).unwrap()
+    };
+    let sysroot = output(&["+nightly", "--print", "sysroot"]);
+    let version = output(&["+nightly", "-vV"]);
+    let host = version
+        .lines()
+        .find_map(|line| line.strip_prefix("host: "))
+        .unwrap();
+    Path::new(sysroot.trim())
+        .join("lib/rustlib")
+        .join(host)
+        .join("bin/llvm-nm")
+}
+
+#[test]
+#[ignore = "needs a nightly toolchain with rust-src and llvm-tools"]
+fn no_panic_symbols_in_bpfel_build() {
+    let manifest = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/no-panic/Cargo.toml");
+    let target_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("no-panic");
+
+    let status = Command::new("cargo")
+        .env_remove("RUSTFLAGS")
+        .args(["+nightly", "build", "--release", "-Z", "build-std=core"])
+        .args(["--target", "bpfel-unknown-none"])
+        .arg("--manifest-path")
+        .arg(&manifest)
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let lib = target_dir.join("bpfel-unknown-none/release/libnetp_no_panic.a");
+    let out = Command::new(nightly_llvm_nm()).arg(&lib).output().unwrap();
+    assert!(out.status.success(), "llvm-nm failed on {}", lib.display());
+
+    let symbols = String::from_utf8(out.stdout).unwrap();
+    let names: Vec<_> = symbols
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(2))
+        .collect();
+    let panics: Vec<_> = names.iter().filter(|s| s.contains("panic")).collect();
+    assert!(panics.is_empty(), "panic paths survived: {panics:#?}");
+    assert!(names.contains(&"read") && names.contains(&"write"));
+}